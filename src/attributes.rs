@@ -0,0 +1,86 @@
+//! A map written as the attributes of an element.
+
+use serde::de::{Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A set of attributes known only at runtime, written as the attributes of
+/// the element that holds the map.
+///
+/// Entries are written in key order. On deserialization the element's
+/// attributes are collected and its content is skipped.
+///
+/// # Example
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_xml::{from_str, to_string, Attributes};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// struct Widget {
+///     elem: Attributes,
+/// }
+///
+/// let mut widget = Widget { elem: Attributes::default() };
+/// widget.elem.0.insert("b".to_string(), "2".to_string());
+/// widget.elem.0.insert("a".to_string(), "1".to_string());
+///
+/// let xml = to_string(&widget).unwrap();
+/// assert_eq!(xml, r#"<Widget><elem a="1" b="2"/></Widget>"#);
+/// assert_eq!(from_str::<Widget>(&xml).unwrap(), widget);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attributes(pub HashMap<String, String>);
+
+impl Serialize for Attributes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut entries: Vec<_> = self.0.iter().collect();
+        entries.sort();
+        let mut map = serializer.serialize_map(Some(entries.len()))?;
+        for (name, value) in entries {
+            map.serialize_entry(&format!("@{}", name), value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Attributes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct AttributesVisitor;
+
+        impl<'de> Visitor<'de> for AttributesVisitor {
+            type Value = Attributes;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("an element with attributes")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Attributes, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut attributes = HashMap::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.strip_prefix('@') {
+                        Some(name) => {
+                            attributes.insert(name.to_string(), map.next_value()?);
+                        }
+                        None => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(Attributes(attributes))
+            }
+        }
+
+        deserializer.deserialize_map(AttributesVisitor)
+    }
+}
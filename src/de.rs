@@ -2,10 +2,79 @@
 //!
 //! This module provides a full-featured Serde deserializer that converts
 //! XML documents into Rust data structures.
-
-use crate::error::{Error, Result};
-use crate::reader::{XmlEvent, XmlReader};
+//!
+//! Internally tagged enums (`#[serde(tag = "@type")]`) read their tag from an
+//! attribute, or from a child element when the tag has no `@` prefix. Serde
+//! buffers their content as text, so payload fields should be strings or use
+//! `deserialize_with` to parse numbers.
+//!
+//! For this, self-describing targets read an element without attributes that
+//! holds only text, such as `<name>Ann</name>`, as a plain string. This is a
+//! breaking change: earlier versions gave a map with the text under `$value`,
+//! so untagged enums and `serde_json::Value` targets that matched
+//! `{"$value": "Ann"}` now see `"Ann"`.
+//!
+//! Flattened structs (`#[serde(flatten)]`) take their fields from the
+//! element's attributes and children, so a group of attributes such as
+//! `x="0" y="0"` can fill a `Point` of `@x` and `@y`. Serde buffers these as
+//! text too, so numeric and boolean fields of a flattened struct are not
+//! supported directly: make them strings or parse them with
+//! `deserialize_with`. [`Deserializer::infer_scalars`] is no substitute, as
+//! it would also turn text such as `007` meant for a string field into a
+//! number.
+//!
+//! A map target receives an element's attributes first, as `@`-prefixed keys
+//! in source order, followed by its children in source order. Maps that keep
+//! insertion order, such as `IndexMap`, therefore match the document.
+//!
+//! Adjacently tagged enums (`#[serde(tag = "t", content = "c")]`) read their
+//! tag and content from child elements, with the tag first, as the serializer
+//! writes them. Elements are not reordered, so content found before its tag
+//! is left to serde, which buffers it as text and can then only read string
+//! or unit content.
+//!
+//! A `$text` field receives all the text directly inside the element, joined
+//! across any child elements in between, while the children go to their own
+//! fields. The text keeps its inner whitespace, so
+//! `<note>Call <b>Bob</b> today</note>` gives `"Call  today"`; with trimming
+//! on, only the ends of the joined text are trimmed. An element without text
+//! gives an empty `$text`.
+//!
+//! A `$value` field next to other fields likewise receives the text of a
+//! mixed element, joined around its child elements. With
+//! [`Deserializer::leading_text_as_value`] it takes only the text before the
+//! first child, such as `Intro` in `<section>Intro<item>a</item>more</section>`.
+//!
+//! ## Transcoding
+//!
+//! The deserializer is self-describing, so a document can be streamed into
+//! another format with `serde_transcode`. Each element becomes a map of its
+//! `@`-prefixed attributes, its children and, for mixed content, its text under
+//! `$value`; an element holding only text becomes a string. Enable
+//! [`Deserializer::group_repeated_elements`] and [`Deserializer::infer_scalars`]
+//! for natural JSON:
+//!
+//! ```rust
+//! use serde_xml::Deserializer;
+//!
+//! let xml = r#"<shelf id="3"><book>A</book><book>B</book></shelf>"#;
+//! let mut de = Deserializer::from_str(xml)
+//!     .group_repeated_elements(true)
+//!     .infer_scalars(true);
+//! let mut json = Vec::new();
+//! serde_transcode::transcode(&mut de, &mut serde_json::Serializer::new(&mut json)).unwrap();
+//! assert_eq!(String::from_utf8(json).unwrap(), r#"{"@id":3,"book":["A","B"]}"#);
+//! ```
+
+use crate::error::{Error, Position, Result, Span};
+use crate::outer_xml::OUTER_XML_NAME;
+use crate::present::PRESENT_NAME;
+use crate::value::VALUE_NAME;
+use crate::reader::{Attribute, XmlEvent, XmlReader, XmlReaderBuilder};
+use crate::ser::{is_namespace_decl, NoneMode};
 use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 
 /// Deserializes a value from an XML string.
 ///
@@ -34,86 +103,770 @@ where
     T::deserialize(&mut de)
 }
 
+/// Deserializes a value from an XML string with the given options.
+///
+/// See [`DeserializeOptions`].
+pub fn from_str_with_options<'de, T>(s: &'de str, options: &DeserializeOptions) -> Result<T>
+where
+    T: de::Deserialize<'de>,
+{
+    let mut de = Deserializer::from_str_with_options(s, options);
+    T::deserialize(&mut de)
+}
+
+/// Deserializes each top-level element of an XML fragment, such as
+/// `<li>a</li><li>b</li>`, which need not have a single root.
+///
+/// Unlike deserializing a `Vec` with [`from_str`], the elements need not share
+/// a name, so a fragment can hold several variants of an enum.
+///
+/// # Example
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_xml::from_str_seq;
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// enum Shape {
+///     Circle { radius: f64 },
+///     Square { side: f64 },
+/// }
+///
+/// let xml = "<Circle><radius>1</radius></Circle><Square><side>2</side></Square>";
+/// let shapes: Vec<Shape> = from_str_seq(xml).unwrap();
+/// assert_eq!(shapes, [Shape::Circle { radius: 1.0 }, Shape::Square { side: 2.0 }]);
+/// ```
+pub fn from_str_seq<'de, T>(s: &'de str) -> Result<Vec<T>>
+where
+    T: de::Deserialize<'de>,
+{
+    let mut de = Deserializer::from_str(s);
+    let mut values = Vec::new();
+    loop {
+        match de.peek_event()? {
+            XmlEvent::StartElement { .. } | XmlEvent::EmptyElement { .. } => {
+                values.push(T::deserialize(&mut de)?);
+            }
+            XmlEvent::Eof => return Ok(values),
+            XmlEvent::Text(_) | XmlEvent::CData(_) => {
+                return Err(Error::syntax("text outside of an element")
+                    .with_position(de.reader.position()));
+            }
+            _ => {
+                de.next_event()?;
+            }
+        }
+    }
+}
+
 /// Deserializes a value from XML bytes.
+///
+/// The input is expected to be UTF-8 unless it starts with a UTF-16 byte order
+/// mark or its XML declaration names another encoding. UTF-16 and
+/// `ISO-8859-1` documents are always transcoded; others such as
+/// `Windows-1252` are transcoded when the `encoding` feature is enabled and
+/// rejected otherwise.
+///
+/// UTF-8 input is read in place, so borrowed fields such as `&str` work as
+/// with [`from_str`]. Transcoded text is dropped before this returns, so
+/// fields read from it must own their data.
 pub fn from_bytes<'de, T>(bytes: &'de [u8]) -> Result<T>
 where
     T: de::Deserialize<'de>,
 {
-    let s = std::str::from_utf8(bytes)
-        .map_err(|_| Error::new(crate::error::ErrorKind::InvalidUtf8))?;
-    from_str(s)
+    match crate::encoding::decode(bytes)? {
+        Cow::Borrowed(s) => from_str(s),
+        Cow::Owned(s) => {
+            let mut de = Deserializer::from_str(&s);
+            T::deserialize(crate::owned::Owned::new(&mut de))
+        }
+    }
+}
+
+/// Which text forms `bool` values are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoolMode {
+    /// Only `true` and `false`, in lowercase.
+    Strict,
+    /// `true`, `1`, `yes` and `on`, or `false`, `0`, `no` and `off`, in any
+    /// case.
+    #[default]
+    Lenient,
+}
+
+impl BoolMode {
+    /// Parses `text` as a boolean, if this mode accepts it.
+    fn parse(self, text: &str) -> Option<bool> {
+        match self {
+            BoolMode::Strict => match text {
+                "true" => Some(true),
+                "false" => Some(false),
+                _ => None,
+            },
+            BoolMode::Lenient => {
+                const TRUE: [&str; 4] = ["true", "1", "yes", "on"];
+                const FALSE: [&str; 4] = ["false", "0", "no", "off"];
+                if TRUE.iter().any(|t| t.eq_ignore_ascii_case(text)) {
+                    Some(true)
+                } else if FALSE.iter().any(|f| f.eq_ignore_ascii_case(text)) {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Settings for [`Deserializer`], gathered in one value.
+///
+/// Each field matches a [`Deserializer`] or [`XmlReader`] option; the
+/// defaults parse the same way as [`from_str`].
+///
+/// # Example
+///
+/// ```
+/// use serde::Deserialize;
+/// use serde_xml::{from_str_with_options, DeserializeOptions};
+///
+/// #[derive(Deserialize)]
+/// struct Div {
+///     class: String,
+/// }
+///
+/// let options = DeserializeOptions {
+///     case_insensitive: true,
+///     max_depth: Some(8),
+///     ..Default::default()
+/// };
+/// let div: Div = from_str_with_options("<DIV><CLASS>box</CLASS></DIV>", &options).unwrap();
+/// assert_eq!(div.class, "box");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeserializeOptions {
+    /// Whether names are matched to fields ignoring ASCII case. See
+    /// [`Deserializer::case_insensitive`].
+    pub case_insensitive: bool,
+    /// Whether element names are matched to fields without their prefix. See
+    /// [`Deserializer::ignore_namespace_prefixes`].
+    pub ignore_namespace_prefixes: bool,
+    /// Whether structs see every namespace declaration as an attribute. See
+    /// [`Deserializer::keep_namespace_declarations`].
+    pub keep_namespace_declarations: bool,
+    /// Whether whitespace around text is kept instead of trimmed. See
+    /// [`Deserializer::trim_text`].
+    pub preserve_whitespace: bool,
+    /// Which text forms are read as booleans.
+    pub bool_mode: BoolMode,
+    /// Which element forms are read back as `None`.
+    pub none_mode: NoneMode,
+    /// Whether `deserialize_any` reads numbers and booleans from text. See
+    /// [`Deserializer::infer_scalars`].
+    pub infer_scalars: bool,
+    /// Whether `deserialize_any` groups repeated sibling elements. See
+    /// [`Deserializer::group_repeated_elements`].
+    pub group_repeated_elements: bool,
+    /// Whether a sequence field ends at the first element with another name
+    /// instead of taking same-named elements from among others. See
+    /// [`Deserializer::interleaved_sequences`].
+    pub contiguous_sequences: bool,
+    /// Whether a `$value` field takes only the text before the first child
+    /// element. See [`Deserializer::leading_text_as_value`].
+    pub leading_text_as_value: bool,
+    /// Limit on element nesting depth. See [`XmlReader::max_depth`].
+    pub max_depth: Option<usize>,
+    /// Limit on the number of elements. See [`XmlReader::max_elements`].
+    pub max_elements: Option<usize>,
+    /// Limit on the length of unescaped content. See
+    /// [`XmlReader::max_expanded_len`].
+    pub max_expanded_len: Option<usize>,
+    /// General entities available for reference expansion. See
+    /// [`XmlReader::with_entities`].
+    pub entities: HashMap<String, String>,
+    /// Whether `<!ENTITY>` declarations in the DOCTYPE are expanded. See
+    /// [`XmlReader::parse_internal_entities`].
+    pub parse_internal_entities: bool,
+    /// Whether HTML named entities such as `&nbsp;` are recognized. See
+    /// [`XmlReader::html_entities`].
+    #[cfg(feature = "html-entities")]
+    pub html_entities: bool,
 }
 
 /// The XML deserializer.
 pub struct Deserializer<'de> {
     reader: XmlReader<'de>,
-    /// Peeked event for look-ahead.
-    peeked: Option<XmlEvent<'de>>,
+    /// Peeked event for look-ahead, with the input it was read from.
+    peeked: Option<(XmlEvent<'de>, Span)>,
+    /// Events set aside during look-ahead, to be read again before the reader.
+    replay: VecDeque<(XmlEvent<'de>, Span)>,
     /// Pending value to deserialize (for text content or attribute values).
-    pending_value: Option<String>,
+    pending_value: Option<Cow<'de, str>>,
     /// Whether we already consumed the start element for the current struct.
     start_consumed: bool,
     /// Whether the current element is empty (<tag/>).
     is_empty_element: bool,
+    /// Which element forms are read back as `None`.
+    none_mode: NoneMode,
+    /// Which text forms are read as booleans.
+    bool_mode: BoolMode,
+    /// Set while reading a `$value`: the fields of the enclosing struct, whose
+    /// elements are not part of the mixed content.
+    mixed_content: Option<&'static [&'static str]>,
+    /// Whether element and attribute names are matched to fields in lowercase.
+    case_insensitive: bool,
+    /// Whether element names are matched to fields without their prefix.
+    ignore_namespace_prefixes: bool,
+    /// Whether structs see namespace declarations they have no field for.
+    keep_namespace_declarations: bool,
+    /// Whether `deserialize_any` reads numbers and booleans from text.
+    infer_scalars: bool,
+    /// Whether `deserialize_any` groups repeated sibling elements of a map
+    /// into one sequence.
+    group_repeated: bool,
+    /// Set while reading a map value whose element has same-named siblings.
+    repeated_value: bool,
+    /// Set by [`Present`](crate::Present) to the path depth of its field, whose
+    /// empty element then reads as `None`.
+    present: Option<usize>,
+    /// Set by a `$value` sequence to the path depth of its items, so that a
+    /// [`Value`](crate::Value) item keeps its element's name.
+    value_item: Option<usize>,
+    /// Whether sequences look past differently named siblings for more items.
+    interleaved_sequences: bool,
+    /// Whether a scalar `$value` takes only the text before any child element.
+    leading_text_as_value: bool,
+    /// Names of the elements and attributes leading to the value being read.
+    path: Vec<PathSegment>,
+    /// Name and raw text of each leaf element read, if recording is enabled.
+    leaf_texts: Option<Vec<(String, String)>>,
+}
+
+/// Returns the field name an element or attribute name is matched against.
+fn field_name(name: &str, case_insensitive: bool, strip_prefix: bool) -> String {
+    let name = match name.split_once(':') {
+        Some((_, local)) if strip_prefix => local,
+        _ => name,
+    };
+    if case_insensitive {
+        name.to_ascii_lowercase()
+    } else {
+        name.to_string()
+    }
+}
+
+/// One step of the element path reported in errors.
+struct PathSegment {
+    name: String,
+    /// 1-based position among repeated siblings.
+    index: Option<usize>,
 }
 
 impl<'de> Deserializer<'de> {
     /// Creates a new deserializer from a string.
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &'de str) -> Self {
+        Self::from_reader(XmlReader::from_str(s))
+    }
+
+    /// Creates a new deserializer reading from a configured reader.
+    ///
+    /// Use this with [`XmlReaderBuilder`] to combine several reader options.
+    pub fn from_reader(reader: XmlReader<'de>) -> Self {
         Self {
-            reader: XmlReader::from_str(s),
+            reader,
             peeked: None,
+            replay: VecDeque::new(),
             pending_value: None,
             start_consumed: false,
             is_empty_element: false,
+            none_mode: NoneMode::Omit,
+            bool_mode: BoolMode::Lenient,
+            mixed_content: None,
+            case_insensitive: false,
+            ignore_namespace_prefixes: false,
+            keep_namespace_declarations: false,
+            infer_scalars: false,
+            group_repeated: false,
+            repeated_value: false,
+            present: None,
+            value_item: None,
+            interleaved_sequences: true,
+            leading_text_as_value: false,
+            path: Vec::new(),
+            leaf_texts: None,
+        }
+    }
+
+    /// Creates a new deserializer from a string with the given options.
+    pub fn from_str_with_options(s: &'de str, options: &DeserializeOptions) -> Self {
+        let mut builder = XmlReaderBuilder::new()
+            .trim_text(!options.preserve_whitespace)
+            .with_entities(options.entities.clone())
+            .parse_internal_entities(options.parse_internal_entities);
+        if let Some(limit) = options.max_depth {
+            builder = builder.max_depth(limit);
+        }
+        if let Some(limit) = options.max_elements {
+            builder = builder.max_elements(limit);
+        }
+        if let Some(limit) = options.max_expanded_len {
+            builder = builder.max_expanded_len(limit);
+        }
+        #[cfg(feature = "html-entities")]
+        {
+            builder = builder.html_entities(options.html_entities);
+        }
+        Self::from_reader(builder.from_str(s))
+            .case_insensitive(options.case_insensitive)
+            .ignore_namespace_prefixes(options.ignore_namespace_prefixes)
+            .keep_namespace_declarations(options.keep_namespace_declarations)
+            .with_bool_mode(options.bool_mode)
+            .with_none_as(options.none_mode)
+            .infer_scalars(options.infer_scalars)
+            .group_repeated_elements(options.group_repeated_elements)
+            .interleaved_sequences(!options.contiguous_sequences)
+            .leading_text_as_value(options.leading_text_as_value)
+    }
+
+    /// Limits the cumulative length of unescaped content read from the input.
+    ///
+    /// See [`XmlReader::max_expanded_len`].
+    pub fn max_expanded_len(mut self, limit: usize) -> Self {
+        self.reader = self.reader.max_expanded_len(limit);
+        self
+    }
+
+    /// Limits the total number of elements read from the input.
+    ///
+    /// See [`XmlReader::max_elements`].
+    pub fn max_elements(mut self, limit: usize) -> Self {
+        self.reader = self.reader.max_elements(limit);
+        self
+    }
+
+    /// Limits how deeply elements may nest.
+    ///
+    /// See [`XmlReader::max_depth`].
+    pub fn max_depth(mut self, limit: usize) -> Self {
+        self.reader = self.reader.max_depth(limit);
+        self
+    }
+
+    /// Enables building an index of `xml:id` values while deserializing.
+    ///
+    /// See [`XmlReader::track_ids`].
+    pub fn track_ids(mut self, enabled: bool) -> Self {
+        self.reader = self.reader.track_ids(enabled);
+        self
+    }
+
+    /// Enables reading HTML void elements such as `<br>` without `/>`.
+    ///
+    /// See [`XmlReader::html_void_elements`].
+    pub fn html_void_elements(mut self, enabled: bool) -> Self {
+        self.reader = self.reader.html_void_elements(enabled);
+        self
+    }
+
+    /// Sets whether whitespace around text is removed, which it is by default.
+    ///
+    /// When off, strings keep their content exactly, while numbers and
+    /// booleans still parse with whitespace around them. See
+    /// [`XmlReader::trim_text`].
+    pub fn trim_text(mut self, enabled: bool) -> Self {
+        self.reader = self.reader.trim_text(enabled);
+        self
+    }
+
+    /// Enables closing elements left open, as HTML parsers do, so a struct
+    /// whose end tag is missing binds the fields seen so far.
+    ///
+    /// See [`XmlReader::auto_close`].
+    pub fn auto_close(mut self, enabled: bool) -> Self {
+        self.reader = self.reader.auto_close(enabled);
+        self
+    }
+
+    /// Returns the positions of elements carrying `xml:id` read so far.
+    pub fn ids(&self) -> &HashMap<String, Position> {
+        self.reader.ids()
+    }
+
+    /// Enables recognizing HTML named entities such as `&nbsp;` while
+    /// deserializing.
+    ///
+    /// See [`XmlReader::html_entities`].
+    #[cfg(feature = "html-entities")]
+    pub fn html_entities(mut self, enabled: bool) -> Self {
+        self.reader = self.reader.html_entities(enabled);
+        self
+    }
+
+    /// Sets which element forms are read back as `Option::None`.
+    ///
+    /// With [`NoneMode::EmptyElement`], an empty element without attributes
+    /// (`<value/>`) is `None`. Elements carrying `xsi:nil="true"` are `None`
+    /// in every mode. This mirrors [`Serializer::with_none_as`].
+    ///
+    /// [`Serializer::with_none_as`]: crate::Serializer::with_none_as
+    pub fn with_none_as(mut self, mode: NoneMode) -> Self {
+        self.none_mode = mode;
+        self
+    }
+
+    /// Sets which text forms `bool` fields are read from.
+    ///
+    /// The default, [`BoolMode::Lenient`], accepts `true`, `1`, `yes` and
+    /// `on`, or `false`, `0`, `no` and `off`, ignoring ASCII case.
+    /// [`BoolMode::Strict`] accepts only `true` and `false`, as written.
+    pub fn with_bool_mode(mut self, mode: BoolMode) -> Self {
+        self.bool_mode = mode;
+        self
+    }
+
+    /// Matches element and attribute names to fields ignoring ASCII case, as
+    /// HTML does.
+    ///
+    /// Names are lowercased before matching, so `<DIV CLASS="x">` fills a
+    /// `class` field. Field names must then be lowercase.
+    pub fn case_insensitive(mut self, enabled: bool) -> Self {
+        self.case_insensitive = enabled;
+        self
+    }
+
+    /// Matches element names to fields without their namespace prefix, so
+    /// `<env:Body>` fills a `Body` field.
+    ///
+    /// Prefixes are dropped, not resolved: `a:x` and `b:x` both match `x`.
+    /// Attribute names keep their prefix.
+    pub fn ignore_namespace_prefixes(mut self, enabled: bool) -> Self {
+        self.ignore_namespace_prefixes = enabled;
+        self
+    }
+
+    /// Makes structs see every namespace declaration (`xmlns` and
+    /// `xmlns:prefix`) as an attribute.
+    ///
+    /// By default a struct only sees the declarations it has a field for,
+    /// such as `#[serde(rename = "@xmlns")]`, so a namespaced root doesn't
+    /// trip `deny_unknown_fields`. Maps always see every attribute.
+    pub fn keep_namespace_declarations(mut self, enabled: bool) -> Self {
+        self.keep_namespace_declarations = enabled;
+        self
+    }
+
+    /// Makes self-describing deserialization (`deserialize_any`) read text
+    /// that looks like an integer, float or boolean as that type instead of a
+    /// string.
+    ///
+    /// This lets generic targets such as `serde_json::Value` see `<n>42</n>`
+    /// as the number 42. Typed fields are unaffected.
+    pub fn infer_scalars(mut self, enabled: bool) -> Self {
+        self.infer_scalars = enabled;
+        self
+    }
+
+    /// Makes self-describing deserialization (`deserialize_any`) present
+    /// repeated sibling elements as a single key holding a sequence.
+    ///
+    /// Without this, `<a><b>1</b><b>2</b></a>` reads as a map with the key
+    /// `b` twice, which targets such as `serde_json::Value` collapse to the
+    /// last value. Typed fields are unaffected.
+    pub fn group_repeated_elements(mut self, enabled: bool) -> Self {
+        self.group_repeated = enabled;
+        self
+    }
+
+    /// Sets whether a sequence field also takes same-named elements that
+    /// follow other elements, so `<r><a/><b/><a/></r>` fills `a: Vec<_>` with
+    /// both `a`s. Enabled by default.
+    ///
+    /// A sequence looks past at most 4096 events of other siblings for its
+    /// next item, so that many sequence fields on a large element don't each
+    /// scan all of it; an item beyond that is a duplicate field. When
+    /// disabled, a sequence ends at the first element with another name.
+    pub fn interleaved_sequences(mut self, enabled: bool) -> Self {
+        self.interleaved_sequences = enabled;
+        self
+    }
+
+    /// Makes a scalar `$value` field take only the leading text of a mixed
+    /// element, so `<section>Intro<item>a</item>more</section>` gives
+    /// `"Intro"` while the `item` elements fill their own field.
+    ///
+    /// By default all text directly inside the element is joined, which sets
+    /// aside the child elements until their end tag is found. With this
+    /// option the text is read without looking ahead, and text after the
+    /// first child element is ignored.
+    pub fn leading_text_as_value(mut self, enabled: bool) -> Self {
+        self.leading_text_as_value = enabled;
+        self
+    }
+
+    /// Records the name and raw text of each leaf element read, before
+    /// unescaping, for checking parsed values against the source.
+    pub fn record_leaf_texts(mut self, enabled: bool) -> Self {
+        self.leaf_texts = enabled.then(Vec::new);
+        self
+    }
+
+    /// Returns the name and raw text of each leaf element read so far, in
+    /// document order. Empty unless [`record_leaf_texts`] is enabled.
+    ///
+    /// [`record_leaf_texts`]: Self::record_leaf_texts
+    pub fn leaf_texts(&self) -> &[(String, String)] {
+        self.leaf_texts.as_deref().unwrap_or(&[])
+    }
+
+    /// Moves same-named children of the element whose start tag was just
+    /// read next to each other, at the place of the first one, and returns
+    /// the names that occur more than once. All events read are left to be
+    /// read again.
+    fn group_children(&mut self) -> Result<Vec<String>> {
+        let case_insensitive = self.case_insensitive;
+        let ignore_prefixes = self.ignore_namespace_prefixes;
+        // Each unit holds the elements sharing a name, or a single other event
+        let mut units: Vec<Vec<(XmlEvent<'de>, Span)>> = Vec::new();
+        let mut unit_of: HashMap<String, usize> = HashMap::new();
+        let mut repeated = Vec::new();
+        let mut depth = 0usize;
+        let mut current = 0usize;
+        loop {
+            let (event, span) = self.next_spanned_event()?;
+            if depth == 0 {
+                current = match &event {
+                    XmlEvent::StartElement { name, .. } | XmlEvent::EmptyElement { name, .. } => {
+                        let key = field_name(name, case_insensitive, ignore_prefixes);
+                        match unit_of.get(&key) {
+                            Some(&unit) => {
+                                if !repeated.contains(&key) {
+                                    repeated.push(key);
+                                }
+                                unit
+                            }
+                            None => {
+                                unit_of.insert(key, units.len());
+                                units.push(Vec::new());
+                                units.len() - 1
+                            }
+                        }
+                    }
+                    _ => {
+                        units.push(Vec::new());
+                        units.len() - 1
+                    }
+                };
+            }
+            let done = match &event {
+                XmlEvent::StartElement { .. } => {
+                    depth += 1;
+                    false
+                }
+                XmlEvent::EndElement { .. } if depth == 0 => true,
+                XmlEvent::EndElement { .. } => {
+                    depth -= 1;
+                    false
+                }
+                XmlEvent::Eof => true,
+                _ => false,
+            };
+            units[current].push((event, span));
+            if done {
+                break;
+            }
+        }
+        self.unread_events(units.into_iter().flatten().collect());
+        Ok(repeated)
+    }
+
+    /// Visits text found by `deserialize_any`, inferring its type if enabled.
+    fn visit_text<V>(&self, text: String, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.infer_scalars {
+            let numeric = !text.is_empty()
+                && text
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E'));
+            if numeric {
+                if let Ok(v) = text.parse::<i64>() {
+                    return visitor.visit_i64(v);
+                }
+                if let Ok(v) = text.parse::<u64>() {
+                    return visitor.visit_u64(v);
+                }
+                if let Ok(v) = text.parse::<f64>() {
+                    return visitor.visit_f64(v);
+                }
+            }
+            match text.as_str() {
+                "true" => return visitor.visit_bool(true),
+                "false" => return visitor.visit_bool(false),
+                _ => {}
+            }
         }
+        visitor.visit_string(text)
     }
 
     /// Peeks at the next event without consuming it.
     fn peek_event(&mut self) -> Result<&XmlEvent<'de>> {
         if self.peeked.is_none() {
-            self.peeked = Some(self.reader.next_event()?);
+            self.peeked = Some(self.next_spanned_event()?);
         }
-        Ok(self.peeked.as_ref().unwrap())
+        Ok(&self.peeked.as_ref().unwrap().0)
+    }
+
+    /// Returns where the peeked event starts in the input.
+    fn peeked_offset(&self) -> Option<usize> {
+        self.peeked.as_ref().map(|(_, span)| span.start.offset)
     }
 
     /// Consumes and returns the next event.
     fn next_event(&mut self) -> Result<XmlEvent<'de>> {
-        if let Some(event) = self.peeked.take() {
-            Ok(event)
+        self.next_spanned_event().map(|(event, _)| event)
+    }
+
+    /// Consumes the next event along with the range of input it was read
+    /// from, which stays known for events set aside and read again.
+    fn next_spanned_event(&mut self) -> Result<(XmlEvent<'de>, Span)> {
+        if let Some(entry) = self.peeked.take() {
+            Ok(entry)
+        } else if let Some(entry) = self.replay.pop_front() {
+            Ok(entry)
         } else {
-            self.reader.next_event()
+            let event = self.reader.next_event()?;
+            Ok((event, self.reader.last_span()))
+        }
+    }
+
+    /// Puts events back in front of the upcoming input, in order.
+    fn unread_events(&mut self, events: Vec<(XmlEvent<'de>, Span)>) {
+        if events.is_empty() {
+            return;
+        }
+        if let Some(entry) = self.peeked.take() {
+            self.replay.push_front(entry);
+        }
+        for entry in events.into_iter().rev() {
+            self.replay.push_front(entry);
         }
     }
 
     /// Reads text content until we hit an end tag or another element.
-    fn read_text_content(&mut self) -> Result<String> {
-        let mut content = String::new();
+    ///
+    /// A single text node that needed no unescaping stays borrowed from the input.
+    fn read_text_content(&mut self) -> Result<Cow<'de, str>> {
+        let mut content: Option<Cow<'de, str>> = None;
+
+        while let XmlEvent::Text(_) | XmlEvent::CData(_) = self.peek_event()? {
+            if let XmlEvent::Text(text) | XmlEvent::CData(text) = self.next_event()? {
+                content = Some(match content {
+                    None => text,
+                    Some(prev) => Cow::Owned(prev.into_owned() + &text),
+                });
+            }
+        }
+
+        Ok(content.unwrap_or(Cow::Borrowed("")))
+    }
+
+    /// Reads a value nested under `name`, recording it in the error path.
+    fn in_segment<T>(
+        &mut self,
+        name: String,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.path.push(PathSegment { name, index: None });
+        let result = f(self).map_err(|e| self.attach_path(e));
+        self.path.pop();
+        result
+    }
+
+    /// Records the current element path on an error that has none yet.
+    fn attach_path(&self, err: Error) -> Error {
+        if err.path().is_some() || self.path.is_empty() {
+            return err;
+        }
+
+        let mut path = String::new();
+        for segment in &self.path {
+            path.push('/');
+            path.push_str(&segment.name);
+            if let Some(index) = segment.index {
+                path.push_str(&format!("[{}]", index));
+            }
+        }
+        err.with_path(path)
+    }
+
+    /// Reads the text at the cursor together with any later text directly
+    /// inside the enclosing element, in one pass up to its end tag.
+    ///
+    /// Child elements met on the way are set aside whole, in order, to be read
+    /// afterwards; nothing past the end tag is read. The pieces keep the
+    /// whitespace between them, as for `$text`, so only the ends of the joined
+    /// text are trimmed. Text in a single piece stays borrowed.
+    fn read_mixed_text(&mut self) -> Result<Cow<'de, str>> {
+        if self.leading_text_as_value {
+            return self.read_text_content();
+        }
 
+        let mut pieces: Vec<(Cow<'de, str>, Option<Span>)> = Vec::new();
+        let mut skipped = Vec::new();
         loop {
             match self.peek_event()? {
-                XmlEvent::Text(text) => {
-                    content.push_str(text);
-                    self.next_event()?;
-                }
-                XmlEvent::CData(data) => {
-                    content.push_str(data);
-                    self.next_event()?;
-                }
-                _ => break,
+                XmlEvent::EndElement { .. } | XmlEvent::Eof => break,
+                XmlEvent::Text(_) | XmlEvent::CData(_) => match self.next_spanned_event()? {
+                    (XmlEvent::Text(text), span) => pieces.push((text, Some(span))),
+                    (XmlEvent::CData(data), _) => pieces.push((data, None)),
+                    _ => {}
+                },
+                _ => self.set_aside(&mut skipped)?,
             }
         }
+        self.unread_events(skipped);
 
-        Ok(content)
+        if pieces.len() <= 1 {
+            return Ok(pieces.pop().map_or(Cow::Borrowed(""), |(text, _)| text));
+        }
+        let mut text = String::new();
+        for (piece, span) in &pieces {
+            match span {
+                Some(span) => text.push_str(&self.reader.untrimmed_text(piece, *span)),
+                None => text.push_str(piece),
+            }
+        }
+        if self.reader.trims_text() {
+            text = text.trim().to_string();
+        }
+        Ok(Cow::Owned(text))
+    }
+
+    /// Moves the next event and, for a start tag, its whole subtree to `out`.
+    fn set_aside(&mut self, out: &mut Vec<(XmlEvent<'de>, Span)>) -> Result<()> {
+        let mut depth = 0usize;
+        loop {
+            let (event, span) = self.next_spanned_event()?;
+            match event {
+                XmlEvent::StartElement { .. } => depth += 1,
+                XmlEvent::EndElement { .. } => depth = depth.saturating_sub(1),
+                XmlEvent::Eof => return Err(Error::unexpected_eof()),
+                _ => {}
+            }
+            out.push((event, span));
+            if depth == 0 {
+                return Ok(());
+            }
+        }
     }
 
     /// Reads element text and consumes the end tag.
-    fn read_element_text(&mut self) -> Result<String> {
+    fn read_element_text(&mut self) -> Result<Cow<'de, str>> {
         if self.is_empty_element {
             self.is_empty_element = false;
             self.start_consumed = false;
-            return Ok(String::new());
+            return Ok(Cow::Borrowed(""));
         }
 
         let content = self.read_text_content()?;
@@ -129,14 +882,40 @@ impl<'de> Deserializer<'de> {
         Ok(content)
     }
 
+    /// Reads the text of a leaf element whose start tag ends at `start` and
+    /// was just consumed, recording its raw text.
+    fn read_leaf_text(&mut self, name: String, start: usize) -> Result<Cow<'de, str>> {
+        let content = self.read_text_content()?;
+        let raw = match self.peek_event()? {
+            XmlEvent::EndElement { .. } => {
+                let end = self.peeked_offset().unwrap_or(start).max(start);
+                String::from_utf8_lossy(&self.reader.input()[start..end]).into_owned()
+            }
+            _ => content.clone().into_owned(),
+        };
+        if let Some(leaf_texts) = self.leaf_texts.as_mut() {
+            leaf_texts.push((name, raw));
+        }
+
+        self.start_consumed = false;
+        if let XmlEvent::EndElement { .. } = self.peek_event()? {
+            self.next_event()?;
+        }
+        Ok(content)
+    }
+
     /// Skips the current element and all its children.
+    ///
+    /// Must be called just after the element's start tag. Text, CDATA,
+    /// comments and processing instructions are single events, so markup-like
+    /// content inside them never affects the depth, and the reader ensures end
+    /// tags match their start tags.
     fn skip_element(&mut self) -> Result<()> {
-        let mut depth = 1;
+        let mut depth = 1usize;
         while depth > 0 {
             match self.next_event()? {
                 XmlEvent::StartElement { .. } => depth += 1,
                 XmlEvent::EndElement { .. } => depth -= 1,
-                XmlEvent::EmptyElement { .. } => {}
                 XmlEvent::Eof => return Err(Error::unexpected_eof()),
                 _ => {}
             }
@@ -144,22 +923,251 @@ impl<'de> Deserializer<'de> {
         Ok(())
     }
 
+    /// Skips the declaration, comments and processing instructions that may
+    /// come before an element.
+    fn skip_prolog(&mut self) -> Result<()> {
+        while let XmlEvent::XmlDecl { .. }
+        | XmlEvent::Comment(_)
+        | XmlEvent::ProcessingInstruction { .. } = self.peek_event()?
+        {
+            self.next_event()?;
+        }
+        Ok(())
+    }
+
+    /// Reads the next element and returns its markup exactly as written.
+    fn read_outer_xml(&mut self) -> Result<String> {
+        if self.pending_value.is_some() || self.mixed_content.is_some() || self.start_consumed {
+            return Err(Error::invalid_value("expected an element to capture"));
+        }
+        self.skip_prolog()?;
+        // Spans are kept for events read ahead, so they locate the markup too
+        let (event, span) = self.next_spanned_event()?;
+        let start = span.start.offset;
+        let mut end = span.end.offset;
+        let mut depth = match event {
+            XmlEvent::StartElement { .. } => 1usize,
+            XmlEvent::EmptyElement { .. } => 0,
+            _ => return Err(Error::invalid_value("expected an element to capture")),
+        };
+        while depth > 0 {
+            let (event, span) = self.next_spanned_event()?;
+            match event {
+                XmlEvent::StartElement { .. } => depth += 1,
+                XmlEvent::EndElement { .. } => depth -= 1,
+                XmlEvent::Eof => return Err(Error::unexpected_eof()),
+                _ => {}
+            }
+            end = end.max(span.end.offset);
+        }
+        std::str::from_utf8(&self.reader.input()[start..end])
+            .map(str::to_string)
+            .map_err(|_| Error::new(crate::error::ErrorKind::InvalidUtf8))
+    }
+
+    /// Deserializes an element as a map of its attributes and children.
+    ///
+    /// For a struct, `fields` lists its field names; if it has a `$value`
+    /// field, child elements matching no other field are routed to it.
+    fn deserialize_element<V>(
+        &mut self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.mixed_content = None;
+
+        // Check if start was already consumed (for nested structs)
+        let (name, mut attrs, is_empty) = if self.start_consumed {
+            self.start_consumed = false;
+            let is_empty = self.is_empty_element;
+            self.is_empty_element = false;
+            (None, vec![], is_empty)
+        } else {
+            self.skip_prolog()?;
+
+            // Get attributes from the start element
+            match self.next_event()? {
+                XmlEvent::StartElement { name, attributes } => {
+                    let attrs: Vec<_> = attributes
+                        .into_iter()
+                        .map(|a| (a.name, a.value))
+                        .collect();
+                    (Some(name.into_owned()), attrs, false)
+                }
+                XmlEvent::EmptyElement { name, attributes } => {
+                    let attrs: Vec<_> = attributes
+                        .into_iter()
+                        .map(|a| (a.name, a.value))
+                        .collect();
+                    (Some(name.into_owned()), attrs, true)
+                }
+                XmlEvent::Eof => (None, vec![], true),
+                XmlEvent::Text(text) | XmlEvent::CData(text) if self.path.is_empty() => {
+                    return Err(Error::syntax(format!(
+                        "unexpected text before the root element: {:?}",
+                        text
+                    ))
+                    .with_position(self.reader.position()));
+                }
+                _ => (None, vec![], false),
+            }
+        };
+
+        // Namespace declarations are markup, not data, unless a field asks for them
+        if !fields.is_empty() && !self.keep_namespace_declarations {
+            let case_insensitive = self.case_insensitive;
+            attrs.retain(|(name, _)| {
+                !is_namespace_decl(name) || {
+                    let key = format!("@{}", field_name(name, case_insensitive, false));
+                    fields.contains(&key.as_str())
+                }
+            });
+        }
+
+        // Maps read with grouping take each name's elements together
+        let repeated = if self.group_repeated && fields.is_empty() && !is_empty {
+            self.group_children()?
+        } else {
+            Vec::new()
+        };
+
+        let read_content = move |de: &mut Self| {
+            let result = visitor.visit_map(MapDeserializer {
+                de: &mut *de,
+                attrs,
+                attr_idx: 0,
+                fields,
+                repeated,
+                key: None,
+                value_seen: false,
+                text: String::new(),
+                text_seen: false,
+                comments: Vec::new(),
+                comments_seen: false,
+                finished: is_empty,
+            })?;
+
+            // Consume remaining content until end element
+            if !is_empty {
+                loop {
+                    match de.peek_event()? {
+                        XmlEvent::EndElement { .. } => {
+                            de.next_event()?;
+                            break;
+                        }
+                        XmlEvent::Eof => break,
+                        _ => {
+                            de.next_event()?;
+                        }
+                    }
+                }
+            }
+
+            Ok(result)
+        };
+
+        // The outermost element starts the error path
+        match name {
+            Some(name) if self.path.is_empty() => self.in_segment(name, read_content),
+            _ => read_content(self),
+        }
+    }
+
+    /// Deserializes a tuple from the child elements of the next element,
+    /// taking the first `len` children by position whatever their names.
+    ///
+    /// An element holding text instead of children starts a sequence of
+    /// same-named siblings, which is read as before.
+    ///
+    /// If `exact` is set, more than `len` children or siblings is an error;
+    /// otherwise the extra children are skipped.
+    fn deserialize_positional<V>(
+        &mut self,
+        len: usize,
+        exact: bool,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.pending_value.is_none() && self.mixed_content.is_none() && !self.start_consumed {
+            if let XmlEvent::StartElement { .. } = self.peek_event()? {
+                let start = self.next_spanned_event()?;
+                while let XmlEvent::Comment(_) | XmlEvent::ProcessingInstruction { .. } =
+                    self.peek_event()?
+                {
+                    self.next_event()?;
+                }
+                if let XmlEvent::StartElement { .. } | XmlEvent::EmptyElement { .. } =
+                    self.peek_event()?
+                {
+                    let mut seq = TupleDeserializer {
+                        de: &mut *self,
+                        remaining: len,
+                        count: 0,
+                    };
+                    let value = visitor.visit_seq(&mut seq)?;
+                    if exact {
+                        seq.remaining = 1;
+                        while seq.next_element::<de::IgnoredAny>()?.is_some() {
+                            seq.remaining = 1;
+                        }
+                        if seq.count > len {
+                            return Err(too_many_items(seq.count, len));
+                        }
+                    }
+                    // Drop any further children along with the end tag
+                    self.skip_element()?;
+                    return Ok(value);
+                }
+                self.unread_events(vec![start]);
+            }
+        }
+        if !exact {
+            return de::Deserializer::deserialize_seq(self, visitor);
+        }
+        let exclude = self.mixed_content.take();
+        let mut seq = SeqDeserializer::new(self, exclude);
+        let value = visitor.visit_seq(&mut seq)?;
+        while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+        if seq.count > len {
+            return Err(too_many_items(seq.count, len));
+        }
+        Ok(value)
+    }
+
     /// Parses a value from a string.
     fn parse_value<T>(&self, s: &str) -> Result<T>
     where
         T: std::str::FromStr,
         T::Err: std::fmt::Display,
     {
-        s.parse()
+        s.trim()
+            .parse()
             .map_err(|e: T::Err| Error::invalid_value(e.to_string()))
     }
 
     /// Gets text for primitive deserialization.
-    fn get_text(&mut self) -> Result<String> {
+    fn get_text(&mut self) -> Result<Cow<'de, str>> {
         if let Some(value) = self.pending_value.take() {
             return Ok(value);
         }
 
+        // A scalar `$value` takes the text around child elements, which are left
+        // for their own fields or skipped as unknown
+        if self.mixed_content.take().is_some() {
+            if let XmlEvent::Text(_)
+            | XmlEvent::CData(_)
+            | XmlEvent::StartElement { .. }
+            | XmlEvent::EmptyElement { .. } = self.peek_event()?
+            {
+                return self.read_mixed_text();
+            }
+        }
+
         // If start was already consumed
         if self.start_consumed {
             return self.read_element_text();
@@ -168,14 +1176,23 @@ impl<'de> Deserializer<'de> {
         // Otherwise, we might need to consume a start element first
         match self.peek_event()? {
             XmlEvent::StartElement { .. } => {
-                self.next_event()?;
+                let (start, span) = self.next_spanned_event()?;
                 self.start_consumed = true;
                 self.is_empty_element = false;
-                self.read_element_text()
+                match start {
+                    XmlEvent::StartElement { name, .. } if self.leaf_texts.is_some() => {
+                        self.read_leaf_text(name.into_owned(), span.end.offset)
+                    }
+                    _ => self.read_element_text(),
+                }
             }
             XmlEvent::EmptyElement { .. } => {
-                self.next_event()?;
-                Ok(String::new())
+                if let XmlEvent::EmptyElement { name, .. } = self.next_event()? {
+                    if let Some(leaf_texts) = self.leaf_texts.as_mut() {
+                        leaf_texts.push((name.into_owned(), String::new()));
+                    }
+                }
+                Ok(Cow::Borrowed(""))
             }
             _ => self.read_text_content(),
         }
@@ -190,22 +1207,50 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         if let Some(value) = self.pending_value.take() {
-            return visitor.visit_string(value);
+            return self.visit_text(value.into_owned(), visitor);
+        }
+
+        if std::mem::take(&mut self.repeated_value) {
+            return self.deserialize_seq(visitor);
+        }
+
+        if self.mixed_content.take().is_some() {
+            if let XmlEvent::Text(_) | XmlEvent::CData(_) = self.peek_event()? {
+                let text = self.read_mixed_text()?.into_owned();
+                return self.visit_text(text, visitor);
+            }
         }
 
         match self.peek_event()? {
+            XmlEvent::StartElement { attributes, .. } if attributes.is_empty() => {
+                // An element holding only text is a string rather than a map
+                let mut events = vec![self.next_spanned_event()?];
+                let mut text = String::new();
+                while let XmlEvent::Text(t) | XmlEvent::CData(t) = self.peek_event()? {
+                    text.push_str(t);
+                    events.push(self.next_spanned_event()?);
+                }
+                if events.len() > 1 {
+                    if let XmlEvent::EndElement { .. } = self.peek_event()? {
+                        self.next_event()?;
+                        return self.visit_text(text, visitor);
+                    }
+                }
+                self.unread_events(events);
+                self.deserialize_map(visitor)
+            }
             XmlEvent::StartElement { .. } | XmlEvent::EmptyElement { .. } => {
                 self.deserialize_map(visitor)
             }
             XmlEvent::Text(text) => {
                 let text = text.clone().into_owned();
                 self.next_event()?;
-                visitor.visit_string(text)
+                self.visit_text(text, visitor)
             }
             XmlEvent::CData(data) => {
                 let data = data.clone().into_owned();
                 self.next_event()?;
-                visitor.visit_string(data)
+                self.visit_text(data, visitor)
             }
             XmlEvent::EndElement { .. } => visitor.visit_unit(),
             XmlEvent::Eof => visitor.visit_unit(),
@@ -221,10 +1266,9 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         let text = self.get_text()?;
-        match text.as_str() {
-            "true" | "1" | "yes" => visitor.visit_bool(true),
-            "false" | "0" | "no" => visitor.visit_bool(false),
-            _ => Err(Error::invalid_value(format!("expected boolean, got '{}'", text))),
+        match self.bool_mode.parse(text.trim()) {
+            Some(v) => visitor.visit_bool(v),
+            None => Err(Error::invalid_value(format!("expected boolean, got '{}'", text))),
         }
     }
 
@@ -324,7 +1368,11 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_string(visitor)
+        // Attribute values and text without entity references borrow from the input
+        match self.get_text()? {
+            Cow::Borrowed(text) => visitor.visit_borrowed_str(text),
+            Cow::Owned(text) => visitor.visit_string(text),
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
@@ -332,15 +1380,18 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         let text = self.get_text()?;
-        visitor.visit_string(text)
+        visitor.visit_string(text.into_owned())
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let text = self.get_text()?;
-        visitor.visit_bytes(text.as_bytes())
+        // Text without entity references is a slice of the input
+        match self.get_text()? {
+            Cow::Borrowed(text) => visitor.visit_borrowed_bytes(text.as_bytes()),
+            Cow::Owned(text) => visitor.visit_byte_buf(text.into_bytes()),
+        }
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
@@ -354,14 +1405,32 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        let present = self.present == Some(self.path.len());
+        if present {
+            self.present = None;
+        }
         if self.pending_value.is_some() || self.start_consumed {
             return visitor.visit_some(self);
         }
 
-        match self.peek_event()? {
-            XmlEvent::EndElement { .. } | XmlEvent::Eof => visitor.visit_none(),
-            _ => visitor.visit_some(self),
-        }
+        let empty_is_none = present || self.none_mode == NoneMode::EmptyElement;
+        let is_none = match self.peek_event()? {
+            XmlEvent::EndElement { .. } | XmlEvent::Eof => return visitor.visit_none(),
+            XmlEvent::StartElement { attributes, .. } => is_nil(attributes),
+            XmlEvent::EmptyElement { attributes, .. } => {
+                is_nil(attributes) || (empty_is_none && attributes.is_empty())
+            }
+            _ => false,
+        };
+
+        if is_none {
+            if let XmlEvent::StartElement { .. } = self.next_event()? {
+                self.skip_element()?;
+            }
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
     }
 
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
@@ -419,10 +1488,29 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
         visitor.visit_unit()
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if name == PRESENT_NAME {
+            // The field's own empty element stands for `None`
+            self.present = Some(self.path.len());
+            let value = visitor.visit_newtype_struct(&mut *self);
+            self.present = None;
+            return value;
+        }
+        if name == OUTER_XML_NAME {
+            let xml = self.read_outer_xml()?;
+            return visitor.visit_newtype_struct(de::value::StringDeserializer::new(xml));
+        }
+        if name == VALUE_NAME && self.value_item.take() == Some(self.path.len()) {
+            // An element item of a `$value` sequence is named like a variant
+            if let XmlEvent::StartElement { .. } | XmlEvent::EmptyElement { .. } =
+                self.peek_event()?
+            {
+                return visitor.visit_enum(EnumDeserializer::new(self));
+            }
+        }
         visitor.visit_newtype_struct(self)
     }
 
@@ -430,108 +1518,117 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(SeqDeserializer::new(self))
+        let exclude = self.mixed_content.take();
+        visitor.visit_seq(SeqDeserializer::new(self, exclude))
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        // Tuples and arrays hold exactly `len` items
+        self.deserialize_positional(len, true, visitor)
     }
 
     fn deserialize_tuple_struct<V>(
         self,
         _name: &'static str,
-        _len: usize,
+        len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.deserialize_positional(len, false, visitor)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        // Check if start was already consumed (for nested structs)
-        let (attrs, is_empty) = if self.start_consumed {
-            self.start_consumed = false;
-            let is_empty = self.is_empty_element;
-            self.is_empty_element = false;
-            (vec![], is_empty)
-        } else {
-            // Get attributes from the start element
-            match self.next_event()? {
-                XmlEvent::StartElement { attributes, .. } => {
-                    let attrs: Vec<_> = attributes
-                        .into_iter()
-                        .map(|a| (a.name.into_owned(), a.value.into_owned()))
-                        .collect();
-                    (attrs, false)
-                }
-                XmlEvent::EmptyElement { attributes, .. } => {
-                    let attrs: Vec<_> = attributes
-                        .into_iter()
-                        .map(|a| (a.name.into_owned(), a.value.into_owned()))
-                        .collect();
-                    (attrs, true)
-                }
-                XmlEvent::Eof => (vec![], true),
-                _ => (vec![], false),
-            }
-        };
-
-        let result = visitor.visit_map(MapDeserializer {
-            de: self,
-            attrs,
-            attr_idx: 0,
-            finished: is_empty,
-        })?;
-
-        // Consume remaining content until end element
-        if !is_empty {
-            loop {
-                match self.peek_event()? {
-                    XmlEvent::EndElement { .. } => {
-                        self.next_event()?;
-                        break;
-                    }
-                    XmlEvent::Eof => break,
-                    _ => {
-                        self.next_event()?;
-                    }
-                }
-            }
-        }
-
-        Ok(result)
+        self.deserialize_element(&[], visitor)
     }
 
     fn deserialize_struct<V>(
         self,
         _name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        self.deserialize_element(fields, visitor)
     }
 
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_enum(EnumDeserializer::new(self))
+        if self.pending_value.is_some() || self.start_consumed {
+            return visitor.visit_enum(EnumDeserializer::new(self));
+        }
+
+        // An element typed with `xsi:type` holds the variant's content itself
+        let typed = match self.peek_event()? {
+            XmlEvent::StartElement { attributes, .. }
+            | XmlEvent::EmptyElement { attributes, .. } => xsi_type(attributes),
+            _ => None,
+        };
+        if let Some(variant) = typed {
+            return visitor.visit_enum(EnumDeserializer {
+                de: self,
+                from_text: false,
+                typed: Some(variant),
+            });
+        }
+
+        // The element of the field holding the enum, or at the top level one
+        // named after the type, wraps the variant
+        let expected = match self.path.last() {
+            Some(segment) => segment.name.clone(),
+            None => name.to_string(),
+        };
+        let (case_insensitive, ignore_prefixes) =
+            (self.case_insensitive, self.ignore_namespace_prefixes);
+        let wrapper = match self.peek_event()? {
+            XmlEvent::StartElement { name, .. }
+                if field_name(name, case_insensitive, ignore_prefixes) == expected =>
+            {
+                name.to_string()
+            }
+            _ => return visitor.visit_enum(EnumDeserializer::new(self)),
+        };
+
+        self.next_event()?;
+        while let XmlEvent::Comment(_) | XmlEvent::ProcessingInstruction { .. } =
+            self.peek_event()?
+        {
+            self.next_event()?;
+        }
+        let value = visitor.visit_enum(EnumDeserializer::new(self))?;
+
+        // Only the wrapper's end tag may follow the variant
+        loop {
+            match self.next_event()? {
+                XmlEvent::EndElement { .. } => return Ok(value),
+                XmlEvent::Text(text) if text.trim().is_empty() => {}
+                XmlEvent::Comment(_) | XmlEvent::ProcessingInstruction { .. } => {}
+                XmlEvent::Eof => return Err(Error::unexpected_eof()),
+                _ => {
+                    return Err(Error::invalid_value(format!(
+                        "unexpected content after the variant in '{}'",
+                        wrapper
+                    ))
+                    .with_position(self.reader.position()));
+                }
+            }
+        }
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
@@ -545,7 +1642,11 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.pending_value = None;
+        // An unknown attribute's value is all there is to skip; the event after
+        // it belongs to the next field
+        if self.pending_value.take().is_some() {
+            return visitor.visit_unit();
+        }
 
         if self.start_consumed {
             if !self.is_empty_element {
@@ -573,18 +1674,90 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     }
 }
 
+/// Returns the variant named by an `xsi:type` attribute, without any
+/// namespace prefix.
+fn xsi_type<'de>(attributes: &[Attribute<'de>]) -> Option<Cow<'de, str>> {
+    let value = &attributes.iter().find(|a| a.name == "xsi:type")?.value;
+    match value.rsplit_once(':') {
+        Some((_, variant)) => Some(Cow::Owned(variant.to_string())),
+        None => Some(value.clone()),
+    }
+}
+
+/// Returns whether the attributes mark an element as nil (`xsi:nil="true"`).
+fn is_nil(attributes: &[Attribute<'_>]) -> bool {
+    attributes
+        .iter()
+        .any(|a| (a.name == "xsi:nil" || a.name == "nil") && a.value == "true")
+}
+
+/// The number of events of other siblings a sequence sets aside while
+/// looking for its next item.
+const INTERLEAVED_LOOKAHEAD: usize = 4096;
+
 /// Sequence deserializer for arrays and vectors.
+///
+/// Items are the siblings sharing the first item's element name. Differently
+/// named siblings in between are set aside and handed back to the parent once
+/// the sequence is dropped, so `<a/><b/><a/>` yields both `a` elements, up to
+/// [`INTERLEAVED_LOOKAHEAD`] set-aside events. Without
+/// [`Deserializer::interleaved_sequences`], the sequence ends at the first
+/// differently named element instead. Text and comments between items are
+/// always set aside.
 struct SeqDeserializer<'a, 'de> {
     de: &'a mut Deserializer<'de>,
     element_name: Option<String>,
+    /// For a `$value` sequence, the sibling fields that are not items. Every
+    /// other child element and text node is an item.
+    exclude: Option<&'static [&'static str]>,
+    /// Whether differently named siblings are set aside rather than ending
+    /// the sequence.
+    interleaved: bool,
+    /// Sibling events skipped while looking for further items.
+    skipped: Vec<(XmlEvent<'de>, Span)>,
+    /// Number of items read so far.
+    count: usize,
 }
 
 impl<'a, 'de> SeqDeserializer<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Self {
+    fn new(de: &'a mut Deserializer<'de>, exclude: Option<&'static [&'static str]>) -> Self {
         Self {
+            interleaved: de.interleaved_sequences,
             de,
             element_name: None,
+            exclude,
+            skipped: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Reads the next item, numbering it in the error path.
+    fn read_item<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.count += 1;
+        if let Some(segment) = self.de.path.last_mut() {
+            segment.index = Some(self.count);
+        }
+        if self.exclude.is_some() {
+            self.de.value_item = Some(self.de.path.len());
         }
+        let item = seed.deserialize(&mut *self.de);
+        self.de.value_item = None;
+        item.map(Some)
+    }
+
+    /// Sets aside the next event and, for a start tag, its whole subtree.
+    fn skip_sibling(&mut self) -> Result<()> {
+        self.de.set_aside(&mut self.skipped)
+    }
+}
+
+impl<'a, 'de> Drop for SeqDeserializer<'a, 'de> {
+    fn drop(&mut self) {
+        let skipped = std::mem::take(&mut self.skipped);
+        self.de.unread_events(skipped);
     }
 }
 
@@ -595,38 +1768,123 @@ impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
     where
         T: DeserializeSeed<'de>,
     {
+        let case_insensitive = self.de.case_insensitive;
+        let ignore_prefixes = self.de.ignore_namespace_prefixes;
         loop {
             match self.de.peek_event()? {
                 XmlEvent::StartElement { name, .. } | XmlEvent::EmptyElement { name, .. } => {
-                    let current_name = name.clone().into_owned();
+                    let name = field_name(name, case_insensitive, ignore_prefixes);
+                    if let Some(fields) = self.exclude {
+                        if fields.contains(&name.as_str()) {
+                            self.skip_sibling()?;
+                            continue;
+                        }
+                        return self.read_item(seed);
+                    }
 
-                    if let Some(ref expected) = self.element_name {
-                        if &current_name != expected {
-                            return Ok(None);
+                    match self.element_name {
+                        Some(ref expected) if name != *expected => {
+                            if !self.interleaved || self.skipped.len() >= INTERLEAVED_LOOKAHEAD {
+                                return Ok(None);
+                            }
+                            self.skip_sibling()?;
+                            continue;
                         }
-                    } else {
-                        self.element_name = Some(current_name);
+                        Some(_) => {}
+                        None => self.element_name = Some(name),
                     }
 
-                    return seed.deserialize(&mut *self.de).map(Some);
+                    return self.read_item(seed);
                 }
                 XmlEvent::EndElement { .. } | XmlEvent::Eof => return Ok(None),
+                XmlEvent::Text(_) | XmlEvent::CData(_)
+                    if self.exclude.is_none() && self.element_name.is_some() =>
+                {
+                    self.skip_sibling()?;
+                }
                 XmlEvent::Text(_) | XmlEvent::CData(_) => {
+                    return self.read_item(seed);
+                }
+                XmlEvent::Comment(_) => {
+                    // Left for the parent's `$comment` field
+                    self.skip_sibling()?;
+                }
+                _ => {
+                    self.de.next_event()?;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the error for a tuple or array given `count` items instead of `len`.
+fn too_many_items(count: usize, len: usize) -> Error {
+    de::Error::invalid_length(count, &format!("{} items", len).as_str())
+}
+
+/// Sequence deserializer for tuples, reading child elements by position.
+struct TupleDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    /// Number of items still expected.
+    remaining: usize,
+    /// Number of items read so far.
+    count: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for TupleDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        loop {
+            match self.de.peek_event()? {
+                XmlEvent::StartElement { .. } | XmlEvent::EmptyElement { .. } => {
+                    self.remaining -= 1;
+                    self.count += 1;
+                    if let Some(segment) = self.de.path.last_mut() {
+                        segment.index = Some(self.count);
+                    }
                     return seed.deserialize(&mut *self.de).map(Some);
                 }
+                XmlEvent::EndElement { .. } | XmlEvent::Eof => return Ok(None),
                 _ => {
                     self.de.next_event()?;
                 }
             }
         }
     }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
 }
 
 /// Map deserializer for structs.
 struct MapDeserializer<'a, 'de> {
     de: &'a mut Deserializer<'de>,
-    attrs: Vec<(String, String)>,
+    attrs: Vec<(Cow<'de, str>, Cow<'de, str>)>,
     attr_idx: usize,
+    /// Field names of the struct being deserialized, if any.
+    fields: &'static [&'static str],
+    /// Names of children grouped into one sequence each.
+    repeated: Vec<String>,
+    /// Name of the child element whose value is read next.
+    key: Option<String>,
+    /// Whether the `$value` key has been produced.
+    value_seen: bool,
+    /// Direct text gathered for the struct's `$text` field.
+    text: String,
+    /// Whether the `$text` key has been produced.
+    text_seen: bool,
+    /// Comments gathered for the struct's `$comment` field.
+    comments: Vec<String>,
+    /// Whether the `$comment` key has been produced.
+    comments_seen: bool,
     finished: bool,
 }
 
@@ -641,29 +1899,71 @@ impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a, 'de> {
         if self.attr_idx < self.attrs.len() {
             let (name, _) = &self.attrs[self.attr_idx];
             // Prefix with @ to match serde rename convention for attributes
-            self.de.pending_value = Some(format!("@{}", name));
+            let key = format!("@{}", field_name(name, self.de.case_insensitive, false));
+            self.de.pending_value = Some(Cow::Owned(key));
             return seed.deserialize(&mut *self.de).map(Some);
         }
 
         if self.finished {
-            return Ok(None);
+            return self.next_gathered_key(seed);
         }
 
         // Then check for child elements
+        let case_insensitive = self.de.case_insensitive;
+        let ignore_prefixes = self.de.ignore_namespace_prefixes;
         loop {
             match self.de.peek_event()? {
                 XmlEvent::StartElement { name, .. } | XmlEvent::EmptyElement { name, .. } => {
-                    let name = name.clone().into_owned();
+                    let name = field_name(name, case_insensitive, ignore_prefixes);
+                    // Elements matching no field belong to the struct's `$value`, if
+                    // any, when it is a sequence or enum; a scalar skips them
+                    let key = if !self.value_seen
+                        && self.fields.contains(&"$value")
+                        && !self.fields.contains(&name.as_str())
+                    {
+                        self.value_seen = true;
+                        self.de.mixed_content = Some(self.fields);
+                        "$value".to_string()
+                    } else {
+                        name
+                    };
+                    if self.repeated.contains(&key) {
+                        self.de.repeated_value = true;
+                    }
                     // Don't consume the element here - let the value deserializer do it
-                    self.de.pending_value = Some(name);
+                    self.key = Some(key.clone());
+                    self.de.pending_value = Some(Cow::Owned(key));
                     return seed.deserialize(&mut *self.de).map(Some);
                 }
                 XmlEvent::EndElement { .. } | XmlEvent::Eof => {
                     self.finished = true;
-                    return Ok(None);
+                    return self.next_gathered_key(seed);
+                }
+                XmlEvent::Comment(_) if self.fields.contains(&"$comment") => {
+                    if let XmlEvent::Comment(comment) = self.de.next_event()? {
+                        self.comments.push(comment.into_owned());
+                    }
+                }
+                XmlEvent::Text(_) | XmlEvent::CData(_) if self.fields.contains(&"$text") => {
+                    // All direct text forms `$text`, whatever children lie between
+                    match self.de.next_spanned_event()? {
+                        (XmlEvent::Text(text), span) => {
+                            self.text.push_str(&self.de.reader.untrimmed_text(&text, span));
+                        }
+                        (XmlEvent::CData(data), _) => self.text.push_str(&data),
+                        _ => {}
+                    }
+                }
+                XmlEvent::Text(_) | XmlEvent::CData(_) if self.value_seen => {
+                    // Already read as part of `$value`
+                    self.de.next_event()?;
                 }
                 XmlEvent::Text(_) | XmlEvent::CData(_) => {
-                    self.de.pending_value = Some("$value".to_string());
+                    // Text split around child elements forms a single `$value`
+                    self.value_seen = true;
+                    self.de.mixed_content = Some(self.fields);
+                    self.key = Some("$value".to_string());
+                    self.de.pending_value = Some(Cow::Borrowed("$value"));
                     return seed.deserialize(&mut *self.de).map(Some);
                 }
                 _ => {
@@ -679,25 +1979,112 @@ impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a, 'de> {
     {
         // Handle attribute values
         if self.attr_idx < self.attrs.len() {
-            let (_, value) = &self.attrs[self.attr_idx];
+            let (name, value) = &mut self.attrs[self.attr_idx];
             self.attr_idx += 1;
-            self.de.pending_value = Some(value.clone());
-            return seed.deserialize(&mut *self.de);
+            self.de.pending_value = Some(std::mem::take(value));
+            let segment = format!("@{}", name);
+            return self.de.in_segment(segment, |de| seed.deserialize(de));
         }
 
         // Handle element values - element already consumed in next_key_seed
-        seed.deserialize(&mut *self.de)
+        let key = self.key.take().unwrap_or_default();
+        if key == "$comment" {
+            let comments = std::mem::take(&mut self.comments);
+            return seed.deserialize(CommentsDeserializer(comments));
+        }
+        if key == "$text" {
+            let text = std::mem::take(&mut self.text);
+            let text = if self.de.reader.trims_text() { text.trim().to_string() } else { text };
+            self.de.pending_value = Some(Cow::Owned(text));
+        }
+        let value = self.de.in_segment(key, |de| seed.deserialize(de));
+        self.de.mixed_content = None;
+        self.de.repeated_value = false;
+        value
+    }
+}
+
+impl<'a, 'de> MapDeserializer<'a, 'de> {
+    /// Produces the keys of values gathered from among the children, once
+    /// they have all been read.
+    fn next_gathered_key<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let key = if self.fields.contains(&"$comment") && !self.comments_seen {
+            self.comments_seen = true;
+            "$comment"
+        } else if self.fields.contains(&"$text") && !self.text_seen {
+            self.text_seen = true;
+            "$text"
+        } else {
+            return Ok(None);
+        };
+        self.key = Some(key.to_string());
+        self.de.pending_value = Some(Cow::Borrowed(key));
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+/// Deserializes the comments of an element, as a sequence or as one string
+/// with the comments separated by newlines.
+struct CommentsDeserializer(Vec<String>);
+
+impl<'de> de::Deserializer<'de> for CommentsDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(de::value::SeqDeserializer::new(self.0.into_iter()))
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.0.join("\n"))
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.0.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char bytes byte_buf unit
+        unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
     }
 }
 
 /// Enum deserializer.
 struct EnumDeserializer<'a, 'de> {
     de: &'a mut Deserializer<'de>,
+    /// Whether the variant name came from text or an attribute value rather
+    /// than from an element that still has to be consumed.
+    from_text: bool,
+    /// The variant named by the next element's `xsi:type` attribute.
+    typed: Option<Cow<'de, str>>,
 }
 
 impl<'a, 'de> EnumDeserializer<'a, 'de> {
     fn new(de: &'a mut Deserializer<'de>) -> Self {
-        Self { de }
+        Self { de, from_text: false, typed: None }
     }
 }
 
@@ -705,12 +2092,19 @@ impl<'de, 'a> de::EnumAccess<'de> for EnumDeserializer<'a, 'de> {
     type Error = Error;
     type Variant = Self;
 
-    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    fn variant_seed<V>(mut self, seed: V) -> Result<(V::Value, Self::Variant)>
     where
         V: DeserializeSeed<'de>,
     {
+        if let Some(variant) = self.typed.take() {
+            self.de.pending_value = Some(variant);
+            let variant = seed.deserialize(&mut *self.de)?;
+            return Ok((variant, self));
+        }
+
         // Check for pending value (text-based enum)
         if self.de.pending_value.is_some() {
+            self.from_text = true;
             let variant = seed.deserialize(&mut *self.de)?;
             return Ok((variant, self));
         }
@@ -718,12 +2112,13 @@ impl<'de, 'a> de::EnumAccess<'de> for EnumDeserializer<'a, 'de> {
         // The variant name is the element name
         match self.de.peek_event()? {
             XmlEvent::StartElement { name, .. } | XmlEvent::EmptyElement { name, .. } => {
-                let name = name.clone().into_owned();
+                let name = name.clone();
                 self.de.pending_value = Some(name);
             }
-            XmlEvent::Text(text) => {
-                let text = text.clone().into_owned();
+            XmlEvent::Text(_) | XmlEvent::CData(_) => {
+                let text = self.de.read_text_content()?;
                 self.de.pending_value = Some(text);
+                self.from_text = true;
             }
             _ => {}
         }
@@ -745,6 +2140,9 @@ impl<'de, 'a> de::VariantAccess<'de> for EnumDeserializer<'a, 'de> {
             self.de.is_empty_element = false;
             return Ok(());
         }
+        if self.from_text {
+            return Ok(());
+        }
 
         match self.de.peek_event()? {
             XmlEvent::EmptyElement { .. } => {
@@ -766,18 +2164,18 @@ impl<'de, 'a> de::VariantAccess<'de> for EnumDeserializer<'a, 'de> {
         seed.deserialize(&mut *self.de)
     }
 
-    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        de::Deserializer::deserialize_seq(&mut *self.de, visitor)
+        self.de.deserialize_positional(len, false, visitor)
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        de::Deserializer::deserialize_map(&mut *self.de, visitor)
+        self.de.deserialize_element(fields, visitor)
     }
 }
 
@@ -861,6 +2259,26 @@ mod tests {
         assert_eq!(config2.value, None);
     }
 
+    #[test]
+    fn test_deserialize_xsi_nil() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Config {
+            value: Option<String>,
+            count: Option<u32>,
+            name: String,
+        }
+
+        let xml = r#"<Config><value xsi:nil="true"/><count nil="true"></count><name>n</name></Config>"#;
+        let config: Config = from_str(xml).unwrap();
+        assert_eq!(config.value, None);
+        assert_eq!(config.count, None);
+        assert_eq!(config.name, "n");
+
+        let xml = r#"<Config><value xsi:nil="false">v</value><count>1</count><name>n</name></Config>"#;
+        let config: Config = from_str(xml).unwrap();
+        assert_eq!(config.value, Some("v".to_string()));
+    }
+
     #[test]
     fn test_deserialize_bool() {
         #[derive(Debug, Deserialize, PartialEq)]
@@ -903,6 +2321,81 @@ mod tests {
         assert_eq!(items.item, vec!["one", "two", "three"]);
     }
 
+    #[test]
+    fn test_deserialize_vector_interleaved() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct A {
+            #[serde(rename = "@n")]
+            n: u32,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct B {
+            value: String,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Root {
+            a: Vec<A>,
+            b: B,
+            c: Vec<String>,
+        }
+
+        let xml = r#"<r><a n="1"/><c>x</c><b><value>v</value></b><a n="2"/><c>y</c><a n="3"/></r>"#;
+        let root: Root = from_str(xml).unwrap();
+        assert_eq!(root.a, vec![A { n: 1 }, A { n: 2 }, A { n: 3 }]);
+        assert_eq!(root.b.value, "v");
+        assert_eq!(root.c, vec!["x", "y"]);
+
+        // When disabled, a sequence ends at the first element with another name
+        let mut de = Deserializer::from_str(xml).interleaved_sequences(false);
+        let err = Root::deserialize(&mut de).unwrap_err();
+        assert!(err.to_string().contains("duplicate field `a`"), "{}", err);
+        let xml = r#"<r><a n="1"/><!-- c --><a n="2"/><b><value>v</value></b><c>x</c></r>"#;
+        let mut de = Deserializer::from_str(xml).interleaved_sequences(false);
+        let root = Root::deserialize(&mut de).unwrap();
+        assert_eq!(root.a, vec![A { n: 1 }, A { n: 2 }]);
+        assert_eq!(root.c, vec!["x"]);
+
+        // The look-ahead for the next item is bounded
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Many {
+            a: Vec<u32>,
+            b: Vec<String>,
+        }
+
+        let near = format!("<m><a>1</a>{}<a>2</a></m>", "<b>x</b>".repeat(1000));
+        assert_eq!(from_str::<Many>(&near).unwrap().a, [1, 2]);
+        let far = format!("<m><a>1</a>{}<a>2</a></m>", "<b>x</b>".repeat(2000));
+        let err = from_str::<Many>(&far).unwrap_err();
+        assert!(err.to_string().contains("duplicate field `a`"), "{}", err);
+    }
+
+    #[test]
+    fn test_deserialize_vector_mixed_empty_items() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct List {
+            item: Vec<String>,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct OptionalList {
+            item: Vec<Option<String>>,
+        }
+
+        let xml = "<list><item/><item>x</item><item/></list>";
+        let list: List = from_str(xml).unwrap();
+        assert_eq!(list.item, vec!["", "x", ""]);
+
+        let list: OptionalList = from_str(xml).unwrap();
+        assert_eq!(list.item, vec![Some(String::new()), Some("x".to_string()), Some(String::new())]);
+
+        let mut de = Deserializer::from_str(xml).with_none_as(NoneMode::EmptyElement);
+        let list = OptionalList::deserialize(&mut de).unwrap();
+        assert_eq!(list.item, vec![None, Some("x".to_string()), None]);
+    }
+
     #[test]
     fn test_deserialize_escaped_content() {
         #[derive(Debug, Deserialize, PartialEq)]
@@ -962,48 +2455,137 @@ mod tests {
     }
 
     #[test]
-    fn test_deserialize_vector_of_structs() {
+    fn test_from_bytes_borrows_utf8() {
         #[derive(Debug, Deserialize, PartialEq)]
-        struct Item {
-            name: String,
-            count: u32,
+        struct Data<'a> {
+            value: &'a str,
+        }
+
+        let bytes = b"<Data><value>test</value></Data>";
+        let data: Data<'_> = from_bytes(bytes).unwrap();
+        assert_eq!(data.value, "test");
+
+        // Transcoded text cannot be borrowed
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("<Data><value>x</value></Data>".encode_utf16().flat_map(u16::to_le_bytes));
+        assert!(from_bytes::<Data<'_>>(&bytes).is_err());
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Kind {
+            A,
+            B(String),
         }
 
         #[derive(Debug, Deserialize, PartialEq)]
-        struct Items {
-            item: Vec<Item>,
+        struct Owned {
+            item: Vec<String>,
+            note: Option<String>,
+            kind: Vec<Kind>,
         }
 
-        let xml = r#"<Items><item><name>A</name><count>1</count></item><item><name>B</name><count>2</count></item></Items>"#;
-        let items: Items = from_str(xml).unwrap();
-        assert_eq!(items.item.len(), 2);
-        assert_eq!(items.item[0].name, "A");
-        assert_eq!(items.item[1].name, "B");
+        let xml = "<Owned><item>a</item><item>b</item><note>n</note>\
+                   <kind>A</kind><kind><B>c</B></kind></Owned>";
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(xml.encode_utf16().flat_map(u16::to_le_bytes));
+        let data: Owned = from_bytes(&bytes).unwrap();
+        assert_eq!(data, from_str::<Owned>(xml).unwrap());
+        assert_eq!(data.kind, vec![Kind::A, Kind::B("c".to_string())]);
     }
 
     #[test]
-    fn test_deserialize_multiple_attributes() {
+    fn test_from_bytes_utf8_bom() {
         #[derive(Debug, Deserialize, PartialEq)]
-        struct Element {
-            #[serde(rename = "@id")]
-            id: String,
-            #[serde(rename = "@class")]
-            class: String,
-            name: String,
+        struct Data {
+            value: String,
         }
 
-        let xml = r#"<Element id="main" class="container"><name>Test</name></Element>"#;
-        let elem: Element = from_str(xml).unwrap();
-        assert_eq!(elem.id, "main");
-        assert_eq!(elem.class, "container");
-        assert_eq!(elem.name, "Test");
+        let bytes = b"\xEF\xBB\xBF<Data><value>test</value></Data>";
+        let data: Data = from_bytes(bytes).unwrap();
+        assert_eq!(data.value, "test");
+        let data: Data = from_str("\u{feff}<?xml version=\"1.0\"?><Data><value>x</value></Data>").unwrap();
+        assert_eq!(data.value, "x");
     }
 
     #[test]
-    fn test_deserialize_attributes_with_special_chars() {
+    fn test_from_bytes_utf16() {
         #[derive(Debug, Deserialize, PartialEq)]
-        struct Element {
-            #[serde(rename = "@title")]
+        struct Data {
+            value: String,
+        }
+
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\n<Data><value>caf\u{e9}</value></Data>";
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(xml.encode_utf16().flat_map(u16::to_le_bytes));
+        let data: Data = from_bytes(&bytes).unwrap();
+        assert_eq!(data.value, "caf\u{e9}");
+
+        // A byte order mark without a declaration
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("<Data><value>x</value></Data>".encode_utf16().flat_map(u16::to_le_bytes));
+        let data: Data = from_bytes(&bytes).unwrap();
+        assert_eq!(data.value, "x");
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn test_from_bytes_latin1() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Data {
+            value: String,
+        }
+
+        let bytes = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><Data><value>caf\xE9</value></Data>";
+        let data: Data = from_bytes(bytes).unwrap();
+        assert_eq!(data.value, "caf\u{e9}");
+
+        let bytes = b"<?xml version=\"1.0\" encoding=\"windows-1252\"?><Data><value>\x80</value></Data>";
+        let data: Data = from_bytes(bytes).unwrap();
+        assert_eq!(data.value, "\u{20ac}");
+    }
+
+    #[test]
+    fn test_deserialize_vector_of_structs() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Item {
+            name: String,
+            count: u32,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Items {
+            item: Vec<Item>,
+        }
+
+        let xml = r#"<Items><item><name>A</name><count>1</count></item><item><name>B</name><count>2</count></item></Items>"#;
+        let items: Items = from_str(xml).unwrap();
+        assert_eq!(items.item.len(), 2);
+        assert_eq!(items.item[0].name, "A");
+        assert_eq!(items.item[1].name, "B");
+    }
+
+    #[test]
+    fn test_deserialize_multiple_attributes() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Element {
+            #[serde(rename = "@id")]
+            id: String,
+            #[serde(rename = "@class")]
+            class: String,
+            name: String,
+        }
+
+        let xml = r#"<Element id="main" class="container"><name>Test</name></Element>"#;
+        let elem: Element = from_str(xml).unwrap();
+        assert_eq!(elem.id, "main");
+        assert_eq!(elem.class, "container");
+        assert_eq!(elem.name, "Test");
+    }
+
+    #[test]
+    fn test_deserialize_attributes_with_special_chars() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Element {
+            #[serde(rename = "@title")]
             title: String,
         }
 
@@ -1094,4 +2676,1100 @@ mod tests {
         assert_eq!(list.item[0].id, 1);
         assert_eq!(list.item[1].id, 2);
     }
+
+    #[test]
+    fn test_max_expanded_len() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Data {
+            content: String,
+        }
+
+        let xml = format!("<Data><content>{}</content></Data>", "&lt;".repeat(4096));
+        let mut de = Deserializer::from_str(&xml).max_expanded_len(1024);
+        assert!(Data::deserialize(&mut de).is_err());
+
+        let mut de = Deserializer::from_str(&xml).max_expanded_len(4096);
+        let data = Data::deserialize(&mut de).unwrap();
+        assert_eq!(data.content.len(), 4096);
+    }
+
+    #[test]
+    fn test_max_elements() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Data {
+            item: Vec<u32>,
+        }
+
+        let xml = format!("<Data>{}</Data>", "<item>1</item>".repeat(100));
+        let mut de = Deserializer::from_str(&xml).max_elements(100);
+        assert!(Data::deserialize(&mut de).is_err());
+
+        let mut de = Deserializer::from_str(&xml).max_elements(101);
+        assert_eq!(Data::deserialize(&mut de).unwrap().item.len(), 100);
+    }
+
+    #[test]
+    fn test_deserialize_enum_by_child_element() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Circle {
+            r: u32,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Shape {
+            Empty,
+            Circle(Circle),
+            Label(String),
+            Rect { w: u32, h: u32 },
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Doc {
+            kind: Shape,
+            name: String,
+        }
+
+        let parse = |kind: &str| -> Shape {
+            let xml = format!("<Doc><kind>{}</kind><name>n</name></Doc>", kind);
+            let doc: Doc = from_str(&xml).unwrap();
+            assert_eq!(doc.name, "n");
+            doc.kind
+        };
+
+        assert_eq!(parse("<Empty/>"), Shape::Empty);
+        assert_eq!(parse("Empty"), Shape::Empty);
+        assert_eq!(parse("<Circle><r>3</r></Circle>"), Shape::Circle(Circle { r: 3 }));
+        assert_eq!(parse("<Label>x</Label>"), Shape::Label("x".to_string()));
+        assert_eq!(parse("<Rect><w>1</w><h>2</h></Rect>"), Shape::Rect { w: 1, h: 2 });
+    }
+
+    #[test]
+    fn test_deserialize_enum_sequence_by_child_element() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Shape {
+            Empty,
+            Label(String),
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Doc {
+            kind: Vec<Shape>,
+        }
+
+        let xml = "<Doc><kind><Label>a</Label></kind><kind><Empty/></kind></Doc>";
+        let doc: Doc = from_str(xml).unwrap();
+        assert_eq!(doc.kind, vec![Shape::Label("a".to_string()), Shape::Empty]);
+    }
+
+    #[test]
+    fn test_deserialize_enum_wrapper_named_like_variant() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[allow(non_camel_case_types)]
+        enum Kind {
+            kind,
+            other(String),
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Doc {
+            kind: Kind,
+        }
+
+        let doc: Doc = from_str("<Doc><kind><other>x</other></kind></Doc>").unwrap();
+        assert_eq!(doc.kind, Kind::other("x".to_string()));
+        let doc: Doc = from_str("<Doc><kind><kind/></kind></Doc>").unwrap();
+        assert_eq!(doc.kind, Kind::kind);
+        let doc: Doc = from_str("<Doc><kind>\n  <!-- c --><kind/>\n</kind></Doc>").unwrap();
+        assert_eq!(doc.kind, Kind::kind);
+
+        for xml in [
+            "<Doc><kind><kind/><other>x</other></kind></Doc>",
+            "<Doc><kind><kind/>tail</kind></Doc>",
+        ] {
+            let err = from_str::<Doc>(xml).unwrap_err();
+            assert!(err.to_string().contains("after the variant in 'kind'"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_deserialize_borrowed_bytes() {
+        #[derive(Debug, Deserialize)]
+        struct Packet<'a> {
+            #[serde(borrow)]
+            raw: &'a [u8],
+            #[serde(borrow)]
+            escaped: Cow<'a, [u8]>,
+        }
+
+        let xml = "<Packet><raw>DEADBEEF</raw><escaped>a&amp;b</escaped></Packet>";
+        let packet: Packet<'_> = from_str(xml).unwrap();
+        assert_eq!(packet.raw, b"DEADBEEF");
+        assert!(matches!(packet.escaped, Cow::Owned(ref bytes) if bytes == b"a&b"));
+
+        let input = xml.as_bytes().as_ptr_range();
+        assert!(input.contains(&packet.raw.as_ptr()));
+    }
+
+    #[test]
+    fn test_deserialize_borrowed_attributes() {
+        #[derive(Debug, Deserialize)]
+        struct Node<'a> {
+            #[serde(rename = "@id")]
+            id: &'a str,
+            #[serde(rename = "@label", borrow)]
+            label: Cow<'a, str>,
+            #[serde(rename = "@title", borrow)]
+            title: Cow<'a, str>,
+            name: &'a str,
+        }
+
+        let xml = r#"<Node id="n1" label="plain" title="R&amp;D"><name>first</name></Node>"#;
+        let node: Node<'_> = from_str(xml).unwrap();
+        assert_eq!(node.id, "n1");
+        assert!(matches!(node.label, Cow::Borrowed("plain")));
+        // Unescaping needs an owned copy
+        assert!(matches!(node.title, Cow::Owned(ref title) if title == "R&D"));
+        assert_eq!(node.name, "first");
+
+        let input = xml.as_bytes().as_ptr_range();
+        assert!(input.contains(&node.id.as_ptr()));
+        assert!(input.contains(&node.label.as_ptr()));
+        assert!(input.contains(&node.name.as_ptr()));
+    }
+
+    #[test]
+    fn test_track_ids() {
+        #[derive(Debug, Deserialize)]
+        struct Section {
+            title: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Doc {
+            section: Vec<Section>,
+        }
+
+        let xml = r#"<Doc xml:id="doc"><section xml:id="s1"><title>A</title></section><section xml:id="s2"><title>B</title></section></Doc>"#;
+        let mut de = Deserializer::from_str(xml).track_ids(true);
+        let doc = Doc::deserialize(&mut de).unwrap();
+        assert_eq!(doc.section.len(), 2);
+        assert_eq!(doc.section[1].title, "B");
+
+        let ids = de.ids();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids["doc"].offset, 0);
+        assert_eq!(ids["s1"].offset, xml.find(r#"<section xml:id="s1""#).unwrap());
+        assert_eq!(ids["s2"].offset, xml.find(r#"<section xml:id="s2""#).unwrap());
+    }
+
+    #[test]
+    fn test_deserialize_attributes_text_and_children() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Icon {
+            #[serde(rename = "@src")]
+            src: String,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Link {
+            #[serde(rename = "@href")]
+            href: String,
+            #[serde(rename = "$value")]
+            text: String,
+            icon: Icon,
+            #[serde(rename = "@target")]
+            target: String,
+        }
+
+        let expected = Link {
+            href: "/home".to_string(),
+            text: "Home page".to_string(),
+            icon: Icon { src: "home.png".to_string() },
+            target: "_blank".to_string(),
+        };
+
+        for xml in [
+            r#"<Link href="/home" target="_blank">Home page<icon src="home.png"/></Link>"#,
+            r#"<Link target="_blank" rel="x" href="/home"><icon src="home.png"/>Home page</Link>"#,
+            r#"<Link href="/home" target="_blank">Home pa<!-- c --><icon src="home.png"/>ge</Link>"#,
+        ] {
+            let link: Link = from_str(xml).unwrap();
+            assert_eq!(link, expected, "{}", xml);
+        }
+
+        // Unknown elements are not taken as the text
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Anchor {
+            #[serde(rename = "@href")]
+            href: String,
+            #[serde(rename = "$value")]
+            text: String,
+        }
+
+        let xml = r#"<Link href="a"><unknown>x</unknown>Home</Link>"#;
+        let anchor: Anchor = from_str(xml).unwrap();
+        assert_eq!(anchor, Anchor { href: "a".to_string(), text: "Home".to_string() });
+
+        // Children set aside while joining the text are read afterwards as written
+        #[derive(Debug, Deserialize)]
+        struct Rich<'a> {
+            #[serde(rename = "$value")]
+            text: &'a str,
+            icon: crate::OuterXml,
+        }
+
+        let xml = r#"<Link><icon src="a"><b/></icon>Home page</Link>"#;
+        let rich: Rich<'_> = from_str(xml).unwrap();
+        assert_eq!(rich.text, "Home page");
+        assert_eq!(rich.icon.0, r#"<icon src="a"><b/></icon>"#);
+
+        #[derive(Debug, Deserialize)]
+        struct Split {
+            #[serde(rename = "$value")]
+            text: String,
+            icon: crate::OuterXml,
+        }
+
+        let split: Split = from_str(r#"<Link>Home <icon src="a"><b/></icon>page</Link>"#).unwrap();
+        assert_eq!(split.text, "Home page");
+        assert_eq!(split.icon.0, r#"<icon src="a"><b/></icon>"#);
+
+        // The whitespace between the pieces is kept, as for `$text`
+        let split: Split =
+            from_str(r#"<p>  Call <icon/> today <![CDATA[at 5]]> </p>"#).unwrap();
+        assert_eq!(split.text, "Call  today at 5");
+    }
+
+    #[test]
+    fn test_from_reader() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Doc {
+            name: String,
+        }
+
+        let builder = XmlReader::builder().max_elements(2).track_ids(true);
+        let xml = r#"<Doc xml:id="d"><name>n</name></Doc>"#;
+        let mut de = Deserializer::from_reader(builder.from_str(xml));
+        assert_eq!(Doc::deserialize(&mut de).unwrap().name, "n");
+        assert!(de.ids().contains_key("d"));
+
+        let xml = "<Doc><name>n</name><extra/></Doc>";
+        let mut de = Deserializer::from_reader(builder.from_str(xml));
+        assert!(Doc::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn test_error_path() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Employee {
+            #[serde(rename = "@id")]
+            id: u32,
+            age: u32,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Company {
+            name: String,
+            employee: Vec<Employee>,
+        }
+
+        let xml = r#"<Company><name>ACME</name><employee id="1"><age>30</age></employee><employee id="2"><age>old</age></employee></Company>"#;
+        let err = from_str::<Company>(xml).unwrap_err();
+        assert_eq!(err.path(), Some("/Company/employee[2]/age"));
+        assert!(err.to_string().contains("at /Company/employee[2]/age"));
+
+        let xml = r#"<Company><name>ACME</name><employee id="x"><age>30</age></employee></Company>"#;
+        let err = from_str::<Company>(xml).unwrap_err();
+        assert_eq!(err.path(), Some("/Company/employee[1]/@id"));
+    }
+
+    #[test]
+    fn test_invalid_escape_context() {
+        use crate::error::ErrorKind;
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Employee {
+            #[serde(rename = "@title")]
+            title: String,
+            name: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Company {
+            employee: Vec<Employee>,
+        }
+
+        // In text
+        let xml = "<Company><employee title=\"a\"><name>A</name></employee>\n\
+                   <employee title=\"b\"><name>B &bogus;</name></employee></Company>";
+        let err = from_str::<Company>(xml).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidEscape(e) if e == "&bogus;"));
+        assert_eq!(err.path(), Some("/Company/employee[2]/name"));
+        let position = err.position().unwrap();
+        assert_eq!((position.line, position.column), (2, 29));
+
+        // In an attribute value
+        let xml = "<Company><employee title=\"R&amp;D &bogus;\"><name>A</name></employee></Company>";
+        let err = from_str::<Company>(xml).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidEscape(e) if e == "&bogus;"));
+        assert_eq!(err.path(), Some("/Company/employee/@title"));
+        assert_eq!(err.position().unwrap().offset, xml.find("&bogus;").unwrap());
+        assert!(err.to_string().contains("at /Company/employee/@title, line 1"));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Div {
+            #[serde(rename = "@class")]
+            class: String,
+            p: Vec<String>,
+            span: String,
+        }
+
+        let xml = r#"<DIV CLASS="x"><P>one</P><Span>s</Span><p>two</p></DIV>"#;
+        let mut de = Deserializer::from_str(xml).case_insensitive(true);
+        let div = Div::deserialize(&mut de).unwrap();
+        assert_eq!(div.class, "x");
+        assert_eq!(div.p, vec!["one", "two"]);
+        assert_eq!(div.span, "s");
+
+        assert!(from_str::<Div>(xml).is_err());
+    }
+
+    #[test]
+    fn test_skip_tricky_subtrees() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Doc {
+            next: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Skips {
+            skip: Vec<serde::de::IgnoredAny>,
+            next: String,
+        }
+
+        let subtrees = [
+            "<skip><skip><skip/></skip><skip>a</skip></skip>",
+            "<skip><![CDATA[<skip></skip> ]] > </next>]]></skip>",
+            "<skip><!-- </skip><next>wrong</next> --></skip>",
+            "<skip>text<a>1</a>more<?pi </skip>?><b/>tail</skip>",
+        ];
+        for subtree in subtrees {
+            let xml = format!("<Doc>{}<next>right</next></Doc>", subtree);
+            let doc: Doc = from_str(&xml).unwrap();
+            assert_eq!(doc.next, "right", "skipping {}", subtree);
+
+            let xml = format!("<Skips><skip/>{}<next>right</next></Skips>", subtree);
+            let skips: Skips = from_str(&xml).unwrap();
+            assert_eq!(skips.skip.len(), 2, "skipping {}", subtree);
+            assert_eq!(skips.next, "right", "skipping {}", subtree);
+        }
+    }
+
+    #[test]
+    fn test_ignore_unknown_attribute() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Item {
+            name: String,
+            #[serde(default)]
+            tags: Vec<String>,
+        }
+
+        let xml = r#"<Item extra="x" more="y"><name>n</name><tags>a</tags><tags>b</tags></Item>"#;
+        let item: Item = from_str(xml).unwrap();
+        assert_eq!(item.name, "n");
+        assert_eq!(item.tags, vec!["a", "b"]);
+
+        let item: Item = from_str(r#"<Item extra="x"><odd/><name>n</name></Item>"#).unwrap();
+        assert_eq!(item.name, "n");
+    }
+
+    #[test]
+    fn test_ignore_namespace_prefixes() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Body {
+            #[serde(rename = "@xmlns:env")]
+            ns: String,
+            child: String,
+            item: Vec<u32>,
+        }
+
+        let xml = r#"<env:Body xmlns:env="urn:e"><child>x</child><a:item>1</a:item><b:item>2</b:item></env:Body>"#;
+        let mut de = Deserializer::from_str(xml).ignore_namespace_prefixes(true);
+        let body = Body::deserialize(&mut de).unwrap();
+        assert_eq!(body.ns, "urn:e");
+        assert_eq!(body.child, "x");
+        assert_eq!(body.item, vec![1, 2]);
+
+        assert!(from_str::<Body>(xml).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_tuple_positional() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Record(String, u32, bool);
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Doc {
+            t: (String, u32, bool),
+            record: Record,
+            after: u32,
+        }
+
+        let tuple: (String, u32, bool) =
+            from_str("<t><a>x</a><b>5</b><c>true</c></t>").unwrap();
+        assert_eq!(tuple, ("x".to_string(), 5, true));
+
+        let xml = "<Doc><t><a>x</a><b>5</b><!-- c --><c>true</c></t>\
+                   <record><name>r</name><n>1</n><ok>false</ok><extra>e</extra></record><after>2</after></Doc>";
+        let doc: Doc = from_str(xml).unwrap();
+        assert_eq!(doc.t, ("x".to_string(), 5, true));
+        assert_eq!(doc.record, Record("r".to_string(), 1, false));
+        assert_eq!(doc.after, 2);
+
+        // Same-named siblings holding text are still read in order
+        let xml = "<Doc><t>y</t><t>6</t><t>false</t><record><a>s</a><b>3</b><c>true</c></record><after>2</after></Doc>";
+        let doc: Doc = from_str(xml).unwrap();
+        assert_eq!(doc.t, ("y".to_string(), 6, false));
+    }
+
+    #[test]
+    fn test_deserialize_outer_xml() {
+        use crate::OuterXml;
+
+        #[derive(Debug, Deserialize)]
+        struct Doc {
+            a: OuterXml,
+            #[serde(default)]
+            empty: Option<OuterXml>,
+            after: u32,
+        }
+
+        let xml = r#"<Doc><a id="1"><b>x</b><!-- </a> --></a><empty  flag="y" /><after>2</after></Doc>"#;
+        let doc: Doc = from_str(xml).unwrap();
+        assert_eq!(doc.a.0, r#"<a id="1"><b>x</b><!-- </a> --></a>"#);
+        assert_eq!(doc.empty.unwrap().0, r#"<empty  flag="y" />"#);
+        assert_eq!(doc.after, 2);
+
+        let xml = "<?xml version=\"1.0\"?>\n<a id=\"1\"><b>x</b></a>\n";
+        let outer: OuterXml = from_str(xml).unwrap();
+        assert_eq!(outer.0, r#"<a id="1"><b>x</b></a>"#);
+
+        // Elements set aside while reading a sequence are still captured
+        #[derive(Debug, Deserialize)]
+        struct List {
+            item: Vec<u32>,
+            extra: OuterXml,
+        }
+
+        let xml = r#"<List><item>1</item><extra k="v"><b>&amp;</b></extra><item>2</item></List>"#;
+        let list: List = from_str(xml).unwrap();
+        assert_eq!(list.item, [1, 2]);
+        assert_eq!(list.extra.0, r#"<extra k="v"><b>&amp;</b></extra>"#);
+    }
+
+    #[test]
+    fn test_deserialize_array() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Point {
+            coords: [f64; 3],
+            name: String,
+        }
+
+        let xml = "<Point><coords>1</coords><coords>2.5</coords><coords>-3</coords><name>p</name></Point>";
+        let point: Point = from_str(xml).unwrap();
+        assert_eq!(point.coords, [1.0, 2.5, -3.0]);
+        assert_eq!(point.name, "p");
+
+        let xml = "<Point><coords><x>1</x><y>2</y><z>3</z></coords><name>p</name></Point>";
+        let point: Point = from_str(xml).unwrap();
+        assert_eq!(point.coords, [1.0, 2.0, 3.0]);
+
+        // Too few
+        let xml = "<Point><coords>1</coords><coords>2</coords><name>p</name></Point>";
+        assert!(from_str::<Point>(xml).is_err());
+        let xml = "<Point><coords><x>1</x><y>2</y></coords><name>p</name></Point>";
+        assert!(from_str::<Point>(xml).is_err());
+
+        // Too many
+        let xml = "<Point><coords>1</coords><coords>2</coords><coords>3</coords>\
+                   <coords>4</coords><name>p</name></Point>";
+        let err = from_str::<Point>(xml).unwrap_err();
+        assert!(err.to_string().contains("invalid length 4"), "{}", err);
+        let xml = "<Point><coords><x>1</x><y>2</y><z>3</z><w>4</w></coords><name>p</name></Point>";
+        assert!(from_str::<Point>(xml).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_text_field() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Note {
+            #[serde(rename = "@priority")]
+            priority: String,
+            #[serde(rename = "$text")]
+            text: String,
+            b: Vec<String>,
+        }
+
+        let xml = r#"<note priority="high">Call <b>Bob</b>, today<!-- c --><b>9</b><![CDATA[!]]></note>"#;
+        let note: Note = from_str(xml).unwrap();
+        assert_eq!(note.priority, "high");
+        assert_eq!(note.text, "Call , today!");
+        assert_eq!(note.b, vec!["Bob", "9"]);
+
+        let note: Note = from_str("<note priority=\"low\"><b>x</b>later</note>").unwrap();
+        assert_eq!(note.text, "later");
+        assert_eq!(note.b, vec!["x"]);
+
+        let xml = r#"<note priority="high">Call <b>Bob</b> today</note>"#;
+        let note: Note = from_str(xml).unwrap();
+        assert_eq!(note.text, "Call  today");
+        assert_eq!(note.b, vec!["Bob"]);
+
+        let xml = "<note priority=\"high\">\n  Call <b>Bob</b> today\n</note>";
+        assert_eq!(from_str::<Note>(xml).unwrap().text, "Call  today");
+
+        // An element without text has an empty `$text`
+        let note: Note = from_str(r#"<note priority="low"><b>x</b></note>"#).unwrap();
+        assert_eq!(note.text, "");
+        #[derive(Debug, Deserialize)]
+        struct Plain {
+            #[serde(rename = "$text")]
+            text: String,
+        }
+        assert_eq!(from_str::<Plain>("<note/>").unwrap().text, "");
+    }
+
+    #[test]
+    fn test_deserialize_after_prolog() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Doc {
+            name: String,
+        }
+
+        let xml = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<!-- first comment -->
+<!-- second comment -->
+<?xml-stylesheet type="text/xsl" href="style.xsl"?>
+<!DOCTYPE Doc [
+  <!ELEMENT Doc (name)>
+  <!ENTITY greeting "hi">
+]>
+<!-- after doctype --><?pi?>
+<Doc><name>n</name></Doc>
+<!-- trailing -->"#;
+        let doc: Doc = from_str(xml).unwrap();
+        assert_eq!(doc.name, "n");
+        let outer: crate::OuterXml = from_str(xml).unwrap();
+        assert_eq!(outer.0, "<Doc><name>n</name></Doc>");
+        let doc: Doc = from_bytes(xml.as_bytes()).unwrap();
+        assert_eq!(doc.name, "n");
+
+        for xml in [
+            "<?xml version=\"1.0\"?><!-- c -->stray<Doc><name>n</name></Doc>",
+            "<!-- c --><![CDATA[stray]]><Doc><name>n</name></Doc>",
+            "stray",
+        ] {
+            let err = from_str::<Doc>(xml).unwrap_err();
+            assert!(err.to_string().contains("before the root element"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_infer_scalars() {
+        use serde_json::{json, Value};
+
+        let xml = r#"<r id="7"><n>42</n><neg>-3</neg><big>18446744073709551615</big><f>2.5</f><b>true</b><s>1.2.3</s><t>nan</t></r>"#;
+        let mut de = Deserializer::from_str(xml).infer_scalars(true);
+        let value = Value::deserialize(&mut de).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "@id": 7,
+                "n": 42,
+                "neg": -3,
+                "big": 18446744073709551615u64,
+                "f": 2.5,
+                "b": true,
+                "s": "1.2.3",
+                "t": "nan",
+            })
+        );
+
+        let value: Value = from_str(xml).unwrap();
+        assert_eq!(value["n"], json!("42"));
+        assert_eq!(value["b"], json!("true"));
+    }
+
+    #[test]
+    fn test_transcode_to_json() {
+        use serde_json::{json, Value};
+
+        let xml = r#"<library name="City">
+            <book id="1"><title>Rust</title><author>A</author><author>B</author></book>
+            <magazine>M</magazine>
+            <book id="2" lang="en"><title>XML</title></book>
+            <note>Open <b>daily</b></note>
+            <empty/>
+        </library>"#;
+
+        let mut de = Deserializer::from_str(xml)
+            .group_repeated_elements(true)
+            .infer_scalars(true);
+        let mut out = Vec::new();
+        serde_transcode::transcode(&mut de, &mut serde_json::Serializer::new(&mut out)).unwrap();
+        let value: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "@name": "City",
+                "book": [
+                    {"@id": 1, "title": "Rust", "author": ["A", "B"]},
+                    {"@id": 2, "@lang": "en", "title": "XML"},
+                ],
+                "magazine": "M",
+                "note": {"$value": "Open", "b": "daily"},
+                "empty": {},
+            })
+        );
+
+        // Each name's elements are gathered at the place of the first one
+        let xml = "<a><x>1</x><y>2</y><x>3</x><!-- c --><z/><y>4</y></a>";
+        let mut de = Deserializer::from_str(xml).group_repeated_elements(true);
+        let mut out = Vec::new();
+        serde_transcode::transcode(&mut de, &mut serde_json::Serializer::new(&mut out)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#"{"x":["1","3"],"y":["2","4"],"z":{}}"#);
+
+        // Without grouping, repeated elements are separate keys
+        let mut de = Deserializer::from_str("<a><b>1</b><b>2</b></a>");
+        let mut out = Vec::new();
+        serde_transcode::transcode(&mut de, &mut serde_json::Serializer::new(&mut out)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#"{"b":"1","b":"2"}"#);
+    }
+
+    #[test]
+    fn test_deserialize_xsi_type() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Circle {
+            radius: f64,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Shape {
+            Round(Circle),
+            Label(String),
+            Empty,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Drawing {
+            shapes: Vec<Shape>,
+        }
+
+        let xml = r#"<Drawing>
+            <shapes xsi:type="geo:Round"><radius>2</radius></shapes>
+            <shapes xsi:type="Label">hi</shapes>
+            <shapes xsi:type="Empty"/>
+        </Drawing>"#;
+        let drawing: Drawing = from_str(xml).unwrap();
+        assert_eq!(
+            drawing.shapes,
+            vec![
+                Shape::Round(Circle { radius: 2.0 }),
+                Shape::Label("hi".to_string()),
+                Shape::Empty
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_leaf_texts() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Item {
+            #[serde(rename = "@id")]
+            id: u32,
+            name: String,
+            note: String,
+            price: f64,
+        }
+
+        let xml = r#"<Item id="7"><name>Fish &amp; Chips</name><note/><price> 4.50 </price></Item>"#;
+        let mut de = Deserializer::from_str(xml).record_leaf_texts(true);
+        let item = Item::deserialize(&mut de).unwrap();
+        assert_eq!(item.name, "Fish & Chips");
+        assert_eq!(item.price, 4.5);
+        assert_eq!(
+            de.leaf_texts(),
+            [
+                ("name".to_string(), "Fish &amp; Chips".to_string()),
+                ("note".to_string(), String::new()),
+                ("price".to_string(), " 4.50 ".to_string()),
+            ]
+        );
+
+        let mut de = Deserializer::from_str(xml);
+        Item::deserialize(&mut de).unwrap();
+        assert!(de.leaf_texts().is_empty());
+
+        // Leaves set aside while reading a sequence keep their raw text
+        #[derive(Debug, Deserialize)]
+        struct List {
+            item: Vec<u32>,
+            name: String,
+        }
+
+        let xml = "<List><item>1</item><name>x&amp;y</name><item>2</item></List>";
+        let mut de = Deserializer::from_str(xml).record_leaf_texts(true);
+        let list = List::deserialize(&mut de).unwrap();
+        assert_eq!((list.item, list.name.as_str()), (vec![1, 2], "x&y"));
+        assert!(de.leaf_texts().contains(&("name".to_string(), "x&amp;y".to_string())));
+    }
+
+    #[test]
+    fn test_from_str_seq() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Li {
+            #[serde(rename = "$value")]
+            text: String,
+        }
+
+        let items: Vec<Li> = from_str_seq("<li>a</li>\n<!-- b --><li>b</li>").unwrap();
+        assert_eq!(items, [Li { text: "a".to_string() }, Li { text: "b".to_string() }]);
+
+        let words: Vec<String> = from_str_seq("<w>x</w><v>y</v>").unwrap();
+        assert_eq!(words, ["x", "y"]);
+
+        assert!(from_str_seq::<String>("").unwrap().is_empty());
+        assert!(from_str_seq::<String>("<w>x</w>stray").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_comments() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Config {
+            host: String,
+            #[serde(rename = "$comment")]
+            comments: Vec<String>,
+            port: u16,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Note {
+            #[serde(rename = "$comment")]
+            note: Option<String>,
+        }
+
+        let xml = "<Config><!-- a --><host>h</host><!--b--><port>1</port></Config>";
+        let config: Config = from_str(xml).unwrap();
+        assert_eq!(config.comments, ["a", "b"]);
+        assert_eq!(config.port, 1);
+
+        let note: Note = from_str("<Note><!-- one --><!-- two --></Note>").unwrap();
+        assert_eq!(note.note.as_deref(), Some("one\ntwo"));
+        let note: Note = from_str("<Note></Note>").unwrap();
+        assert_eq!(note.note, None);
+
+        // Comments among the items of a sequence field are kept
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct List {
+            item: Vec<u32>,
+            #[serde(rename = "$comment")]
+            comments: Vec<String>,
+        }
+
+        let xml = "<List><!--a--><item>1</item><!--b--><item>2</item><!--c--></List>";
+        let list: List = from_str(xml).unwrap();
+        assert_eq!(list.item, [1, 2]);
+        assert_eq!(list.comments, ["a", "b", "c"]);
+
+        let list: List = from_str("<List><item>1</item></List>").unwrap();
+        assert!(list.comments.is_empty());
+        let config: Config = from_str("<Config><host>h</host><port>1</port></Config>").unwrap();
+        assert!(config.comments.is_empty());
+    }
+
+    #[test]
+    fn test_deserialize_auto_close() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Card {
+            title: String,
+            #[serde(default)]
+            body: String,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Deck {
+            card: Vec<Card>,
+            name: String,
+        }
+
+        let mut de = Deserializer::from_str("<card><title>x</card>").auto_close(true);
+        let card = Card::deserialize(&mut de).unwrap();
+        assert_eq!(card, Card { title: "x".to_string(), body: String::new() });
+        assert!(from_str::<Card>("<card><title>x</card>").is_err());
+
+        let xml = "<deck><card><title>a</card><card><title>b</title><body>c</card><name>n</deck>";
+        let mut de = Deserializer::from_str(xml).auto_close(true);
+        let deck = Deck::deserialize(&mut de).unwrap();
+        assert_eq!(deck.card.len(), 2);
+        assert_eq!(deck.card[0], Card { title: "a".to_string(), body: String::new() });
+        assert_eq!(deck.card[1], Card { title: "b".to_string(), body: "c".to_string() });
+        assert_eq!(deck.name, "n");
+    }
+
+    #[test]
+    fn test_bool_mode() {
+        #[derive(Debug, Deserialize)]
+        struct Flag {
+            #[serde(rename = "@on")]
+            on: bool,
+        }
+
+        let read = |text: &str, mode: BoolMode| {
+            let xml = format!(r#"<Flag on="{}"/>"#, text);
+            let mut de = Deserializer::from_str(&xml).with_bool_mode(mode);
+            Flag::deserialize(&mut de).map(|flag| flag.on).ok()
+        };
+
+        for (text, expected) in [
+            ("true", Some(true)),
+            ("True", Some(true)),
+            ("YES", Some(true)),
+            ("On", Some(true)),
+            ("1", Some(true)),
+            ("false", Some(false)),
+            ("FALSE", Some(false)),
+            ("No", Some(false)),
+            ("Off", Some(false)),
+            ("0", Some(false)),
+            ("maybe", None),
+        ] {
+            assert_eq!(read(text, BoolMode::Lenient), expected, "{}", text);
+        }
+
+        assert_eq!(read("true", BoolMode::Strict), Some(true));
+        assert_eq!(read("false", BoolMode::Strict), Some(false));
+        for text in ["True", "TRUE", "False", "1", "0", "yes", "on"] {
+            assert_eq!(read(text, BoolMode::Strict), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_trim_text() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Person {
+            name: String,
+            age: u32,
+            active: bool,
+        }
+
+        let xml = "<Person>\n  <name> Bob </name>\n  <age> 30 </age>\n  <active> true </active>\n</Person>";
+        let person: Person = from_str(xml).unwrap();
+        assert_eq!(person, Person { name: "Bob".to_string(), age: 30, active: true });
+
+        let mut de = Deserializer::from_str(xml).trim_text(false);
+        let person = Person::deserialize(&mut de).unwrap();
+        assert_eq!(person, Person { name: " Bob ".to_string(), age: 30, active: true });
+
+        let xml = "<Person>\n  <name>   </name>\n  <age>30</age>\n  <active>true</active>\n</Person>";
+        let mut de = Deserializer::from_str(xml).trim_text(false);
+        let person = Person::deserialize(&mut de).unwrap();
+        assert_eq!(person, Person { name: "   ".to_string(), age: 30, active: true });
+    }
+
+    #[test]
+    fn test_deserialize_options() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Person {
+            #[serde(default)]
+            name: String,
+            #[serde(default)]
+            active: Option<bool>,
+        }
+
+        let xml = "<Person><Name> Bob </Name><active>Yes</active></Person>";
+
+        let strict = DeserializeOptions { bool_mode: BoolMode::Strict, ..Default::default() };
+        assert!(from_str_with_options::<Person>(xml, &strict).is_err());
+
+        let person: Person = from_str_with_options(xml, &DeserializeOptions::default()).unwrap();
+        assert_eq!(person, Person { name: String::new(), active: Some(true) });
+
+        let html = DeserializeOptions {
+            case_insensitive: true,
+            preserve_whitespace: true,
+            ..Default::default()
+        };
+        let person: Person = from_str_with_options(xml, &html).unwrap();
+        assert_eq!(person, Person { name: " Bob ".to_string(), active: Some(true) });
+
+        let shallow = DeserializeOptions { max_depth: Some(1), ..Default::default() };
+        assert!(from_str_with_options::<Person>(xml, &shallow).unwrap_err().is_syntax());
+    }
+
+    #[test]
+    fn test_deserialize_adjacently_tagged_tag_first() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(tag = "t", content = "c")]
+        enum Msg {
+            Ping(u32),
+            Move { x: i32, y: i32 },
+        }
+
+        let xml = "<Msg><t>Move</t><c><x>1</x><y>-2</y></c></Msg>";
+        assert_eq!(from_str::<Msg>(xml).unwrap(), Msg::Move { x: 1, y: -2 });
+
+        let xml = "<Msg><t>Ping</t><!-- content --><c>5</c></Msg>";
+        assert_eq!(from_str::<Msg>(xml).unwrap(), Msg::Ping(5));
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Log {
+            first: Msg,
+            second: Msg,
+        }
+
+        let xml = "<Log><second><t>Ping</t><c>1</c></second>\
+                   <first><t>Move</t><c><x>3</x><y>4</y></c></first></Log>";
+        let log: Log = from_str(xml).unwrap();
+        assert_eq!(log, Log { first: Msg::Move { x: 3, y: 4 }, second: Msg::Ping(1) });
+
+        // Content before its tag is not moved, and serde reads it as text
+        let xml = "<Msg><c>5</c><t>Ping</t></Msg>";
+        assert!(from_str::<Msg>(xml).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_two_field_struct_not_reordered() {
+        // Children are read in document order, so a plain two-field struct
+        // reads like one with more fields
+        #[derive(Debug, Deserialize)]
+        struct Two {
+            a: String,
+            b: Vec<String>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Three {
+            a: String,
+            b: Vec<String>,
+            c: Option<String>,
+        }
+
+        let xml = "<s><b>1</b><a>x</a><b>2</b></s>";
+        let two: Two = from_str(xml).unwrap();
+        let three: Three = from_str(xml).unwrap();
+        assert_eq!((two.a.as_str(), two.b), ("x", vec!["1".to_string(), "2".to_string()]));
+        assert_eq!((three.a.as_str(), three.b), ("x", vec!["1".to_string(), "2".to_string()]));
+
+        let mut de = Deserializer::from_str(xml).interleaved_sequences(false);
+        let two = Two::deserialize(&mut de).unwrap_err().to_string();
+        let mut de = Deserializer::from_str(xml).interleaved_sequences(false);
+        let three = Three::deserialize(&mut de).unwrap_err().to_string();
+        assert!(two.contains("duplicate field `b`"), "{}", two);
+        assert!(three.contains("duplicate field `b`"), "{}", three);
+
+        // In order, both read the same
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct S {
+            b: String,
+            a: crate::OuterXml,
+        }
+
+        let s: S = from_str("<S><a><x/></a><b>t</b></S>").unwrap();
+        assert_eq!((s.b.as_str(), s.a.0.as_str()), ("t", "<a><x/></a>"));
+    }
+
+    #[test]
+    fn test_map_entries_in_source_order() {
+        /// A map that keeps its entries in insertion order.
+        #[derive(Debug, PartialEq)]
+        struct Ordered(Vec<(String, String)>);
+
+        impl<'de> Deserialize<'de> for Ordered {
+            fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+            where
+                D: de::Deserializer<'de>,
+            {
+                struct OrderedVisitor;
+
+                impl<'de> Visitor<'de> for OrderedVisitor {
+                    type Value = Ordered;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                        f.write_str("a map")
+                    }
+
+                    fn visit_map<A>(self, mut map: A) -> std::result::Result<Ordered, A::Error>
+                    where
+                        A: MapAccess<'de>,
+                    {
+                        let mut entries = Vec::new();
+                        while let Some(entry) = map.next_entry()? {
+                            entries.push(entry);
+                        }
+                        Ok(Ordered(entries))
+                    }
+                }
+
+                deserializer.deserialize_map(OrderedVisitor)
+            }
+        }
+
+        let entries = |pairs: &[(&str, &str)]| {
+            Ordered(pairs.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect())
+        };
+
+        let map: Ordered = from_str(r#"<x c="1" a="2" b="3"/>"#).unwrap();
+        assert_eq!(map, entries(&[("@c", "1"), ("@a", "2"), ("@b", "3")]));
+
+        let map: Ordered = from_str(r#"<x c="1" a="2"><z>3</z><b>4</b><a>5</a></x>"#).unwrap();
+        assert_eq!(map, entries(&[("@c", "1"), ("@a", "2"), ("z", "3"), ("b", "4"), ("a", "5")]));
+    }
+
+    #[test]
+    fn test_namespace_declarations() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(deny_unknown_fields)]
+        struct Item {
+            #[serde(rename = "@id")]
+            id: u32,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(deny_unknown_fields)]
+        struct Root {
+            item: Item,
+        }
+
+        let xml = r#"<Root xmlns="http://ns" xmlns:x="urn:x"><item xmlns:y="y" id="1"/></Root>"#;
+        let root: Root = from_str(xml).unwrap();
+        assert_eq!(root, Root { item: Item { id: 1 } });
+
+        let mut de = Deserializer::from_str(xml).keep_namespace_declarations(true);
+        let err = Root::deserialize(&mut de).unwrap_err();
+        assert!(err.to_string().contains("xmlns"), "{}", err);
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Captured {
+            #[serde(rename = "@xmlns")]
+            ns: String,
+            #[serde(rename = "@id")]
+            id: u32,
+        }
+
+        let captured: Captured =
+            from_str(r#"<c xmlns:x="urn:x" xmlns="http://ns" id="2"/>"#).unwrap();
+        assert_eq!(captured, Captured { ns: "http://ns".to_string(), id: 2 });
+
+        let map: std::collections::BTreeMap<String, String> =
+            from_str(r#"<m xmlns="http://ns" a="1"/>"#).unwrap();
+        assert_eq!(map.get("@xmlns").map(String::as_str), Some("http://ns"));
+    }
 }
@@ -0,0 +1,176 @@
+//! Character encoding detection and transcoding.
+//!
+//! The reader operates on UTF-8. UTF-16 documents, recognized by their byte
+//! order mark or their leading `<`, and ISO-8859-1 documents are always
+//! transcoded. Documents whose XML declaration names another encoding are
+//! transcoded to UTF-8 when the `encoding` feature is enabled, and rejected
+//! with a clear error otherwise.
+
+use crate::error::{Error, ErrorKind, Result};
+use memchr::memchr;
+use std::borrow::Cow;
+
+/// Returns the encoding label from a leading XML declaration, if any.
+///
+/// Only ASCII-compatible encodings can be detected this way, which covers
+/// every encoding the `encoding` feature is able to transcode.
+pub(crate) fn declared_encoding(bytes: &[u8]) -> Option<&str> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    if !bytes.starts_with(b"<?xml") {
+        return None;
+    }
+
+    let end = memchr(b'>', bytes)?;
+    let decl = std::str::from_utf8(&bytes[5..end]).ok()?;
+    let rest = &decl[decl.find("encoding")? + 8..];
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+
+    let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let rest = &rest[1..];
+    rest.find(quote).map(|len| &rest[..len])
+}
+
+/// Returns whether an encoding label names UTF-8 or a subset of it.
+fn is_utf8_compatible(label: &str) -> bool {
+    ["utf-8", "utf8", "us-ascii", "ascii"]
+        .iter()
+        .any(|l| label.eq_ignore_ascii_case(l))
+}
+
+/// Byte order of UTF-16 input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// Detects UTF-16 input from its byte order mark or, lacking one, from a
+/// leading `<` encoded in UTF-16, returning the input after any mark.
+fn detect_utf16(bytes: &[u8]) -> Option<(ByteOrder, &[u8])> {
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => Some((ByteOrder::Little, rest)),
+        [0xFE, 0xFF, rest @ ..] => Some((ByteOrder::Big, rest)),
+        [b'<', 0, ..] => Some((ByteOrder::Little, bytes)),
+        [0, b'<', ..] => Some((ByteOrder::Big, bytes)),
+        _ => None,
+    }
+}
+
+/// Decodes UTF-16 input without a byte order mark.
+fn decode_utf16(bytes: &[u8], order: ByteOrder) -> Result<String> {
+    let malformed = || Error::invalid_value("malformed UTF-16 input");
+    let pairs = bytes.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(malformed());
+    }
+    let units = pairs.map(|pair| match order {
+        ByteOrder::Little => u16::from_le_bytes([pair[0], pair[1]]),
+        ByteOrder::Big => u16::from_be_bytes([pair[0], pair[1]]),
+    });
+    char::decode_utf16(units)
+        .collect::<std::result::Result<String, _>>()
+        .map_err(|_| malformed())
+}
+
+/// Decodes a document to UTF-8 according to its byte order mark or XML
+/// declaration.
+///
+/// UTF-8 input is borrowed; other encodings are transcoded.
+pub(crate) fn decode(bytes: &[u8]) -> Result<Cow<'_, str>> {
+    if let Some((order, rest)) = detect_utf16(bytes) {
+        return decode_utf16(rest, order).map(Cow::Owned);
+    }
+    match declared_encoding(bytes) {
+        Some(label) if !is_utf8_compatible(label) => transcode(bytes, label),
+        _ => std::str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(|_| Error::new(ErrorKind::InvalidUtf8)),
+    }
+}
+
+#[cfg(feature = "encoding")]
+fn transcode<'a>(bytes: &'a [u8], label: &str) -> Result<Cow<'a, str>> {
+    let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| Error::unsupported(format!("encoding '{}'", label)))?;
+    let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+    if had_errors {
+        return Err(Error::invalid_value(format!("malformed {} input", encoding.name())));
+    }
+    Ok(text)
+}
+
+/// Returns whether an encoding label names ISO-8859-1.
+#[cfg(not(feature = "encoding"))]
+fn is_latin1(label: &str) -> bool {
+    ["iso-8859-1", "iso8859-1", "iso_8859-1", "latin1", "l1"]
+        .iter()
+        .any(|l| label.eq_ignore_ascii_case(l))
+}
+
+#[cfg(not(feature = "encoding"))]
+fn transcode<'a>(bytes: &'a [u8], label: &str) -> Result<Cow<'a, str>> {
+    if is_latin1(label) {
+        // Every byte is the code point of the same value
+        return Ok(Cow::Owned(bytes.iter().map(|&b| b as char).collect()));
+    }
+    Err(Error::unsupported(format!(
+        "encoding '{}' (enable the `encoding` feature to transcode)",
+        label
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declared_encoding() {
+        assert_eq!(
+            declared_encoding(br#"<?xml version="1.0" encoding="ISO-8859-1"?><a/>"#),
+            Some("ISO-8859-1")
+        );
+        assert_eq!(declared_encoding(b"<?xml version='1.0' encoding = 'utf-8'?>"), Some("utf-8"));
+        assert_eq!(declared_encoding(br#"<?xml version="1.0"?><a/>"#), None);
+        assert_eq!(declared_encoding(b"<a/>"), None);
+    }
+
+    #[test]
+    fn test_decode_utf8_borrows() {
+        let bytes = br#"<?xml version="1.0" encoding="UTF-8"?><a/>"#;
+        assert!(matches!(decode(bytes).unwrap(), Cow::Borrowed(_)));
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn test_decode_latin1() {
+        let bytes = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>caf\xE9</a>";
+        assert!(decode(bytes).unwrap().ends_with("<a>caf\u{e9}</a>"));
+    }
+
+    #[cfg(not(feature = "encoding"))]
+    #[test]
+    fn test_decode_without_encoding_feature() {
+        let bytes = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>caf\xE9</a>";
+        assert!(decode(bytes).unwrap().ends_with("<a>caf\u{e9}</a>"));
+
+        let bytes = b"<?xml version=\"1.0\" encoding=\"Shift_JIS\"?><a/>";
+        let err = decode(bytes).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Unsupported(_)));
+    }
+
+    #[test]
+    fn test_decode_utf16() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><a>\u{e9}\u{1f600}</a>";
+        let le: Vec<u8> = xml.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = xml.encode_utf16().flat_map(u16::to_be_bytes).collect();
+
+        assert_eq!(decode(&[&[0xFF, 0xFE][..], &le].concat()).unwrap(), xml);
+        assert_eq!(decode(&[&[0xFE, 0xFF][..], &be].concat()).unwrap(), xml);
+        // Without a byte order mark the leading `<` gives the order away
+        assert_eq!(decode(&le).unwrap(), xml);
+        assert_eq!(decode(&be).unwrap(), xml);
+
+        assert!(decode(&[0xFF, 0xFE, b'<']).is_err());
+        assert!(decode(&[0xFF, 0xFE, 0x00, 0xD8]).is_err());
+    }
+}
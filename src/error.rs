@@ -11,6 +11,8 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub struct Error {
     kind: ErrorKind,
     position: Option<Position>,
+    span: Option<Box<Span>>,
+    path: Option<String>,
 }
 
 /// Position information for error reporting.
@@ -24,6 +26,15 @@ pub struct Position {
     pub offset: usize,
 }
 
+/// A range of the input, e.g. from an opening tag to its closing tag.
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    /// Start of the range.
+    pub start: Position,
+    /// End of the range (exclusive).
+    pub end: Position,
+}
+
 /// The kind of error that occurred.
 #[derive(Debug)]
 pub enum ErrorKind {
@@ -62,11 +73,30 @@ pub enum ErrorKind {
     Unsupported(String),
 }
 
+/// A coarse classification of errors, for deciding how to handle them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Reading or writing failed.
+    Io,
+    /// The input is not well-formed XML.
+    Syntax,
+    /// The input ended before the document was complete.
+    Eof,
+    /// The XML is well-formed but doesn't match the data type, or the data
+    /// can't be represented as XML.
+    Data,
+}
+
 impl Error {
     /// Creates a new error with the given kind.
     #[inline]
     pub fn new(kind: ErrorKind) -> Self {
-        Self { kind, position: None }
+        Self {
+            kind,
+            position: None,
+            span: None,
+            path: None,
+        }
     }
 
     /// Creates a new error with position information.
@@ -76,6 +106,21 @@ impl Error {
         self
     }
 
+    /// Attaches the range of input the error relates to.
+    #[inline]
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(Box::new(span));
+        self
+    }
+
+    /// Attaches the path of the element being deserialized, such as
+    /// `/Company/employee[2]/age`.
+    #[inline]
+    pub fn with_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
     /// Returns the error kind.
     #[inline]
     pub fn kind(&self) -> &ErrorKind {
@@ -88,6 +133,77 @@ impl Error {
         self.position
     }
 
+    /// Returns the range of input the error relates to, if known.
+    ///
+    /// For a mismatched closing tag this runs from the opening tag to the end
+    /// of the offending closing tag.
+    #[inline]
+    pub fn span(&self) -> Option<Span> {
+        self.span.as_deref().copied()
+    }
+
+    /// Returns the path of the element being deserialized when the error
+    /// occurred, if known.
+    #[inline]
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Returns the category of the error.
+    pub fn category(&self) -> ErrorCategory {
+        match self.kind {
+            ErrorKind::Io(_) => ErrorCategory::Io,
+            ErrorKind::UnexpectedEof => ErrorCategory::Eof,
+            ErrorKind::Syntax(_)
+            | ErrorKind::InvalidName(_)
+            | ErrorKind::UnclosedTag(_)
+            | ErrorKind::MismatchedTag { .. }
+            | ErrorKind::InvalidEscape(_)
+            | ErrorKind::InvalidUtf8 => ErrorCategory::Syntax,
+            ErrorKind::MissingAttribute(_)
+            | ErrorKind::UnexpectedElement(_)
+            | ErrorKind::UnexpectedAttribute(_)
+            | ErrorKind::InvalidValue(_)
+            | ErrorKind::Custom(_)
+            | ErrorKind::Unsupported(_) => ErrorCategory::Data,
+        }
+    }
+
+    /// Returns `true` if the input ended unexpectedly.
+    #[inline]
+    pub fn is_eof(&self) -> bool {
+        matches!(self.kind, ErrorKind::UnexpectedEof)
+    }
+
+    /// Returns `true` if the input is not well-formed XML, e.g. a syntax
+    /// error, a mismatched closing tag or an invalid escape.
+    ///
+    /// This covers every error in [`ErrorCategory::Syntax`], not only
+    /// [`ErrorKind::Syntax`]; match on [`kind`](Self::kind) to single that out.
+    #[inline]
+    pub fn is_syntax(&self) -> bool {
+        self.category() == ErrorCategory::Syntax
+    }
+
+    /// Returns `true` if this error was caused by an I/O failure.
+    #[inline]
+    pub fn is_io(&self) -> bool {
+        matches!(self.kind, ErrorKind::Io(_))
+    }
+
+    /// Returns `true` if well-formed XML didn't match the data type, e.g. a
+    /// missing field or a value that could not be parsed.
+    #[inline]
+    pub fn is_data(&self) -> bool {
+        self.category() == ErrorCategory::Data
+    }
+
+    /// Returns `true` if a value could not be converted to the requested type.
+    #[inline]
+    pub fn is_invalid_value(&self) -> bool {
+        matches!(self.kind, ErrorKind::InvalidValue(_))
+    }
+
     /// Creates an unexpected EOF error.
     #[inline]
     pub fn unexpected_eof() -> Self {
@@ -167,8 +283,13 @@ impl Display for Error {
             ErrorKind::Unsupported(msg) => write!(f, "unsupported: {}", msg),
         }?;
 
+        if let Some(ref path) = self.path {
+            write!(f, " at {}", path)?;
+        }
+
         if let Some(pos) = self.position {
-            write!(f, " at line {}, column {} (offset {})", pos.line, pos.column, pos.offset)?;
+            let sep = if self.path.is_some() { "," } else { " at" };
+            write!(f, "{} line {}, column {} (offset {})", sep, pos.line, pos.column, pos.offset)?;
         }
 
         Ok(())
@@ -222,6 +343,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_error_with_path() {
+        let err = Error::invalid_value("invalid digit found in string")
+            .with_path("/Company/employee[2]/age");
+        assert_eq!(err.path(), Some("/Company/employee[2]/age"));
+        assert_eq!(
+            err.to_string(),
+            "invalid value: invalid digit found in string at /Company/employee[2]/age"
+        );
+
+        let err = err.with_position(Position { line: 1, column: 2, offset: 1 });
+        assert!(err.to_string().ends_with("at /Company/employee[2]/age, line 1, column 2 (offset 1)"));
+    }
+
     #[test]
     fn test_mismatched_tag_error() {
         let err = Error::mismatched_tag("foo", "bar");
@@ -238,6 +373,70 @@ mod tests {
         assert!(err.to_string().contains("I/O error"));
     }
 
+    #[test]
+    fn test_predicates() {
+        let eof = Error::unexpected_eof();
+        assert!(eof.is_eof());
+        assert!(!eof.is_syntax());
+
+        let syntax = Error::syntax("expected '>'");
+        assert!(syntax.is_syntax());
+        assert!(!syntax.is_io());
+
+        // Every kind of malformed input counts, not just `ErrorKind::Syntax`
+        for err in [
+            Error::mismatched_tag("a", "b"),
+            Error::unclosed_tag("a"),
+            Error::invalid_escape("&bogus;"),
+            Error::new(ErrorKind::InvalidUtf8),
+        ] {
+            assert!(err.is_syntax());
+        }
+
+        let io = Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "boom"));
+        assert!(io.is_io());
+        assert!(!io.is_invalid_value());
+
+        let invalid = Error::invalid_value("not a number");
+        assert!(invalid.is_invalid_value());
+        assert!(!invalid.is_eof());
+    }
+
+    #[test]
+    fn test_category() {
+        let io = Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "boom"));
+        assert_eq!(io.category(), ErrorCategory::Io);
+        assert!(io.is_io() && !io.is_syntax() && !io.is_eof() && !io.is_data());
+
+        let eof = Error::unexpected_eof();
+        assert_eq!(eof.category(), ErrorCategory::Eof);
+        assert!(eof.is_eof() && !eof.is_io() && !eof.is_syntax() && !eof.is_data());
+
+        for err in [
+            Error::syntax("expected '>'"),
+            Error::mismatched_tag("a", "b"),
+            Error::unclosed_tag("a"),
+            Error::invalid_escape("&bogus;"),
+        ] {
+            assert_eq!(err.category(), ErrorCategory::Syntax);
+            assert!(err.is_syntax() && !err.is_data());
+        }
+
+        for err in [
+            Error::invalid_value("not a number"),
+            Error::custom("missing field `name`"),
+            Error::unsupported("map keys must be strings"),
+        ] {
+            assert_eq!(err.category(), ErrorCategory::Data);
+            assert!(err.is_data() && !err.is_syntax() && !err.is_io() && !err.is_eof());
+        }
+
+        let err = crate::from_str::<u32>("<n>x</n>").unwrap_err();
+        assert!(err.is_data());
+        let err = crate::from_str::<u32>("<n>1</m>").unwrap_err();
+        assert!(err.is_syntax());
+    }
+
     #[test]
     fn test_custom_error() {
         let err = Error::custom("something went wrong");
@@ -4,44 +4,150 @@
 //! and unescaping XML special characters.
 
 use memchr::memchr;
+use std::collections::HashMap;
+
+/// Controls which of the five XML special characters are escaped, and how
+/// control characters are handled.
+///
+/// The default escapes all of them. `<` and `&` must stay escaped for the
+/// output to be well-formed; the others can be relaxed for compact or
+/// HTML-compatible output. A `>` that would complete a `]]>` sequence is
+/// always escaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EscapeConfig {
+    /// Escape `<` as `&lt;`.
+    pub lt: bool,
+    /// Escape `>` as `&gt;`.
+    pub gt: bool,
+    /// Escape `&` as `&amp;`.
+    pub amp: bool,
+    /// Escape `"` as `&quot;`.
+    pub quot: bool,
+    /// Escape `'` as `&apos;`.
+    pub apos: bool,
+    /// Handling of control characters XML 1.0 does not allow in text.
+    pub control_chars: ControlChars,
+}
+
+/// How control characters other than tab, CR and LF are handled when escaping.
+///
+/// XML 1.0 allows them neither literally nor as character references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlChars {
+    /// Reject them with [`ErrorKind::InvalidValue`](crate::ErrorKind::InvalidValue).
+    ///
+    /// Honored by [`try_escape_with`], the serializer and
+    /// [`XmlWriter`](crate::XmlWriter); the infallible functions can't fail
+    /// and drop them instead, as with [`ControlChars::Strip`].
+    #[default]
+    Reject,
+    /// Drop them, so the output is well-formed XML 1.0 at the cost of losing
+    /// them.
+    Strip,
+    /// Write them as numeric character references, e.g. `&#x7;`, which only
+    /// XML 1.1 allows. U+0000 is allowed by neither and is dropped.
+    Escape,
+}
+
+/// Returns `true` for control bytes XML 1.0 does not allow in text.
+#[inline(always)]
+pub(crate) fn is_restricted_control(b: u8) -> bool {
+    b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r')
+}
+
+impl Default for EscapeConfig {
+    fn default() -> Self {
+        Self {
+            lt: true,
+            gt: true,
+            amp: true,
+            quot: true,
+            apos: true,
+            control_chars: ControlChars::default(),
+        }
+    }
+}
 
 /// Escapes XML special characters in a string.
 ///
 /// Returns a `Cow<str>` to avoid allocation when no escaping is needed.
 #[inline]
 pub fn escape(s: &str) -> std::borrow::Cow<'_, str> {
+    escape_with(s, &EscapeConfig::default())
+}
+
+/// Escapes XML special characters in a string according to `config`.
+///
+/// Returns a `Cow<str>` to avoid allocation when no escaping is needed.
+#[inline]
+pub fn escape_with<'a>(s: &'a str, config: &EscapeConfig) -> std::borrow::Cow<'a, str> {
     let bytes = s.as_bytes();
 
     // Fast path: scan for any character needing escape
-    let needs_escape = bytes.iter().any(|&b| matches!(b, b'<' | b'>' | b'&' | b'"' | b'\''));
+    let needs_escape = bytes
+        .iter()
+        .any(|&b| matches!(b, b'<' | b'>' | b'&' | b'"' | b'\'') || is_restricted_control(b));
 
     if !needs_escape {
         return std::borrow::Cow::Borrowed(s);
     }
 
     let mut result = String::with_capacity(s.len() + s.len() / 8);
-    escape_to_inner(bytes, &mut result);
+    escape_to_inner(bytes, &mut result, config);
     std::borrow::Cow::Owned(result)
 }
 
+/// Escapes XML special characters according to `config`, failing if the
+/// input contains a control character and `config.control_chars` is
+/// [`ControlChars::Reject`].
+#[inline]
+pub fn try_escape_with<'a>(
+    s: &'a str,
+    config: &EscapeConfig,
+) -> crate::Result<std::borrow::Cow<'a, str>> {
+    if config.control_chars == ControlChars::Reject {
+        reject_control_chars(s)?;
+    }
+    Ok(escape_with(s, config))
+}
+
+/// Fails if `s` contains a control character XML 1.0 does not allow.
+pub(crate) fn reject_control_chars(s: &str) -> crate::Result<()> {
+    match s.bytes().find(|&b| is_restricted_control(b)) {
+        Some(b) => Err(crate::Error::invalid_value(format!(
+            "control character U+{:04X} is not allowed in XML",
+            b
+        ))),
+        None => Ok(()),
+    }
+}
+
 /// Escapes XML special characters and appends to the given string.
 #[inline]
 pub fn escape_to(s: &str, out: &mut String) {
-    escape_to_inner(s.as_bytes(), out);
+    escape_to_inner(s.as_bytes(), out, &EscapeConfig::default());
+}
+
+/// Escapes XML special characters according to `config` and appends to the
+/// given string.
+#[inline]
+pub fn escape_to_with(s: &str, out: &mut String, config: &EscapeConfig) {
+    escape_to_inner(s.as_bytes(), out, config);
 }
 
 /// Internal escape implementation - simple byte-by-byte with batching.
 #[inline(always)]
-fn escape_to_inner(bytes: &[u8], out: &mut String) {
+fn escape_to_inner(bytes: &[u8], out: &mut String, config: &EscapeConfig) {
     let mut start = 0;
 
     for (i, &byte) in bytes.iter().enumerate() {
         let escaped = match byte {
-            b'<' => "&lt;",
-            b'>' => "&gt;",
-            b'&' => "&amp;",
-            b'"' => "&quot;",
-            b'\'' => "&apos;",
+            b'<' if config.lt => "&lt;",
+            b'>' if config.gt || bytes[..i].ends_with(b"]]") => "&gt;",
+            b'&' if config.amp => "&amp;",
+            b'"' if config.quot => "&quot;",
+            b'\'' if config.apos => "&apos;",
+            b if is_restricted_control(b) => "",
             _ => continue,
         };
 
@@ -50,7 +156,20 @@ fn escape_to_inner(bytes: &[u8], out: &mut String) {
             // SAFETY: Only escaping ASCII chars, so UTF-8 boundaries are preserved
             out.push_str(unsafe { std::str::from_utf8_unchecked(&bytes[start..i]) });
         }
-        out.push_str(escaped);
+        if escaped.is_empty() {
+            // Control character: a numeric character reference, or dropped
+            if config.control_chars == ControlChars::Escape && byte != 0 {
+                const HEX: &[u8; 16] = b"0123456789ABCDEF";
+                out.push_str("&#x");
+                if byte >= 0x10 {
+                    out.push(HEX[(byte >> 4) as usize] as char);
+                }
+                out.push(HEX[(byte & 0xF) as usize] as char);
+                out.push(';');
+            }
+        } else {
+            out.push_str(escaped);
+        }
         start = i + 1;
     }
 
@@ -71,6 +190,64 @@ pub fn escape_attr(s: &str) -> std::borrow::Cow<'_, str> {
 /// Returns a `Cow<str>` to avoid allocation when no unescaping is needed.
 #[inline]
 pub fn unescape(s: &str) -> Result<std::borrow::Cow<'_, str>, UnescapeError> {
+    unescape_resolving(s, Entities::default())
+}
+
+/// Unescapes XML entities in a string, resolving names not predefined by XML
+/// through the given entity map.
+///
+/// Replacement text is inserted verbatim; it is not scanned for further
+/// references, so values that refer to other entities must be expanded
+/// beforehand. [`XmlReader`](crate::reader::XmlReader) does this for entities
+/// declared in a DOCTYPE.
+#[inline]
+pub fn unescape_with<'a>(
+    s: &'a str,
+    entities: &HashMap<String, String>,
+) -> Result<std::borrow::Cow<'a, str>, UnescapeError> {
+    unescape_resolving(
+        s,
+        Entities {
+            custom: Some(entities),
+            html: false,
+            limit: None,
+        },
+    )
+}
+
+/// Unescapes XML entities in a string, also accepting HTML named entities
+/// such as `&nbsp;`, `&copy;` and `&mdash;`.
+#[cfg(feature = "html-entities")]
+#[inline]
+pub fn unescape_html(s: &str) -> Result<std::borrow::Cow<'_, str>, UnescapeError> {
+    unescape_resolving(
+        s,
+        Entities {
+            custom: None,
+            html: true,
+            limit: None,
+        },
+    )
+}
+
+/// Named entities recognized in addition to the five predefined by XML.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Entities<'a> {
+    /// Custom general entities, e.g. declared in a DTD.
+    pub(crate) custom: Option<&'a HashMap<String, String>>,
+    /// Whether HTML named entities are recognized.
+    pub(crate) html: bool,
+    /// Length the output may not exceed by expanding a custom entity; such a
+    /// reference is reported as an error instead.
+    pub(crate) limit: Option<usize>,
+}
+
+/// Unescapes a string using the given set of named entities.
+#[inline(always)]
+pub(crate) fn unescape_resolving<'a>(
+    s: &'a str,
+    entities: Entities<'_>,
+) -> Result<std::borrow::Cow<'a, str>, UnescapeError> {
     let bytes = s.as_bytes();
 
     // Fast path: check if any unescaping is needed using memchr
@@ -84,7 +261,7 @@ pub fn unescape(s: &str) -> Result<std::borrow::Cow<'_, str>, UnescapeError> {
                     std::str::from_utf8_unchecked(&bytes[..first_amp])
                 });
             }
-            unescape_from(bytes, first_amp, &mut result)?;
+            unescape_from(bytes, first_amp, &mut result, entities)?;
             Ok(std::borrow::Cow::Owned(result))
         }
     }
@@ -122,14 +299,19 @@ pub fn unescape_to(s: &str, out: &mut String) -> Result<(), UnescapeError> {
                     std::str::from_utf8_unchecked(&bytes[..first_amp])
                 });
             }
-            unescape_from(bytes, first_amp, out)
+            unescape_from(bytes, first_amp, out, Entities::default())
         }
     }
 }
 
 /// Internal unescape starting from a position known to have '&'.
 #[inline(always)]
-fn unescape_from(bytes: &[u8], start: usize, out: &mut String) -> Result<(), UnescapeError> {
+fn unescape_from(
+    bytes: &[u8],
+    start: usize,
+    out: &mut String,
+    entities: Entities<'_>,
+) -> Result<(), UnescapeError> {
     let mut i = start;
 
     while i < bytes.len() {
@@ -139,13 +321,32 @@ fn unescape_from(bytes: &[u8], start: usize, out: &mut String) -> Result<(), Une
 
             // Find semicolon using memchr for speed
             match memchr(b';', &bytes[i..]) {
-                Some(len) if len > 0 && len <= 10 => {
+                Some(len) if len > 0 && (len <= 10 || entities.custom.is_some()) => {
                     let entity = unsafe {
                         std::str::from_utf8_unchecked(&bytes[i..i + len])
                     };
 
-                    if let Some(c) = decode_entity_fast(entity) {
+                    let decoded = if let Some(c) = decode_entity_fast(entity) {
+                        out.push(c);
+                        true
+                    } else if let Some(c) = entities.html.then(|| decode_html_entity(entity)).flatten() {
                         out.push(c);
+                        true
+                    } else if let Some(value) = entities.custom.and_then(|m| m.get(entity)) {
+                        let len = out.len() + value.len();
+                        if matches!(entities.limit, Some(limit) if len > limit) {
+                            return Err(UnescapeError {
+                                entity: format!("&{};", entity),
+                                position: entity_start,
+                            });
+                        }
+                        out.push_str(value);
+                        true
+                    } else {
+                        false
+                    };
+
+                    if decoded {
                         i += len + 1;
 
                         // Find and append text until next &
@@ -230,6 +431,60 @@ fn decode_numeric_entity(entity: &str) -> Option<char> {
     char::from_u32(code)
 }
 
+/// Latin-1 supplement entity names, indexed by code point minus 0xA0.
+#[cfg(feature = "html-entities")]
+static HTML_LATIN1: [&str; 96] = [
+    "nbsp", "iexcl", "cent", "pound", "curren", "yen", "brvbar", "sect",
+    "uml", "copy", "ordf", "laquo", "not", "shy", "reg", "macr",
+    "deg", "plusmn", "sup2", "sup3", "acute", "micro", "para", "middot",
+    "cedil", "sup1", "ordm", "raquo", "frac14", "frac12", "frac34", "iquest",
+    "Agrave", "Aacute", "Acirc", "Atilde", "Auml", "Aring", "AElig", "Ccedil",
+    "Egrave", "Eacute", "Ecirc", "Euml", "Igrave", "Iacute", "Icirc", "Iuml",
+    "ETH", "Ntilde", "Ograve", "Oacute", "Ocirc", "Otilde", "Ouml", "times",
+    "Oslash", "Ugrave", "Uacute", "Ucirc", "Uuml", "Yacute", "THORN", "szlig",
+    "agrave", "aacute", "acirc", "atilde", "auml", "aring", "aelig", "ccedil",
+    "egrave", "eacute", "ecirc", "euml", "igrave", "iacute", "icirc", "iuml",
+    "eth", "ntilde", "ograve", "oacute", "ocirc", "otilde", "ouml", "divide",
+    "oslash", "ugrave", "uacute", "ucirc", "uuml", "yacute", "thorn", "yuml",
+];
+
+/// Commonly used HTML entities outside the Latin-1 supplement.
+#[cfg(feature = "html-entities")]
+static HTML_OTHER: [(&str, char); 48] = [
+    ("OElig", '\u{152}'), ("oelig", '\u{153}'), ("Scaron", '\u{160}'), ("scaron", '\u{161}'),
+    ("Yuml", '\u{178}'), ("fnof", '\u{192}'), ("circ", '\u{2c6}'), ("tilde", '\u{2dc}'),
+    ("ensp", '\u{2002}'), ("emsp", '\u{2003}'), ("thinsp", '\u{2009}'), ("zwnj", '\u{200c}'),
+    ("zwj", '\u{200d}'), ("lrm", '\u{200e}'), ("rlm", '\u{200f}'), ("ndash", '\u{2013}'),
+    ("mdash", '\u{2014}'), ("lsquo", '\u{2018}'), ("rsquo", '\u{2019}'), ("sbquo", '\u{201a}'),
+    ("ldquo", '\u{201c}'), ("rdquo", '\u{201d}'), ("bdquo", '\u{201e}'), ("dagger", '\u{2020}'),
+    ("Dagger", '\u{2021}'), ("bull", '\u{2022}'), ("hellip", '\u{2026}'), ("permil", '\u{2030}'),
+    ("prime", '\u{2032}'), ("Prime", '\u{2033}'), ("lsaquo", '\u{2039}'), ("rsaquo", '\u{203a}'),
+    ("euro", '\u{20ac}'), ("trade", '\u{2122}'), ("larr", '\u{2190}'), ("uarr", '\u{2191}'),
+    ("rarr", '\u{2192}'), ("darr", '\u{2193}'), ("harr", '\u{2194}'), ("minus", '\u{2212}'),
+    ("infin", '\u{221e}'), ("ne", '\u{2260}'), ("le", '\u{2264}'), ("ge", '\u{2265}'),
+    ("asymp", '\u{2248}'), ("spades", '\u{2660}'), ("hearts", '\u{2665}'), ("diams", '\u{2666}'),
+];
+
+/// Decodes an HTML named entity.
+#[cfg(feature = "html-entities")]
+#[inline]
+fn decode_html_entity(entity: &str) -> Option<char> {
+    if let Some(idx) = HTML_LATIN1.iter().position(|&name| name == entity) {
+        return char::from_u32(0xA0 + idx as u32);
+    }
+    HTML_OTHER
+        .iter()
+        .find(|(name, _)| *name == entity)
+        .map(|&(_, c)| c)
+}
+
+/// HTML entities are unavailable without the `html-entities` feature.
+#[cfg(not(feature = "html-entities"))]
+#[inline(always)]
+fn decode_html_entity(_entity: &str) -> Option<char> {
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -275,6 +530,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_escape_with_config() {
+        let config = EscapeConfig {
+            gt: false,
+            apos: false,
+            ..EscapeConfig::default()
+        };
+        assert_eq!(escape_with("a > b", &config), "a > b");
+        assert_eq!(escape_with("it's <b>", &config), "it's &lt;b>");
+        assert_eq!(escape_with("x]]>y", &config), "x]]&gt;y");
+        assert_eq!(escape_with("a > b", &EscapeConfig::default()), "a &gt; b");
+
+        let mut out = String::new();
+        escape_to_with("\"q\"", &mut out, &EscapeConfig { quot: false, ..EscapeConfig::default() });
+        assert_eq!(out, "\"q\"");
+    }
+
+    #[test]
+    fn test_escape_control_chars() {
+        // The infallible functions can't reject them, so they drop them
+        assert_eq!(escape("ding\u{0007}dong"), "dingdong");
+        assert_eq!(escape("\u{0}<\u{1B}"), "&lt;");
+        assert_eq!(escape("tab\tnew\nline\r"), "tab\tnew\nline\r");
+        let mut out = String::new();
+        escape_to("a\u{0007}b", &mut out);
+        assert_eq!(out, "ab");
+
+        let strip = EscapeConfig {
+            control_chars: ControlChars::Strip,
+            ..EscapeConfig::default()
+        };
+        assert_eq!(escape_with("ding\u{0007}dong", &strip), "dingdong");
+        assert_eq!(escape_with("\u{0}<\u{1B}", &strip), "&lt;");
+
+        let xml11 = EscapeConfig {
+            control_chars: ControlChars::Escape,
+            ..EscapeConfig::default()
+        };
+        assert_eq!(escape_with("ding\u{0007}dong", &xml11), "ding&#x7;dong");
+        assert_eq!(escape_with("\u{0}\u{1B}", &xml11), "&#x1B;");
+        assert_eq!(unescape(&escape_with("a\u{0007}b", &xml11)).unwrap(), "a\u{0007}b");
+
+        let reject = EscapeConfig::default();
+        let err = try_escape_with("ding\u{0007}dong", &reject).unwrap_err();
+        assert!(matches!(err.kind(), crate::ErrorKind::InvalidValue(_)));
+        assert_eq!(try_escape_with("a & b", &reject).unwrap(), "a &amp; b");
+        assert_eq!(try_escape_with("a\u{0007}b", &strip).unwrap(), "ab");
+    }
+
     #[test]
     fn test_unescape_no_entities() {
         let s = "Hello, World!";
@@ -352,6 +656,30 @@ mod tests {
         assert_eq!(out, "&lt;test&gt;");
     }
 
+    #[test]
+    fn test_unescape_with_entities() {
+        let mut entities = HashMap::new();
+        entities.insert("company".to_string(), "ACME".to_string());
+        entities.insert("a_rather_long_entity_name".to_string(), "long".to_string());
+
+        assert_eq!(unescape_with("&company; &amp; co", &entities).unwrap(), "ACME & co");
+        assert_eq!(unescape_with("&a_rather_long_entity_name;", &entities).unwrap(), "long");
+        assert!(unescape_with("&unknown;", &entities).is_err());
+        assert!(unescape("&company;").is_err());
+    }
+
+    #[cfg(feature = "html-entities")]
+    #[test]
+    fn test_unescape_html_entities() {
+        assert_eq!(unescape_html("&nbsp;").unwrap(), "\u{a0}");
+        assert_eq!(unescape_html("&copy; 2025").unwrap(), "\u{a9} 2025");
+        assert_eq!(unescape_html("a &mdash; b").unwrap(), "a \u{2014} b");
+        assert_eq!(unescape_html("caf&eacute;").unwrap(), "caf\u{e9}");
+        assert_eq!(unescape_html("&yuml;&euro;&amp;").unwrap(), "\u{ff}\u{20ac}&");
+        assert!(unescape_html("&bogus;").is_err());
+        assert!(unescape("&nbsp;").is_err());
+    }
+
     #[test]
     fn test_roundtrip() {
         let original = "<div class=\"foo\">Hello & goodbye</div>";
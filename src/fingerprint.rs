@@ -0,0 +1,133 @@
+//! Hashing the logical content of a document.
+
+use crate::error::Result;
+use crate::reader::{Attribute, XmlEvent, XmlReader};
+
+/// Computes a hash of a document's logical content, for caching and change
+/// detection.
+///
+/// The hash covers element names, attribute names and values, and text. It
+/// ignores whitespace between elements and around text, attribute order, the
+/// difference between `<a/>` and `<a></a>`, text split into CDATA sections,
+/// comments and the XML declaration. The hash function is FNV-1a, so the
+/// result is the same across runs and platforms.
+///
+/// # Example
+///
+/// ```
+/// use serde_xml::fingerprint;
+///
+/// let a = fingerprint(r#"<a x="1" y="2"><b/></a>"#).unwrap();
+/// let b = fingerprint("<a y=\"2\" x=\"1\">\n  <b></b>\n</a>").unwrap();
+/// assert_eq!(a, b);
+/// assert_ne!(a, fingerprint(r#"<a x="1" y="3"><b/></a>"#).unwrap());
+/// ```
+pub fn fingerprint(s: &str) -> Result<u64> {
+    let mut reader = XmlReader::from_str(s);
+    let mut hasher = Fnv1a::new();
+    let mut text = String::new();
+
+    loop {
+        let event = reader.next_event()?;
+        if let XmlEvent::Text(t) | XmlEvent::CData(t) = &event {
+            text.push_str(t);
+            continue;
+        }
+        if !text.is_empty() {
+            hasher.write_field(b'T', &text);
+            text.clear();
+        }
+        match event {
+            XmlEvent::StartElement { name, attributes } => {
+                hasher.write_start(&name, attributes);
+            }
+            XmlEvent::EmptyElement { name, attributes } => {
+                hasher.write_start(&name, attributes);
+                hasher.write_field(b'E', "");
+            }
+            XmlEvent::EndElement { .. } => hasher.write_field(b'E', ""),
+            XmlEvent::ProcessingInstruction { target, data } => {
+                hasher.write_field(b'P', &target);
+                hasher.write_field(b'D', data.as_deref().unwrap_or(""));
+            }
+            XmlEvent::Eof => return Ok(hasher.finish()),
+            _ => {}
+        }
+    }
+}
+
+/// The 64-bit FNV-1a hash.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// Writes a tagged, length-prefixed field, so fields can't run together.
+    fn write_field(&mut self, tag: u8, value: &str) {
+        self.write(&[tag]);
+        self.write(&(value.len() as u64).to_le_bytes());
+        self.write(value.as_bytes());
+    }
+
+    /// Writes an element's name and its attributes, sorted by name.
+    fn write_start(&mut self, name: &str, mut attributes: Vec<Attribute<'_>>) {
+        self.write_field(b'S', name);
+        attributes.sort_by(|a, b| a.name.cmp(&b.name));
+        for attr in &attributes {
+            self.write_field(b'A', &attr.name);
+            self.write_field(b'V', &attr.value);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equal_documents() {
+        let a = r#"<?xml version="1.0"?>
+            <order id="7" status="new">
+                <!-- items -->
+                <item sku="a" qty="1"/>
+                <note>  rush  </note>
+            </order>"#;
+        let b = r#"<order status="new" id="7"><item qty="1" sku="a"></item><note>rush</note></order>"#;
+        let c = r#"<order status="new" id="7"><item qty="1" sku="a"/><note><![CDATA[ru]]>sh</note></order>"#;
+        assert_eq!(fingerprint(a).unwrap(), fingerprint(b).unwrap());
+        assert_eq!(fingerprint(b).unwrap(), fingerprint(c).unwrap());
+    }
+
+    #[test]
+    fn test_changed_documents() {
+        let base = fingerprint(r#"<order id="7"><item>a</item><note>x</note></order>"#).unwrap();
+        for changed in [
+            r#"<order id="8"><item>a</item><note>x</note></order>"#,
+            r#"<order id="7"><item>b</item><note>x</note></order>"#,
+            r#"<order ID="7"><item>a</item><note>x</note></order>"#,
+            r#"<order id="7"><item>a<note>x</note></item></order>"#,
+            r#"<order id="7"><item>ax</item><note/></order>"#,
+            r#"<order id="7"><note>x</note><item>a</item></order>"#,
+        ] {
+            assert_ne!(fingerprint(changed).unwrap(), base, "{}", changed);
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_error() {
+        assert!(fingerprint("<a><b></a>").is_err());
+    }
+}
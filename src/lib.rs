@@ -111,19 +111,48 @@
 #![warn(rust_2018_idioms)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
+mod attributes;
 pub mod de;
+mod encoding;
 pub mod error;
 pub mod escape;
+mod fingerprint;
+mod outer_xml;
+mod owned;
+mod present;
+mod raw_node;
+mod raw_number;
 pub mod reader;
 pub mod ser;
+pub mod serde_duration;
+mod token_list;
+pub mod value;
 pub mod writer;
 
 // Re-export main types and functions
-pub use de::{from_bytes, from_str, Deserializer};
-pub use error::{Error, ErrorKind, Position, Result};
-pub use escape::{escape, unescape};
-pub use reader::{Attribute, XmlEvent, XmlReader};
-pub use ser::{to_string, to_string_with_root, to_vec, to_writer, Serializer};
+pub use attributes::Attributes;
+pub use de::{
+    from_bytes, from_str, from_str_seq, from_str_with_options, BoolMode, DeserializeOptions,
+    Deserializer,
+};
+pub use error::{Error, ErrorCategory, ErrorKind, Position, Result, Span};
+pub use escape::{escape, escape_with, unescape, EscapeConfig};
+pub use fingerprint::fingerprint;
+pub use outer_xml::OuterXml;
+pub use present::Present;
+pub use raw_node::RawNode;
+pub use raw_number::RawNumber;
+pub use reader::{
+    parse_pi_pseudo_attrs, Attribute, XmlEvent, XmlEventKind, XmlReader, XmlReaderBuilder,
+};
+pub use ser::{
+    to_string, to_string_canonical, to_string_pretty, to_string_with_options,
+    to_string_with_root, to_vec, to_vec_pretty, to_vec_with_options, to_writer,
+    to_writer_with_options, EmptyStyle, EnumRepr, FloatFormat, NoneMode, SerializeHook,
+    SerializeOptions, Serializer, SharedHook, TupleRepr,
+};
+pub use token_list::TokenList;
+pub use value::{merge, Element, Value};
 pub use writer::{IndentConfig, XmlWriter};
 
 #[cfg(test)]
@@ -210,6 +239,48 @@ mod tests {
         assert_eq!(with_value, parsed);
     }
 
+    #[test]
+    fn test_roundtrip_enum_rename_all() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename_all = "snake_case")]
+        enum Kind {
+            CamelCase,
+            NewType(String),
+        }
+
+        for original in [Kind::CamelCase, Kind::NewType("value".to_string())] {
+            let xml = to_string(&original).unwrap();
+            let parsed: Kind = from_str(&xml).unwrap();
+            assert_eq!(original, parsed);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_none_modes() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Config {
+            name: String,
+            value: Option<String>,
+            count: Option<u32>,
+        }
+
+        let original = Config {
+            name: "test".to_string(),
+            value: None,
+            count: Some(3),
+        };
+
+        for mode in [NoneMode::Omit, NoneMode::EmptyElement, NoneMode::XsiNil] {
+            let mut ser = Serializer::new().with_none_as(mode);
+            original.serialize(&mut ser).unwrap();
+            let xml = ser.into_string();
+
+            let mut de = Deserializer::from_str(&xml).with_none_as(mode);
+            let parsed = Config::deserialize(&mut de).unwrap();
+            assert_eq!(original, parsed, "mode {:?}: {}", mode, xml);
+        }
+    }
+
     #[test]
     fn test_roundtrip_escaped() {
         #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -287,6 +358,66 @@ mod tests {
         assert!(err.to_string().contains("mismatched") || err.to_string().contains("wrong"));
     }
 
+    #[test]
+    fn test_roundtrip_mixed_text_and_children() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Child {
+            #[serde(rename = "$value")]
+            value: String,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Node {
+            #[serde(rename = "@id")]
+            id: u32,
+            #[serde(rename = "$value")]
+            text: String,
+            child: Child,
+        }
+
+        let xml = r#"<node id="1">some text<child>x</child></node>"#;
+        let parsed: Node = from_str(xml).unwrap();
+        assert_eq!(parsed.id, 1);
+        assert_eq!(parsed.text, "some text");
+        assert_eq!(parsed.child.value, "x");
+
+        let serialized = to_string(&parsed).unwrap();
+        assert_eq!(serialized, r#"<Node id="1">some text<child>x</child></Node>"#);
+        let reparsed: Node = from_str(&serialized).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    fn test_roundtrip_leading_text_and_repeated_children() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Section {
+            #[serde(rename = "$value")]
+            intro: String,
+            item: Vec<String>,
+        }
+
+        let xml = "<section>Intro text<item>a</item><item>b</item></section>";
+        let section: Section = from_str(xml).unwrap();
+        assert_eq!(section.intro, "Intro text");
+        assert_eq!(section.item, ["a", "b"]);
+
+        let serialized = to_string(&section).unwrap();
+        assert_eq!(serialized, "<Section>Intro text<item>a</item><item>b</item></Section>");
+        assert_eq!(from_str::<Section>(&serialized).unwrap(), section);
+
+        // Only the leading text is taken when asked
+        let xml = "<section>Intro text<item>a</item><item>b</item>Outro</section>";
+        let mut de = Deserializer::from_str(xml).leading_text_as_value(true);
+        let section = Section::deserialize(&mut de).unwrap();
+        assert_eq!(section.intro, "Intro text");
+        assert_eq!(section.item, ["a", "b"]);
+        let reparsed: Section = from_str(&to_string(&section).unwrap()).unwrap();
+        assert_eq!(reparsed, section);
+
+        let section: Section = from_str(xml).unwrap();
+        assert_eq!(section.intro, "Intro textOutro");
+    }
+
     #[test]
     fn test_complex_xml() {
         #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -322,4 +453,735 @@ mod tests {
         let parsed: Library = from_str(&xml).unwrap();
         assert_eq!(original, parsed);
     }
+
+    #[test]
+    fn test_roundtrip_internally_tagged_enum() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(tag = "@type", rename_all = "lowercase")]
+        enum Shape {
+            Circle {
+                #[serde(rename = "@radius")]
+                radius: String,
+            },
+            Square {
+                side: String,
+            },
+            Dot,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Drawing {
+            shape: Shape,
+            name: String,
+        }
+
+        let circle = Drawing {
+            shape: Shape::Circle { radius: "3".to_string() },
+            name: "c".to_string(),
+        };
+        let xml = to_string(&circle).unwrap();
+        assert!(xml.contains(r#"<shape type="circle" radius="3"/>"#));
+        assert_eq!(from_str::<Drawing>(&xml).unwrap(), circle);
+
+        for shape in [Shape::Square { side: "2".to_string() }, Shape::Dot] {
+            let xml = to_string(&shape).unwrap();
+            assert_eq!(from_str::<Shape>(&xml).unwrap(), shape);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_internally_tagged_enum_child_tag() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(tag = "kind")]
+        enum Event {
+            Click { x: String, y: String },
+            Key { code: String },
+        }
+
+        let event = Event::Click { x: "1".to_string(), y: "2".to_string() };
+        let xml = to_string(&event).unwrap();
+        assert!(xml.contains("<kind>Click</kind>"));
+        assert_eq!(from_str::<Event>(&xml).unwrap(), event);
+
+        let event: Event = from_str("<Event><code>13</code><kind>Key</kind></Event>").unwrap();
+        assert_eq!(event, Event::Key { code: "13".to_string() });
+    }
+
+    #[test]
+    fn test_roundtrip_flattened_attribute_structs() {
+        // Flattened fields arrive as text, so numbers are parsed by hand
+        fn parse<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+            T: std::str::FromStr,
+            T::Err: std::fmt::Display,
+        {
+            let text = String::deserialize(deserializer)?;
+            text.parse().map_err(serde::de::Error::custom)
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Point {
+            #[serde(rename = "@x", deserialize_with = "parse")]
+            x: i32,
+            #[serde(rename = "@y", deserialize_with = "parse")]
+            y: i32,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Size {
+            #[serde(rename = "@w", deserialize_with = "parse")]
+            w: u32,
+            #[serde(rename = "@h", deserialize_with = "parse")]
+            h: u32,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Rect {
+            #[serde(flatten)]
+            pos: Point,
+            #[serde(flatten)]
+            size: Size,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Drawing {
+            rect: Vec<Rect>,
+        }
+
+        let drawing = Drawing {
+            rect: vec![
+                Rect { pos: Point { x: 0, y: -5 }, size: Size { w: 10, h: 20 } },
+                Rect { pos: Point { x: 3, y: 4 }, size: Size { w: 1, h: 2 } },
+            ],
+        };
+        let xml = to_string(&drawing).unwrap();
+        assert_eq!(
+            xml,
+            concat!(
+                r#"<Drawing><rect x="0" y="-5" w="10" h="20"/>"#,
+                r#"<rect x="3" y="4" w="1" h="2"/></Drawing>"#
+            )
+        );
+        assert_eq!(from_str::<Drawing>(&xml).unwrap(), drawing);
+
+        let xml = r#"<rect h="20" w="10" y="-5" x="0"/>"#;
+        let rect: Rect = from_str(xml).unwrap();
+        assert_eq!(rect.pos, Point { x: 0, y: -5 });
+        assert_eq!(rect.size, Size { w: 10, h: 20 });
+        let xml = to_string_with_root(&rect, "rect").unwrap();
+        assert_eq!(xml, r#"<rect x="0" y="-5" w="10" h="20"/>"#);
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Font {
+            #[serde(rename = "@family")]
+            family: String,
+            #[serde(rename = "@weight")]
+            weight: String,
+            #[serde(rename = "@code")]
+            code: String,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Label {
+            #[serde(flatten)]
+            font: Font,
+            text: String,
+        }
+
+        let xml = r#"<Label weight="bold" family="serif" code="007"><text>Hi</text></Label>"#;
+        let label: Label = from_str(xml).unwrap();
+        assert_eq!((label.font.family.as_str(), label.font.weight.as_str()), ("serif", "bold"));
+        assert_eq!(label.font.code, "007");
+        assert_eq!(label.text, "Hi");
+
+        // Flattened strings and catch-all maps keep numeric text as text
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Ids {
+            #[serde(rename = "@id")]
+            id: String,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Extra {
+            name: String,
+            #[serde(flatten)]
+            ids: Ids,
+            #[serde(flatten)]
+            rest: std::collections::HashMap<String, String>,
+        }
+
+        let xml = r#"<Extra id="10" a="1" b="true"><name>n</name></Extra>"#;
+        let extra: Extra = from_str(xml).unwrap();
+        assert_eq!(extra.ids.id, "10");
+        assert_eq!(extra.rest.get("@a").map(String::as_str), Some("1"));
+        assert_eq!(extra.rest.get("@b").map(String::as_str), Some("true"));
+        assert_eq!(extra.name, "n");
+    }
+
+    #[test]
+    fn test_roundtrip_adjacently_tagged_enum() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(tag = "t", content = "c")]
+        enum Shape {
+            Circle(f64),
+            Rect { width: u32, height: u32 },
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Drawing {
+            #[serde(rename = "@id")]
+            id: u32,
+            shapes: Vec<Shape>,
+        }
+
+        let rect = Shape::Rect { width: 3, height: 4 };
+        let xml = to_string(&rect).unwrap();
+        assert_eq!(xml, "<Shape><t>Rect</t><c><width>3</width><height>4</height></c></Shape>");
+        assert_eq!(from_str::<Shape>(&xml).unwrap(), rect);
+
+        let drawing = Drawing { id: 1, shapes: vec![Shape::Circle(1.5), rect] };
+        let xml = to_string(&drawing).unwrap();
+        assert_eq!(from_str::<Drawing>(&xml).unwrap(), drawing);
+    }
+
+    #[test]
+    fn test_roundtrip_map_of_structs() {
+        use std::collections::HashMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Address {
+            #[serde(rename = "@zip")]
+            zip: String,
+            city: String,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Person {
+            name: String,
+            addresses: HashMap<String, Address>,
+        }
+
+        let mut addresses = HashMap::new();
+        addresses.insert(
+            "home".to_string(),
+            Address { zip: "10001".to_string(), city: "New York".to_string() },
+        );
+        addresses.insert(
+            "work".to_string(),
+            Address { zip: "94105".to_string(), city: "San Francisco".to_string() },
+        );
+
+        let xml = to_string(&addresses).unwrap();
+        assert!(xml.contains(r#"<home zip="10001"><city>New York</city></home>"#));
+        assert!(xml.contains(r#"<work zip="94105"><city>San Francisco</city></work>"#));
+        let parsed: HashMap<String, Address> = from_str(&xml).unwrap();
+        assert_eq!(parsed, addresses);
+
+        let person = Person { name: "Alice".to_string(), addresses };
+        let xml = to_string(&person).unwrap();
+        assert!(xml.contains("<addresses><"));
+        assert!(!xml.contains("<map>"));
+        let parsed: Person = from_str(&xml).unwrap();
+        assert_eq!(parsed, person);
+    }
+
+    #[test]
+    fn test_roundtrip_char_attributes() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Csv {
+            #[serde(rename = "@sep")]
+            sep: char,
+            #[serde(rename = "@currency")]
+            currency: char,
+            #[serde(rename = "@quote")]
+            quote: char,
+        }
+
+        let original = Csv { sep: ',', currency: '€', quote: '"' };
+        let xml = to_string(&original).unwrap();
+        assert!(xml.contains(r#"sep=",""#));
+        assert!(xml.contains(r#"currency="€""#));
+        assert!(xml.contains(r#"quote="&quot;""#));
+
+        let parsed: Csv = from_str(&xml).unwrap();
+        assert_eq!(parsed, original);
+    }
+
+    #[test]
+    fn test_roundtrip_unit_and_struct_variants() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum State {
+            Idle,
+            Running { pid: u32 },
+            Named(String),
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Machine {
+            state: Vec<State>,
+            uptime: u32,
+        }
+
+        let machine = Machine {
+            state: vec![
+                State::Idle,
+                State::Running { pid: 5 },
+                State::Named("web".to_string()),
+                State::Idle,
+            ],
+            uptime: 7,
+        };
+        let xml = to_string(&machine).unwrap();
+        assert!(xml.contains("<state><Running><pid>5</pid></Running></state>"));
+        assert!(xml.contains("<state><Named>web</Named></state>"));
+        let parsed: Machine = from_str(&xml).unwrap();
+        assert_eq!(parsed, machine);
+
+        // Unit variants may also be written as empty elements
+        let xml = "<Machine><state><Idle/></state><state><Running><pid>5</pid></Running></state>\
+                   <state><Idle></Idle></state><uptime>7</uptime></Machine>";
+        let parsed: Machine = from_str(xml).unwrap();
+        assert_eq!(parsed.state, vec![State::Idle, State::Running { pid: 5 }, State::Idle]);
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Children {
+            #[serde(rename = "$value")]
+            states: Vec<State>,
+        }
+
+        let xml = "<Children><Idle/><Running><pid>6</pid></Running><Idle/></Children>";
+        let parsed: Children = from_str(xml).unwrap();
+        assert_eq!(parsed.states, vec![State::Idle, State::Running { pid: 6 }, State::Idle]);
+    }
+
+    #[test]
+    fn test_roundtrip_tuples() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Point(i32, i32);
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Shape {
+            Line(Point, Point),
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Drawing {
+            origin: Point,
+            label: (String, u32),
+            shape: Shape,
+        }
+
+        let drawing = Drawing {
+            origin: Point(0, 1),
+            label: ("l".to_string(), 2),
+            shape: Shape::Line(Point(1, 2), Point(3, 4)),
+        };
+        let xml = to_string(&drawing).unwrap();
+        assert!(xml.contains("<origin><item>0</item><item>1</item></origin>"));
+        assert!(xml.contains("<label>l</label><label>2</label>"));
+        let parsed: Drawing = from_str(&xml).unwrap();
+        assert_eq!(parsed, drawing);
+
+        let mut ser = Serializer::new().with_tuple_repr(TupleRepr::Items);
+        drawing.serialize(&mut ser).unwrap();
+        let xml = ser.into_string();
+        assert!(xml.contains("<label><item>l</item><item>2</item></label>"));
+        assert_eq!(from_str::<Drawing>(&xml).unwrap(), drawing);
+    }
+
+    #[test]
+    fn test_roundtrip_typed_text_with_attributes() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Temp {
+            #[serde(rename = "@scale")]
+            scale: String,
+            #[serde(rename = "$value")]
+            degrees: f64,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Flag {
+            #[serde(rename = "@name")]
+            name: String,
+            #[serde(rename = "$value")]
+            enabled: bool,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Reading {
+            temp: Temp,
+            flag: Flag,
+        }
+
+        let temp = Temp { scale: "C".to_string(), degrees: 37.5 };
+        let xml = to_string(&temp).unwrap();
+        assert_eq!(xml, r#"<Temp scale="C">37.5</Temp>"#);
+        assert_eq!(from_str::<Temp>(&xml).unwrap(), temp);
+
+        let reading = Reading { temp, flag: Flag { name: "alarm".to_string(), enabled: true } };
+        let xml = to_string(&reading).unwrap();
+        assert_eq!(
+            xml,
+            r#"<Reading><temp scale="C">37.5</temp><flag name="alarm">true</flag></Reading>"#
+        );
+        assert_eq!(from_str::<Reading>(&xml).unwrap(), reading);
+
+        // Serializer options apply to the text as well
+        let mut ser = Serializer::new().with_float_format(FloatFormat::Fixed(2));
+        reading.serialize(&mut ser).unwrap();
+        let xml = ser.into_string();
+        assert!(xml.contains(r#"<temp scale="C">37.50</temp>"#));
+        assert_eq!(from_str::<Reading>(&xml).unwrap(), reading);
+    }
+
+    #[test]
+    fn test_roundtrip_rename_all_with_attributes() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename_all = "camelCase")]
+        struct User {
+            #[serde(rename = "@userId")]
+            user_id: u32,
+            #[serde(rename = "@is_admin")]
+            is_admin: bool,
+            display_name: String,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename_all = "kebab-case")]
+        struct Team {
+            #[serde(rename = "@teamName")]
+            team_name: String,
+            team_lead: User,
+        }
+
+        let team = Team {
+            team_name: "core".to_string(),
+            team_lead: User { user_id: 1, is_admin: true, display_name: "Ann".to_string() },
+        };
+        let xml = to_string(&team).unwrap();
+        assert_eq!(
+            xml,
+            r#"<Team teamName="core"><team-lead userId="1" is_admin="true"><displayName>Ann</displayName></team-lead></Team>"#
+        );
+        assert_eq!(from_str::<Team>(&xml).unwrap(), team);
+    }
+
+    #[test]
+    fn test_roundtrip_rename_all_styles() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename_all = "PascalCase")]
+        struct Pascal {
+            first_name: String,
+            item_count: u32,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename_all = "camelCase")]
+        struct Camel {
+            first_name: String,
+            item_count: u32,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename_all = "snake_case")]
+        struct Snake {
+            first_name: String,
+            item_count: u32,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename_all = "kebab-case")]
+        struct Kebab {
+            first_name: String,
+            item_count: u32,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+        struct Screaming {
+            first_name: String,
+            item_count: u32,
+        }
+
+        let pascal = Pascal { first_name: "Ann".to_string(), item_count: 2 };
+        let xml = to_string(&pascal).unwrap();
+        assert_eq!(xml, "<Pascal><FirstName>Ann</FirstName><ItemCount>2</ItemCount></Pascal>");
+        assert_eq!(from_str::<Pascal>(&xml).unwrap(), pascal);
+
+        let camel = Camel { first_name: "Ann".to_string(), item_count: 2 };
+        let xml = to_string(&camel).unwrap();
+        assert_eq!(xml, "<Camel><firstName>Ann</firstName><itemCount>2</itemCount></Camel>");
+        assert_eq!(from_str::<Camel>(&xml).unwrap(), camel);
+
+        let snake = Snake { first_name: "Ann".to_string(), item_count: 2 };
+        let xml = to_string(&snake).unwrap();
+        assert_eq!(xml, "<Snake><first_name>Ann</first_name><item_count>2</item_count></Snake>");
+        assert_eq!(from_str::<Snake>(&xml).unwrap(), snake);
+
+        let kebab = Kebab { first_name: "Ann".to_string(), item_count: 2 };
+        let xml = to_string(&kebab).unwrap();
+        assert_eq!(xml, "<Kebab><first-name>Ann</first-name><item-count>2</item-count></Kebab>");
+        assert_eq!(from_str::<Kebab>(&xml).unwrap(), kebab);
+
+        let screaming = Screaming { first_name: "Ann".to_string(), item_count: 2 };
+        let xml = to_string(&screaming).unwrap();
+        assert_eq!(
+            xml,
+            "<Screaming><FIRST_NAME>Ann</FIRST_NAME><ITEM_COUNT>2</ITEM_COUNT></Screaming>"
+        );
+        assert_eq!(from_str::<Screaming>(&xml).unwrap(), screaming);
+
+        // Names written in another style don't match
+        let xml = "<Camel><first_name>Ann</first_name><item_count>2</item_count></Camel>";
+        assert!(from_str::<Camel>(xml).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_enum_attribute() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Kind {
+            Active,
+            Retired,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Thing {
+            #[serde(rename = "@kind")]
+            kind: Kind,
+            name: String,
+        }
+
+        let cases = [
+            (Kind::Active, r#"<Thing kind="Active"><name>a</name></Thing>"#),
+            (Kind::Retired, r#"<Thing kind="Retired"><name>a</name></Thing>"#),
+        ];
+        for (kind, expected) in cases {
+            let thing = Thing { kind, name: "a".to_string() };
+            let xml = to_string(&thing).unwrap();
+            assert_eq!(xml, expected);
+            assert_eq!(from_str::<Thing>(&xml).unwrap(), thing);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_present() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Order {
+            id: u32,
+            note: Present<Option<String>>,
+            discount: Option<Present<Option<u32>>>,
+        }
+
+        let cases = [
+            (
+                Order { id: 1, note: Present(None), discount: None },
+                "<Order><id>1</id><note/></Order>",
+            ),
+            (
+                Order { id: 2, note: Present(Some("gift".to_string())), discount: Some(Present(None)) },
+                "<Order><id>2</id><note>gift</note><discount/></Order>",
+            ),
+            (
+                Order { id: 3, note: Present(None), discount: Some(Present(Some(5))) },
+                "<Order><id>3</id><note/><discount>5</discount></Order>",
+            ),
+        ];
+        for (order, expected) in cases {
+            let xml = to_string(&order).unwrap();
+            assert_eq!(xml, expected);
+            assert_eq!(from_str::<Order>(&xml).unwrap(), order);
+        }
+
+        // Other `None` fields still follow the serializer's mode
+        #[derive(Serialize)]
+        struct Mixed {
+            a: Option<u32>,
+            b: Present<Option<u32>>,
+        }
+        let mut ser = Serializer::new().with_none_as(NoneMode::XsiNil);
+        Mixed { a: None, b: Present(None) }.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            concat!(
+                r#"<Mixed xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+                r#"<a xsi:nil="true"/><b/></Mixed>"#,
+            )
+        );
+
+        // Only the wrapped field itself is affected, not `None`s nested in it
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Addr {
+            line1: String,
+            line2: Option<String>,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Letter {
+            to: Present<Option<Addr>>,
+            from: Present<Addr>,
+        }
+
+        let addr = || Addr { line1: "x".to_string(), line2: None };
+        let letter = Letter { to: Present(Some(addr())), from: Present(addr()) };
+        let xml = to_string(&letter).unwrap();
+        assert_eq!(
+            xml,
+            "<Letter><to><line1>x</line1></to><from><line1>x</line1></from></Letter>"
+        );
+        assert_eq!(from_str::<Letter>(&xml).unwrap(), letter);
+
+        let xml = "<Letter><to><line1>x</line1><line2/></to><from><line1>x</line1></from></Letter>";
+        let letter: Letter = from_str(xml).unwrap();
+        assert_eq!(letter.to.0.unwrap().line2.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_roundtrip_xsi_type() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Shape {
+            Circle { radius: f64 },
+            Square { side: f64 },
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Drawing {
+            shape: Shape,
+            shapes: Vec<Shape>,
+        }
+
+        let drawing = Drawing {
+            shape: Shape::Circle { radius: 1.5 },
+            shapes: vec![Shape::Square { side: 2.0 }, Shape::Circle { radius: 0.5 }],
+        };
+        let mut ser = Serializer::new().with_enum_repr(EnumRepr::XsiType);
+        drawing.serialize(&mut ser).unwrap();
+        let xml = ser.into_string();
+        assert_eq!(
+            xml,
+            concat!(
+                r#"<Drawing xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+                r#"<shape xsi:type="Circle"><radius>1.5</radius></shape>"#,
+                r#"<shapes xsi:type="Square"><side>2.0</side></shapes>"#,
+                r#"<shapes xsi:type="Circle"><radius>0.5</radius></shapes></Drawing>"#,
+            )
+        );
+        assert_eq!(from_str::<Drawing>(&xml).unwrap(), drawing);
+
+        let mut ser = Serializer::new().with_enum_repr(EnumRepr::XsiType);
+        Shape::Square { side: 1.0 }.serialize(&mut ser).unwrap();
+        let xml = ser.into_string();
+        assert_eq!(
+            xml,
+            concat!(
+                r#"<Shape xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" "#,
+                r#"xsi:type="Square"><side>1.0</side></Shape>"#,
+            )
+        );
+        assert_eq!(from_str::<Shape>(&xml).unwrap(), Shape::Square { side: 1.0 });
+    }
+
+    #[test]
+    fn test_roundtrip_token_list() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Tag {
+            #[serde(rename = "@class")]
+            class: TokenList,
+            #[serde(rename = "@id")]
+            id: String,
+            tokens: TokenList,
+        }
+
+        let xml = r#"<Tag class="a b c" id="s"><tokens> x
+            y </tokens></Tag>"#;
+        let span: Tag = from_str(xml).unwrap();
+        assert_eq!(span.class.0, ["a", "b", "c"]);
+        assert_eq!(span.tokens.0, ["x", "y"]);
+
+        let xml = to_string(&span).unwrap();
+        assert_eq!(xml, r#"<Tag class="a b c" id="s"><tokens>x y</tokens></Tag>"#);
+        assert_eq!(from_str::<Tag>(&xml).unwrap(), span);
+
+        let empty: Tag = from_str(r#"<Tag class="" id="s"><tokens/></Tag>"#).unwrap();
+        assert!(empty.class.0.is_empty() && empty.tokens.0.is_empty());
+    }
+
+    #[test]
+    fn test_roundtrip_raw_number() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Quote {
+            #[serde(rename = "@qty")]
+            qty: RawNumber,
+            price: RawNumber,
+            scale: RawNumber,
+            delta: Vec<RawNumber>,
+        }
+
+        let xml = concat!(
+            r#"<Quote qty="+5"><price>19.90</price><scale>1e3</scale>"#,
+            "<delta>-0.50</delta><delta>007</delta></Quote>"
+        );
+        let quote: Quote = from_str(xml).unwrap();
+        assert_eq!(quote.qty.as_str(), "+5");
+        assert_eq!(quote.qty.as_i64().unwrap(), 5);
+        assert_eq!(quote.price.as_f64().unwrap(), 19.9);
+        assert_eq!(quote.scale.as_f64().unwrap(), 1000.0);
+        assert!(quote.scale.as_i64().is_err());
+        assert_eq!(quote.delta[1].as_i64().unwrap(), 7);
+        assert_eq!(to_string(&quote).unwrap(), xml);
+
+        for valid in [".5", "5.", "-1E+3", "+0.0e-1"] {
+            let quote: Quote = from_str(&xml.replace("19.90", valid)).unwrap();
+            assert_eq!(quote.price.as_str(), valid);
+        }
+        for invalid in ["cheap", "NaN", "inf", "-infinity", ".", "1e", "1.2.3", "--1", "0x10"] {
+            assert!(from_str::<Quote>(&xml.replace("19.90", invalid)).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_comments() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Server {
+            host: String,
+            #[serde(rename = "$comment")]
+            comments: Vec<String>,
+            port: u16,
+        }
+
+        let xml = "<Server><host>localhost</host><!-- port must be > 1024 --><port>8080</port></Server>";
+        let server: Server = from_str(xml).unwrap();
+        assert_eq!(server.comments, ["port must be > 1024"]);
+        assert_eq!(to_string(&server).unwrap(), xml);
+    }
+
+    #[test]
+    fn test_roundtrip_recursive_option_box() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Node {
+            value: String,
+            child: Option<Box<Node>>,
+        }
+
+        let chain = |depth: usize| {
+            (0..depth).rev().fold(None, |child, level| {
+                Some(Box::new(Node { value: format!("n{}", level), child }))
+            })
+        };
+
+        let root = *chain(5).unwrap();
+        let xml = to_string(&root).unwrap();
+        assert_eq!(
+            xml,
+            concat!(
+                "<Node><value>n0</value><child><value>n1</value><child><value>n2</value>",
+                "<child><value>n3</value><child><value>n4</value></child></child></child>",
+                "</child></Node>"
+            )
+        );
+        assert_eq!(from_str::<Node>(&xml).unwrap(), root);
+
+        let root = *chain(100).unwrap();
+        assert_eq!(from_str::<Node>(&to_string(&root).unwrap()).unwrap(), root);
+    }
 }
@@ -0,0 +1,69 @@
+//! A wrapper that captures an element verbatim.
+
+use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+
+/// Newtype name the XML serializer and deserializer recognize.
+pub(crate) const OUTER_XML_NAME: &str = "$serde_xml::OuterXml";
+
+/// An element captured exactly as written in the input, including its own
+/// start and end tags.
+///
+/// On serialization the captured markup is written as is, in place of the
+/// field's element, so it should be well-formed XML.
+///
+/// # Example
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_xml::{from_str, to_string, OuterXml};
+///
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Envelope {
+///     id: u32,
+///     a: OuterXml,
+/// }
+///
+/// let xml = r#"<Envelope><id>7</id><a id="1"><b>x</b></a></Envelope>"#;
+/// let envelope: Envelope = from_str(xml).unwrap();
+/// assert_eq!(envelope.a.0, r#"<a id="1"><b>x</b></a>"#);
+/// assert_eq!(to_string(&envelope).unwrap(), xml);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OuterXml(pub String);
+
+impl Serialize for OuterXml {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(OUTER_XML_NAME, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for OuterXml {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct OuterXmlVisitor;
+
+        impl<'de> Visitor<'de> for OuterXmlVisitor {
+            type Value = OuterXml;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("an element")
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<OuterXml, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                String::deserialize(deserializer).map(OuterXml)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(OUTER_XML_NAME, OuterXmlVisitor)
+    }
+}
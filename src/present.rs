@@ -0,0 +1,79 @@
+//! A wrapper that always writes its field's element.
+
+use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Newtype name the XML serializer and deserializer recognize.
+pub(crate) const PRESENT_NAME: &str = "$serde_xml::Present";
+
+/// Wraps an optional field so its element is always written, as `<field/>`
+/// when the value is `None`, whatever the serializer's
+/// [`NoneMode`](crate::NoneMode).
+///
+/// On deserialization an empty element reads back as `None`. Wrap it in a
+/// further `Option` to tell an empty element apart from a missing one.
+///
+/// # Example
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_xml::{from_str, to_string, Present};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// struct Order {
+///     note: Present<Option<String>>,
+/// }
+///
+/// let order = Order { note: Present(None) };
+/// let xml = to_string(&order).unwrap();
+/// assert_eq!(xml, "<Order><note/></Order>");
+/// assert_eq!(from_str::<Order>(&xml).unwrap(), order);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Present<T>(pub T);
+
+impl<T> Serialize for Present<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(PRESENT_NAME, &self.0)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Present<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct PresentVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for PresentVisitor<T>
+        where
+            T: Deserialize<'de>,
+        {
+            type Value = Present<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("an element that may be empty")
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Present<T>, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                T::deserialize(deserializer).map(Present)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(PRESENT_NAME, PresentVisitor(PhantomData))
+    }
+}
@@ -0,0 +1,83 @@
+//! Comments and processing instructions placed among an element's children.
+
+use crate::outer_xml::OUTER_XML_NAME;
+use crate::reader::is_name;
+use serde::ser::{Error, Serialize, Serializer};
+
+/// A comment or processing instruction written at its field's position among
+/// the element's children.
+///
+/// Collect them in a field renamed to `$nodes` to interleave them with the
+/// other fields in order.
+///
+/// # Example
+///
+/// ```
+/// use serde::Serialize;
+/// use serde_xml::{to_string, RawNode};
+///
+/// #[derive(Serialize)]
+/// struct Page {
+///     title: String,
+///     #[serde(rename = "$nodes")]
+///     nodes: Vec<RawNode>,
+///     body: String,
+/// }
+///
+/// let page = Page {
+///     title: "Home".to_string(),
+///     nodes: vec![
+///         RawNode::Comment("generated".to_string()),
+///         RawNode::Pi("render".to_string(), Some("fast".to_string())),
+///     ],
+///     body: "Hi".to_string(),
+/// };
+/// assert_eq!(
+///     to_string(&page).unwrap(),
+///     "<Page><title>Home</title><!-- generated --><?render fast?><body>Hi</body></Page>"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RawNode {
+    /// A comment: `<!-- text -->`.
+    Comment(String),
+    /// A processing instruction with a target and optional data:
+    /// `<?target data?>`.
+    Pi(String, Option<String>),
+}
+
+impl Serialize for RawNode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let markup = match self {
+            RawNode::Comment(text) => {
+                // The text is padded with spaces, so an edge `-` is fine
+                if text.contains("--") {
+                    return Err(S::Error::custom("comment must not contain '--'"));
+                }
+                format!("<!-- {} -->", text)
+            }
+            RawNode::Pi(target, data) => {
+                if !is_name(target) || target.eq_ignore_ascii_case("xml") {
+                    return Err(S::Error::custom(format!(
+                        "invalid processing instruction target '{}'",
+                        target
+                    )));
+                }
+                match data {
+                    Some(data) if data.contains("?>") => {
+                        return Err(S::Error::custom(
+                            "processing instruction data must not contain '?>'",
+                        ));
+                    }
+                    Some(data) => format!("<?{} {}?>", target, data),
+                    None => format!("<?{}?>", target),
+                }
+            }
+        };
+        // Written verbatim, like captured outer XML
+        serializer.serialize_newtype_struct(OUTER_XML_NAME, &markup)
+    }
+}
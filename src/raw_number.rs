@@ -0,0 +1,122 @@
+//! A number kept as the text it was written as.
+
+use crate::error::{Error, Result};
+use serde::de::{self, Deserialize, Deserializer, Unexpected, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+
+/// A number holding its original text, so formatting such as `19.90`, `1e3`
+/// or `+5` survives a round trip unchanged.
+///
+/// Deserialization accepts the XML Schema numeric form: an optional sign,
+/// digits with an optional fraction, and an optional exponent, such as
+/// `-0.5`, `.5` or `1E-3`. `NaN` and `INF` are rejected. Use
+/// [`as_f64`](Self::as_f64) or [`as_i64`](Self::as_i64) to get the value.
+///
+/// # Example
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_xml::{from_str, to_string, RawNumber};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Item {
+///     price: RawNumber,
+/// }
+///
+/// let item: Item = from_str("<Item><price>19.90</price></Item>").unwrap();
+/// assert_eq!(item.price.as_f64().unwrap(), 19.9);
+/// assert_eq!(to_string(&item).unwrap(), "<Item><price>19.90</price></Item>");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawNumber(pub String);
+
+impl RawNumber {
+    /// Returns the number's text.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Parses the number as an `f64`.
+    pub fn as_f64(&self) -> Result<f64> {
+        self.0
+            .parse()
+            .map_err(|_| Error::invalid_value(format!("'{}' is not a valid f64", self.0)))
+    }
+
+    /// Parses the number as an `i64`, failing if it has a fraction or
+    /// exponent.
+    pub fn as_i64(&self) -> Result<i64> {
+        self.0
+            .parse()
+            .map_err(|_| Error::invalid_value(format!("'{}' is not a valid i64", self.0)))
+    }
+}
+
+impl fmt::Display for RawNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for RawNumber {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawNumber {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RawNumberVisitor;
+
+        impl<'de> Visitor<'de> for RawNumberVisitor {
+            type Value = RawNumber;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a number")
+            }
+
+            fn visit_str<E>(self, v: &str) -> std::result::Result<RawNumber, E>
+            where
+                E: de::Error,
+            {
+                if is_number(v) {
+                    Ok(RawNumber(v.to_string()))
+                } else {
+                    Err(E::invalid_value(Unexpected::Str(v), &self))
+                }
+            }
+        }
+
+        deserializer.deserialize_str(RawNumberVisitor)
+    }
+}
+
+/// Returns true if `s` is an optionally signed decimal number with an
+/// optional exponent.
+fn is_number(s: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let s = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(index) => (&s[..index], Some(&s[index + 1..])),
+        None => (s, None),
+    };
+    let (int, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let exponent_ok = match exponent {
+        Some(exponent) => {
+            let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            !exponent.is_empty() && digits(exponent) == exponent.len()
+        }
+        None => true,
+    };
+    digits(int) == int.len()
+        && digits(fraction) == fraction.len()
+        && int.len() + fraction.len() > 0
+        && exponent_ok
+}
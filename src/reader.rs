@@ -3,10 +3,11 @@
 //! This module provides a fast, zero-copy XML tokenizer that produces events
 //! for elements, attributes, text content, and other XML constructs.
 
-use crate::error::{Error, Position, Result};
-use crate::escape::unescape;
+use crate::error::{Error, ErrorKind, Position, Result, Span};
+use crate::escape::{unescape_resolving, Entities};
 use memchr::{memchr, memchr2};
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 
 /// Whitespace lookup table for fast checking.
 static IS_WHITESPACE: [bool; 256] = {
@@ -55,6 +56,13 @@ static IS_NAME_CHAR: [bool; 256] = {
     lut
 };
 
+/// The UTF-8 byte order mark.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Limit on expanded content applied when DOCTYPE entities are expanded and
+/// no [`XmlReader::max_expanded_len`] is set.
+pub const DEFAULT_ENTITY_EXPANSION_LIMIT: usize = 10 * 1024 * 1024;
+
 /// An XML event produced by the reader.
 #[derive(Debug, Clone, PartialEq)]
 pub enum XmlEvent<'a> {
@@ -103,6 +111,46 @@ pub enum XmlEvent<'a> {
     Eof,
 }
 
+impl XmlEvent<'_> {
+    /// Returns the kind of this event.
+    pub fn kind(&self) -> XmlEventKind {
+        match self {
+            XmlEvent::XmlDecl { .. } => XmlEventKind::XmlDecl,
+            XmlEvent::StartElement { .. } => XmlEventKind::StartElement,
+            XmlEvent::EndElement { .. } => XmlEventKind::EndElement,
+            XmlEvent::EmptyElement { .. } => XmlEventKind::EmptyElement,
+            XmlEvent::Text(_) => XmlEventKind::Text,
+            XmlEvent::CData(_) => XmlEventKind::CData,
+            XmlEvent::Comment(_) => XmlEventKind::Comment,
+            XmlEvent::ProcessingInstruction { .. } => XmlEventKind::ProcessingInstruction,
+            XmlEvent::Eof => XmlEventKind::Eof,
+        }
+    }
+}
+
+/// The kind of an [`XmlEvent`], without its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XmlEventKind {
+    /// XML declaration.
+    XmlDecl,
+    /// Start of an element.
+    StartElement,
+    /// End of an element.
+    EndElement,
+    /// Empty element.
+    EmptyElement,
+    /// Text content.
+    Text,
+    /// CDATA section.
+    CData,
+    /// Comment.
+    Comment,
+    /// Processing instruction.
+    ProcessingInstruction,
+    /// End of document.
+    Eof,
+}
+
 /// An XML attribute.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Attribute<'a> {
@@ -118,10 +166,63 @@ pub struct XmlReader<'a> {
     pos: usize,
     line: usize,
     col: usize,
-    /// Stack of open element names for validation.
-    element_stack: Vec<String>,
+    /// Stack of open element names and their start positions for validation.
+    element_stack: Vec<(String, Position)>,
+    /// Position where the most recently read event began.
+    event_start: Position,
+    /// Cumulative length of unescaped text and attribute values.
+    expanded_len: usize,
+    /// Upper bound on `expanded_len`, if any.
+    max_expanded_len: Option<usize>,
+    /// Number of elements read so far.
+    element_count: usize,
+    /// Upper bound on `element_count`, if any.
+    max_elements: Option<usize>,
+    /// Upper bound on element nesting depth, if any.
+    max_depth: Option<usize>,
+    /// General entities available for reference expansion.
+    entities: HashMap<String, String>,
+    /// Whether to collect `<!ENTITY>` declarations from the DOCTYPE.
+    parse_entities: bool,
+    /// Whether HTML named entities such as `&nbsp;` are recognized.
+    html_entities: bool,
+    /// Positions of elements carrying `xml:id`.
+    ids: HashMap<String, Position>,
+    /// Whether to record `xml:id` positions.
+    track_ids: bool,
+    /// Names of elements that never have content, read as empty elements
+    /// even without `/>`.
+    void_elements: Vec<String>,
+    /// Whether unknown `<!...>` constructs are skipped instead of rejected.
+    skip_unknown_constructs: bool,
+    /// Whether attribute values are returned without unescaping.
+    raw_values: bool,
+    /// Whether an attribute may appear more than once on an element.
+    allow_duplicate_attributes: bool,
+    /// Whether non-ASCII name characters are checked against the XML name
+    /// character ranges.
+    strict_names: bool,
+    /// Whether unclosed elements are closed implicitly, as HTML parsers do.
+    auto_close: bool,
+    /// Whether whitespace around text is removed.
+    trim_text: bool,
+    /// Whether the last event was a start tag, so whitespace running up to
+    /// an end tag is the element's whole content.
+    after_start_tag: bool,
+    /// Whether the last event was an empty element, which has no content.
+    after_empty_tag: bool,
 }
 
+/// The number of attributes of an element that are checked for duplicates by
+/// scanning; past it, the names are kept in a set.
+const ATTRIBUTE_SCAN_LIMIT: usize = 16;
+
+/// The HTML void elements, which have no closing tag.
+const HTML_VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
 impl<'a> XmlReader<'a> {
     /// Creates a new XML reader from a string.
     #[inline]
@@ -131,15 +232,269 @@ impl<'a> XmlReader<'a> {
     }
 
     /// Creates a new XML reader from bytes.
+    ///
+    /// A leading UTF-8 byte order mark is skipped.
     #[inline]
     pub fn from_bytes(input: &'a [u8]) -> Self {
         Self {
             input,
-            pos: 0,
+            pos: if input.starts_with(UTF8_BOM) { UTF8_BOM.len() } else { 0 },
             line: 1,
             col: 1,
             element_stack: Vec::with_capacity(8), // Pre-allocate for typical nesting
+            event_start: Position { line: 1, column: 1, offset: 0 },
+            expanded_len: 0,
+            max_expanded_len: None,
+            element_count: 0,
+            max_elements: None,
+            max_depth: None,
+            entities: HashMap::new(),
+            parse_entities: false,
+            html_entities: false,
+            ids: HashMap::new(),
+            track_ids: false,
+            void_elements: Vec::new(),
+            skip_unknown_constructs: false,
+            raw_values: false,
+            allow_duplicate_attributes: false,
+            strict_names: false,
+            auto_close: false,
+            trim_text: true,
+            after_start_tag: false,
+            after_empty_tag: false,
+        }
+    }
+
+    /// Returns a builder for configuring readers.
+    #[inline]
+    pub fn builder() -> XmlReaderBuilder {
+        XmlReaderBuilder::new()
+    }
+
+    /// Predefines general entities that may be referenced as `&name;`.
+    ///
+    /// Declarations in the document's DOCTYPE do not override these.
+    #[inline]
+    pub fn with_entities(mut self, entities: HashMap<String, String>) -> Self {
+        self.entities = entities;
+        self
+    }
+
+    /// Enables collecting internal general entity declarations
+    /// (`<!ENTITY name "value">`) from the DOCTYPE's internal subset.
+    ///
+    /// External entities are never fetched and are ignored. References in a
+    /// value to entities declared before it are expanded when it is declared.
+    ///
+    /// Since nested entities can expand exponentially, enabling this limits
+    /// expanded content to [`DEFAULT_ENTITY_EXPANSION_LIMIT`] bytes unless
+    /// [`max_expanded_len`](Self::max_expanded_len) sets another limit.
+    #[inline]
+    pub fn parse_internal_entities(mut self, enabled: bool) -> Self {
+        self.parse_entities = enabled;
+        self
+    }
+
+    /// Enables recognizing HTML named entities such as `&nbsp;`, `&copy;`
+    /// and `&mdash;` in text and attribute values.
+    #[cfg(feature = "html-entities")]
+    #[inline]
+    pub fn html_entities(mut self, enabled: bool) -> Self {
+        self.html_entities = enabled;
+        self
+    }
+
+    /// Enables building an index from `xml:id` values to the positions of the
+    /// elements carrying them.
+    ///
+    /// The index is available from [`XmlReader::ids`]. If an id occurs more
+    /// than once, the first element wins.
+    #[inline]
+    pub fn track_ids(mut self, enabled: bool) -> Self {
+        self.track_ids = enabled;
+        self
+    }
+
+    /// Enables reading the HTML void elements (`br`, `img`, `meta`, ...) as
+    /// empty elements, so `<br>` needs no closing tag.
+    ///
+    /// Names are matched ignoring ASCII case. A stray closing tag for a void
+    /// element, such as `</br>`, is skipped.
+    #[inline]
+    pub fn html_void_elements(mut self, enabled: bool) -> Self {
+        self.void_elements = if enabled {
+            HTML_VOID_ELEMENTS
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self
+    }
+
+    /// Sets the names of elements read as empty elements even without `/>`.
+    ///
+    /// Names are matched ignoring ASCII case. See
+    /// [`XmlReader::html_void_elements`] for the HTML set.
+    #[inline]
+    pub fn void_elements<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.void_elements = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Skips `<!...>` constructs other than comments, CDATA sections and the
+    /// DOCTYPE, such as conditional sections (`<![INCLUDE[...]]>`), instead of
+    /// returning a syntax error.
+    ///
+    /// Bracketed constructs end at the matching `]]>`; others at the matching
+    /// `>`, like the DOCTYPE.
+    #[inline]
+    pub fn skip_unknown_constructs(mut self, enabled: bool) -> Self {
+        self.skip_unknown_constructs = enabled;
+        self
+    }
+
+    /// Returns attribute values exactly as written, borrowed from the input,
+    /// without resolving entity and character references.
+    ///
+    /// Useful when attributes must be preserved byte for byte, e.g. for
+    /// signing or canonicalization.
+    #[inline]
+    pub fn raw_values(mut self, enabled: bool) -> Self {
+        self.raw_values = enabled;
+        self
+    }
+
+    /// Accepts an attribute appearing more than once on an element, as
+    /// browsers do, instead of returning a syntax error. Every occurrence is
+    /// kept in the event's attributes.
+    #[inline]
+    pub fn allow_duplicate_attributes(mut self, enabled: bool) -> Self {
+        self.allow_duplicate_attributes = enabled;
+        self
+    }
+
+    /// Checks non-ASCII characters in element, attribute and other names
+    /// against the XML `NameStartChar` and `NameChar` ranges, returning an
+    /// invalid name error for any outside them.
+    ///
+    /// By default any non-ASCII character is accepted in names.
+    #[inline]
+    pub fn strict_names(mut self, enabled: bool) -> Self {
+        self.strict_names = enabled;
+        self
+    }
+
+    /// Closes elements left open, as HTML parsers do, instead of returning an
+    /// error.
+    ///
+    /// An end tag for an enclosing element first ends the elements inside it,
+    /// so `<card><title>x</card>` reads as if `</title>` were present. Elements
+    /// still open at the end of the input are ended there, and an end tag
+    /// matching no open element is skipped.
+    #[inline]
+    pub fn auto_close(mut self, enabled: bool) -> Self {
+        self.auto_close = enabled;
+        self
+    }
+
+    /// Sets whether leading and trailing whitespace is removed from text.
+    ///
+    /// On by default. When off, text is returned exactly as written; text
+    /// consisting only of whitespace, such as indentation, is still skipped
+    /// unless it is the whole content of an element, as in `<name> </name>`.
+    #[inline]
+    pub fn trim_text(mut self, enabled: bool) -> Self {
+        self.trim_text = enabled;
+        self
+    }
+
+    /// Returns whether leading and trailing whitespace is removed from text.
+    #[inline]
+    pub(crate) fn trims_text(&self) -> bool {
+        self.trim_text
+    }
+
+    /// Returns a text node read over `span` together with the whitespace
+    /// around it that trimming removed, such as the space in `Call <b>`.
+    pub(crate) fn untrimmed_text<'t>(&self, text: &'t str, span: Span) -> Cow<'t, str> {
+        if !self.trim_text {
+            return Cow::Borrowed(text);
         }
+        let start = span.start.offset;
+        let before = self.input[..start]
+            .iter()
+            .rev()
+            .take_while(|&&b| IS_WHITESPACE[b as usize])
+            .count();
+        let raw = match std::str::from_utf8(&self.input[start - before..span.end.offset]) {
+            Ok(raw) => raw,
+            Err(_) => return Cow::Borrowed(text),
+        };
+        let leading = &raw[..raw.len() - raw.trim_start().len()];
+        let trailing = &raw[raw.trim_end().len()..];
+        Cow::Owned(format!("{}{}{}", leading, text, trailing))
+    }
+
+    /// Returns whether `name` is configured as a void element.
+    #[inline]
+    fn is_void_element(&self, name: &str) -> bool {
+        self.void_elements
+            .iter()
+            .any(|void| void.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the `xml:id` index built so far.
+    ///
+    /// The index is empty unless [`XmlReader::track_ids`] was enabled.
+    #[inline]
+    pub fn ids(&self) -> &HashMap<String, Position> {
+        &self.ids
+    }
+
+    /// Returns the general entities known to the reader.
+    #[inline]
+    pub fn entities(&self) -> &HashMap<String, String> {
+        &self.entities
+    }
+
+    /// Limits the cumulative length of unescaped text, CDATA and attribute
+    /// values the reader will produce.
+    ///
+    /// Once the budget is exceeded, `next_event` returns a syntax error instead
+    /// of continuing to allocate. Use this when parsing untrusted input.
+    #[inline]
+    pub fn max_expanded_len(mut self, limit: usize) -> Self {
+        self.max_expanded_len = Some(limit);
+        self
+    }
+
+    /// Limits the total number of elements (start and empty tags) the reader
+    /// will produce.
+    ///
+    /// This bounds the work done on flat but huge documents, independent of
+    /// nesting depth or input size.
+    #[inline]
+    pub fn max_elements(mut self, limit: usize) -> Self {
+        self.max_elements = Some(limit);
+        self
+    }
+
+    /// Limits how deeply elements may nest, counting the root element as
+    /// depth 1.
+    ///
+    /// An element that would exceed the limit is a syntax error. Use this to
+    /// bound recursion when deserializing untrusted input into recursive
+    /// types.
+    #[inline]
+    pub fn max_depth(mut self, limit: usize) -> Self {
+        self.max_depth = Some(limit);
+        self
     }
 
     /// Returns the current position in the input.
@@ -152,28 +507,332 @@ impl<'a> XmlReader<'a> {
         }
     }
 
+    /// Returns the limit on expanded content, which defaults to
+    /// [`DEFAULT_ENTITY_EXPANSION_LIMIT`] when DOCTYPE entities are expanded.
+    #[inline(always)]
+    fn expansion_limit(&self) -> Option<usize> {
+        self.max_expanded_len
+            .or(self.parse_entities.then_some(DEFAULT_ENTITY_EXPANSION_LIMIT))
+    }
+
+    /// Returns the error for expanded content exceeding `limit` at `position`.
+    fn expansion_error(limit: usize, position: Position) -> Error {
+        Error::syntax(format!("expanded content exceeds limit of {} bytes", limit))
+            .with_position(position)
+    }
+
+    /// Charges `len` bytes of expanded content against the budget.
+    #[inline(always)]
+    fn track_expansion(&mut self, len: usize) -> Result<()> {
+        self.expanded_len = self.expanded_len.saturating_add(len);
+        match self.expansion_limit() {
+            Some(limit) if self.expanded_len > limit => {
+                Err(Self::expansion_error(limit, self.position()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Unescapes a value found at `offset` in the input, resolving any known
+    /// general entities.
+    ///
+    /// Custom entities are not expanded past the remaining budget, so a value
+    /// made of many large references fails before it is built.
+    #[inline(always)]
+    fn unescape_value(&self, s: &'a str, offset: usize) -> Result<Cow<'a, str>> {
+        let limit = self.expansion_limit();
+        let entities = Entities {
+            custom: (!self.entities.is_empty()).then_some(&self.entities),
+            html: self.html_entities,
+            limit: limit.map(|limit| limit.saturating_sub(self.expanded_len)),
+        };
+        unescape_resolving(s, entities).map_err(|e| {
+            let position = self.position_at(offset + e.position);
+            let name = e.entity.trim_start_matches('&').trim_end_matches(';');
+            match limit {
+                Some(limit) if self.entities.contains_key(name) => {
+                    Self::expansion_error(limit, position)
+                }
+                _ => Error::invalid_escape(e.entity).with_position(position),
+            }
+        })
+    }
+
+    /// Computes the line and column of an input offset.
+    ///
+    /// This scans the input from the start, so it is only used for errors.
+    fn position_at(&self, offset: usize) -> Position {
+        let before = &self.input[..offset];
+        let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        Position {
+            line: 1 + before.iter().filter(|&&b| b == b'\n').count(),
+            column: 1 + offset - line_start,
+            offset,
+        }
+    }
+
+    /// Returns the path of the open elements, such as `/root/item`.
+    fn element_path(&self) -> String {
+        let mut path = String::new();
+        for (name, _) in &self.element_stack {
+            path.push('/');
+            path.push_str(name);
+        }
+        path
+    }
+
     /// Returns whether there are any open elements.
     #[inline]
     pub fn depth(&self) -> usize {
         self.element_stack.len()
     }
 
+    /// Returns the name of the innermost open element.
+    ///
+    /// An element is open from its start tag event until its end tag event;
+    /// empty elements are never open.
+    #[inline]
+    pub fn current_element(&self) -> Option<&str> {
+        self.element_stack.last().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the position where the most recently read event began, such
+    /// as the `<` of a start tag.
+    #[inline]
+    pub fn event_start(&self) -> Position {
+        self.event_start
+    }
+
+    /// Returns the range of the input consumed by the most recent
+    /// [`next_event`](Self::next_event) call, such as a whole start tag or
+    /// text node.
+    ///
+    /// The start is [`event_start`](Self::event_start). Leading whitespace is
+    /// skipped before an event begins; a text node's span includes its
+    /// trailing whitespace. End tags supplied by
+    /// [`auto_close`](Self::auto_close) have an empty span.
+    #[inline]
+    pub fn last_span(&self) -> Span {
+        Span {
+            start: self.event_start,
+            end: self.position(),
+        }
+    }
+
+    /// Returns the input being read.
+    #[inline]
+    pub(crate) fn input(&self) -> &'a [u8] {
+        self.input
+    }
+
+    /// Returns the names of the open elements, from the root inwards.
+    pub fn path(&self) -> impl Iterator<Item = &str> + '_ {
+        self.element_stack.iter().map(|(name, _)| name.as_str())
+    }
+
     /// Reads the next XML event.
     #[inline]
     pub fn next_event(&mut self) -> Result<XmlEvent<'a>> {
-        self.skip_whitespace_fast();
+        // Skipped markup is passed over in a loop, so long runs of it can't
+        // exhaust the stack
+        loop {
+            if let Some(event) = self.read_event()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Reads the markup or text at the current position, returning `None`
+    /// if it produces no event.
+    #[inline]
+    fn read_event(&mut self) -> Result<Option<XmlEvent<'a>>> {
+        let after_start_tag = std::mem::take(&mut self.after_start_tag);
+        self.after_empty_tag = false;
+        if self.trim_text {
+            self.skip_whitespace_fast();
+        }
+        self.event_start = self.position();
 
         if self.pos >= self.input.len() {
-            if let Some(tag) = self.element_stack.pop() {
-                return Err(Error::unclosed_tag(tag).with_position(self.position()));
+            if let Some((tag, start)) = self.element_stack.pop() {
+                if self.auto_close {
+                    return Ok(Some(XmlEvent::EndElement { name: Cow::Owned(tag) }));
+                }
+                let end = self.position();
+                return Err(Error::unclosed_tag(tag)
+                    .with_position(end)
+                    .with_span(Span { start, end }));
             }
-            return Ok(XmlEvent::Eof);
+            return Ok(Some(XmlEvent::Eof));
         }
 
         if self.input[self.pos] == b'<' {
             self.read_tag()
         } else {
-            self.read_text()
+            self.read_text(after_start_tag)
+        }
+    }
+
+    /// Reads the text content of the element whose start tag was just read,
+    /// consuming its end tag.
+    ///
+    /// Text and CDATA sections are joined, and comments and processing
+    /// instructions are skipped. A child element is an error. Content that is
+    /// a single text node needing no unescaping stays borrowed.
+    ///
+    /// Right after an empty element such as `<name/>`, this returns an empty
+    /// string without reading on. After any other event it fails without
+    /// consuming input, as there is no element to read the text of.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use serde_xml::{XmlEvent, XmlReader};
+    ///
+    /// let mut reader = XmlReader::from_str("<name>Alice</name>");
+    /// assert!(matches!(reader.next_event().unwrap(), XmlEvent::StartElement { .. }));
+    /// assert_eq!(reader.read_text_until_end().unwrap(), "Alice");
+    /// assert_eq!(reader.next_event().unwrap(), XmlEvent::Eof);
+    /// ```
+    pub fn read_text_until_end(&mut self) -> Result<Cow<'a, str>> {
+        if self.after_empty_tag {
+            return Ok(Cow::Borrowed(""));
+        }
+        if !self.after_start_tag {
+            return Err(Error::new(ErrorKind::Custom(
+                "read_text_until_end must directly follow a start tag".to_string(),
+            ))
+            .with_position(self.position()));
+        }
+        let mut content: Option<Cow<'a, str>> = None;
+        loop {
+            match self.next_event()? {
+                XmlEvent::Text(text) | XmlEvent::CData(text) => {
+                    content = Some(match content {
+                        None => text,
+                        Some(content) => Cow::Owned(content.into_owned() + &text),
+                    });
+                }
+                XmlEvent::EndElement { .. } => return Ok(content.unwrap_or_default()),
+                XmlEvent::StartElement { name, .. } | XmlEvent::EmptyElement { name, .. } => {
+                    return Err(Error::new(ErrorKind::UnexpectedElement(name.into_owned()))
+                        .with_position(self.event_start));
+                }
+                XmlEvent::Eof => {
+                    return Err(Error::unexpected_eof().with_position(self.position()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the kind of the next event without consuming it.
+    ///
+    /// Only the leading bytes of the event are inspected; a start tag is
+    /// scanned up to its `>` to tell it apart from an empty element. Markup
+    /// that produces no event, such as a DOCTYPE, is consumed along the way.
+    /// Errors inside the event itself are reported by the following
+    /// [`next_event`](Self::next_event) call.
+    pub fn peek_kind(&mut self) -> Result<XmlEventKind> {
+        loop {
+            if !self.trim_text {
+                // Whitespace leading up to text belongs to it
+                let rest = &self.input[self.pos..];
+                let len = rest.iter().take_while(|&&b| IS_WHITESPACE[b as usize]).count();
+                if !matches!(rest.get(len), None | Some(b'<'))
+                    || (len > 0 && self.after_start_tag && rest[len..].starts_with(b"</"))
+                {
+                    return Ok(XmlEventKind::Text);
+                }
+            }
+            self.skip_whitespace_fast();
+            let rest = &self.input[self.pos..];
+
+            if rest.is_empty() {
+                if self.auto_close && !self.element_stack.is_empty() {
+                    return Ok(XmlEventKind::EndElement);
+                }
+                if let Some((tag, start)) = self.element_stack.last() {
+                    let end = self.position();
+                    return Err(Error::unclosed_tag(tag.clone())
+                        .with_position(end)
+                        .with_span(Span { start: *start, end }));
+                }
+                return Ok(XmlEventKind::Eof);
+            }
+            if rest[0] != b'<' {
+                return Ok(XmlEventKind::Text);
+            }
+
+            match rest.get(1) {
+                None => {
+                    return Err(Error::unexpected_eof().with_position(self.position()));
+                }
+                Some(b'/') => {
+                    let name = leading_name(&rest[2..]);
+                    if !self.is_skipped_end(name) {
+                        return Ok(XmlEventKind::EndElement);
+                    }
+                    self.pos += 1;
+                    self.col += 1;
+                    self.read_end_tag()?;
+                }
+                Some(b'?') => {
+                    return Ok(if leading_name(&rest[2..]).eq_ignore_ascii_case("xml") {
+                        XmlEventKind::XmlDecl
+                    } else {
+                        XmlEventKind::ProcessingInstruction
+                    });
+                }
+                Some(b'!') => {
+                    let special = &rest[2..];
+                    if special.starts_with(b"--") {
+                        return Ok(XmlEventKind::Comment);
+                    }
+                    if special.starts_with(b"[CDATA[") {
+                        return Ok(XmlEventKind::CData);
+                    }
+                    let doctype = special.starts_with(b"DOCTYPE");
+                    if !doctype && !self.skip_unknown_constructs {
+                        return Err(Error::syntax("unknown construct after '<!'")
+                            .with_position(self.position()));
+                    }
+                    self.pos += 2;
+                    self.col += 2;
+                    if doctype {
+                        self.skip_doctype()?;
+                    } else {
+                        self.skip_unknown_construct()?;
+                    }
+                }
+                Some(_) => {
+                    let tag = &rest[1..];
+                    let mut quote = None;
+                    let close = tag.iter().position(|&b| match quote {
+                        Some(q) => {
+                            if b == q {
+                                quote = None;
+                            }
+                            false
+                        }
+                        None => {
+                            if b == b'"' || b == b'\'' {
+                                quote = Some(b);
+                            }
+                            b == b'>'
+                        }
+                    });
+                    let Some(close) = close else {
+                        return Err(Error::unexpected_eof().with_position(self.position()));
+                    };
+                    let self_closing = close > 0 && tag[close - 1] == b'/';
+                    return Ok(if self_closing || self.is_void_element(leading_name(tag)) {
+                        XmlEventKind::EmptyElement
+                    } else {
+                        XmlEventKind::StartElement
+                    });
+                }
+            }
         }
     }
 
@@ -197,7 +856,7 @@ impl<'a> XmlReader<'a> {
 
     /// Reads text content using memchr for fast scanning.
     #[inline]
-    fn read_text(&mut self) -> Result<XmlEvent<'a>> {
+    fn read_text(&mut self, after_start_tag: bool) -> Result<Option<XmlEvent<'a>>> {
         let start = self.pos;
 
         // Fast path: find '<' using SIMD-accelerated memchr
@@ -217,16 +876,18 @@ impl<'a> XmlReader<'a> {
             .map_err(|_| Error::new(crate::error::ErrorKind::InvalidUtf8))?;
 
         // Trim whitespace from text
-        let trimmed = text.trim();
-        if trimmed.is_empty() {
-            return self.next_event();
+        let trimmed = if self.trim_text { text.trim() } else { text };
+        // Untrimmed whitespace is kept when it is an element's whole content
+        let whole_content = after_start_tag && self.input[self.pos..].starts_with(b"</");
+        if trimmed.is_empty() || (trimmed.trim_start().is_empty() && !whole_content) {
+            return Ok(None);
         }
 
         // Unescape XML entities
-        match unescape(trimmed) {
-            Ok(unescaped) => Ok(XmlEvent::Text(unescaped)),
-            Err(e) => Err(Error::invalid_escape(e.entity)),
-        }
+        let offset = start + (text.len() - text.trim_start().len());
+        let unescaped = self.unescape_value(trimmed, offset)?;
+        self.track_expansion(unescaped.len())?;
+        Ok(Some(XmlEvent::Text(unescaped)))
     }
 
     /// Updates line/column tracking for a range of bytes.
@@ -246,7 +907,7 @@ impl<'a> XmlReader<'a> {
 
     /// Reads a tag (element, comment, CDATA, PI, or declaration).
     #[inline]
-    fn read_tag(&mut self) -> Result<XmlEvent<'a>> {
+    fn read_tag(&mut self) -> Result<Option<XmlEvent<'a>>> {
         debug_assert_eq!(self.input[self.pos], b'<');
         self.pos += 1;
         self.col += 1;
@@ -257,17 +918,49 @@ impl<'a> XmlReader<'a> {
 
         match self.input[self.pos] {
             b'/' => self.read_end_element(),
-            b'?' => self.read_processing_instruction(),
+            b'?' => self.read_processing_instruction().map(Some),
             b'!' => self.read_special(),
-            _ => self.read_start_element(),
+            _ => self.read_start_element().map(Some),
         }
     }
 
     /// Reads a start element or empty element.
     #[inline]
     fn read_start_element(&mut self) -> Result<XmlEvent<'a>> {
+        self.element_count += 1;
+        if let Some(limit) = self.max_elements {
+            if self.element_count > limit {
+                return Err(Error::syntax(format!(
+                    "document exceeds limit of {} elements",
+                    limit
+                ))
+                .with_position(self.position()));
+            }
+        }
+        if let Some(limit) = self.max_depth {
+            if self.element_stack.len() >= limit {
+                return Err(Error::syntax(format!(
+                    "elements nest deeper than the limit of {}",
+                    limit
+                ))
+                .with_position(self.position()));
+            }
+        }
+
+        // Position of the opening '<'
+        let start = Position {
+            line: self.line,
+            column: self.col - 1,
+            offset: self.pos - 1,
+        };
         let name = self.read_name()?;
-        let attributes = self.read_attributes()?;
+        let attributes = self.read_attributes(Some(name))?;
+
+        if self.track_ids {
+            if let Some(id) = attributes.iter().find(|a| a.name == "xml:id") {
+                self.ids.entry(id.value.to_string()).or_insert(start);
+            }
+        }
 
         self.skip_whitespace_fast();
 
@@ -280,15 +973,25 @@ impl<'a> XmlReader<'a> {
             self.pos += 1;
             self.col += 1;
             self.expect_char(b'>')?;
+            self.after_empty_tag = true;
             Ok(XmlEvent::EmptyElement {
                 name: Cow::Borrowed(name),
                 attributes,
             })
         } else if self.input[self.pos] == b'>' {
-            // Start element: <name>
             self.pos += 1;
             self.col += 1;
-            self.element_stack.push(name.to_string());
+            if self.is_void_element(name) {
+                // Void element: <name>
+                self.after_empty_tag = true;
+                return Ok(XmlEvent::EmptyElement {
+                    name: Cow::Borrowed(name),
+                    attributes,
+                });
+            }
+            // Start element: <name>
+            self.element_stack.push((name.to_string(), start));
+            self.after_start_tag = true;
             Ok(XmlEvent::StartElement {
                 name: Cow::Borrowed(name),
                 attributes,
@@ -298,28 +1001,68 @@ impl<'a> XmlReader<'a> {
         }
     }
 
-    /// Reads an end element.
+    /// Reads an end element, or `None` for an end tag that is skipped.
     #[inline]
-    fn read_end_element(&mut self) -> Result<XmlEvent<'a>> {
-        debug_assert_eq!(self.input[self.pos], b'/');
-        self.pos += 1;
-        self.col += 1;
+    fn read_end_element(&mut self) -> Result<Option<XmlEvent<'a>>> {
+        // Where the tag starts, at its `<`
+        let tag_start = (self.pos - 1, self.line, self.col - 1);
+        let name = self.read_end_tag()?;
+
+        // Void elements have no start tag left open to close
+        if self.is_skipped_end(name) {
+            return Ok(None);
+        }
 
-        let name = self.read_name()?;
-        self.skip_whitespace_fast();
-        self.expect_char(b'>')?;
+        // End the innermost element and read the end tag again afterwards
+        let matched = matches!(self.element_stack.last(), Some((expected, _)) if expected == name);
+        if self.auto_close && !matched {
+            if let Some((expected, _)) = self.element_stack.pop() {
+                (self.pos, self.line, self.col) = tag_start;
+                return Ok(Some(XmlEvent::EndElement { name: Cow::Owned(expected) }));
+            }
+        }
 
         // Validate matching tags
         match self.element_stack.pop() {
-            Some(expected) if expected == name => Ok(XmlEvent::EndElement {
+            Some((expected, _)) if expected == name => Ok(Some(XmlEvent::EndElement {
                 name: Cow::Borrowed(name),
-            }),
-            Some(expected) => Err(Error::mismatched_tag(expected, name.to_string()).with_position(self.position())),
+            })),
+            Some((expected, start)) => {
+                let end = self.position();
+                Err(Error::mismatched_tag(expected, name.to_string())
+                    .with_position(end)
+                    .with_span(Span { start, end }))
+            }
             None => Err(Error::syntax(format!("unexpected closing tag: {}", name))
                 .with_position(self.position())),
         }
     }
 
+    /// Reads an end tag's name, starting at the `/` after `<`.
+    fn read_end_tag(&mut self) -> Result<&'a str> {
+        debug_assert_eq!(self.input[self.pos], b'/');
+        self.pos += 1;
+        self.col += 1;
+
+        let name = self.read_name()?;
+        self.skip_whitespace_fast();
+        self.expect_char(b'>')?;
+        Ok(name)
+    }
+
+    /// Returns true if `name` ends a void element that has no open start tag.
+    fn is_stray_void_end(&self, name: &str) -> bool {
+        let matched = matches!(self.element_stack.last(), Some((expected, _)) if expected == name);
+        !matched && self.is_void_element(name)
+    }
+
+    /// Returns true if the end tag for `name` is skipped: it ends a void
+    /// element, or closes no open element while auto-closing.
+    fn is_skipped_end(&self, name: &str) -> bool {
+        self.is_stray_void_end(name)
+            || (self.auto_close && !self.element_stack.iter().any(|(open, _)| open == name))
+    }
+
     /// Reads a processing instruction.
     fn read_processing_instruction(&mut self) -> Result<XmlEvent<'a>> {
         debug_assert_eq!(self.input[self.pos], b'?');
@@ -371,7 +1114,7 @@ impl<'a> XmlReader<'a> {
 
     /// Reads an XML declaration.
     fn read_xml_decl(&mut self) -> Result<XmlEvent<'a>> {
-        let attributes = self.read_attributes()?;
+        let attributes = self.read_attributes(None)?;
         self.skip_whitespace_fast();
 
         if self.pos + 1 >= self.input.len()
@@ -405,8 +1148,9 @@ impl<'a> XmlReader<'a> {
         })
     }
 
-    /// Reads special constructs (comments, CDATA, DOCTYPE).
-    fn read_special(&mut self) -> Result<XmlEvent<'a>> {
+    /// Reads special constructs (comments, CDATA, DOCTYPE), or `None` for
+    /// one that is skipped.
+    fn read_special(&mut self) -> Result<Option<XmlEvent<'a>>> {
         debug_assert_eq!(self.input[self.pos], b'!');
         self.pos += 1;
         self.col += 1;
@@ -420,22 +1164,62 @@ impl<'a> XmlReader<'a> {
             && self.input[self.pos] == b'-'
             && self.input[self.pos + 1] == b'-'
         {
-            return self.read_comment();
+            return self.read_comment().map(Some);
         }
 
         // Check for CDATA: <![CDATA[
         if self.pos + 6 < self.input.len() && &self.input[self.pos..self.pos + 7] == b"[CDATA[" {
-            return self.read_cdata();
+            return self.read_cdata().map(Some);
         }
 
         // Check for DOCTYPE
         if self.pos + 6 < self.input.len() && self.input[self.pos..].starts_with(b"DOCTYPE") {
-            return self.skip_doctype();
+            self.skip_doctype()?;
+            return Ok(None);
+        }
+
+        if self.skip_unknown_constructs {
+            self.skip_unknown_construct()?;
+            return Ok(None);
         }
 
         Err(Error::syntax("unknown construct after '<!'").with_position(self.position()))
     }
 
+    /// Skips an unknown `<!...>` construct, starting just after `<!`.
+    fn skip_unknown_construct(&mut self) -> Result<()> {
+        if self.input[self.pos] != b'[' {
+            // Balance angle brackets like a DOCTYPE, without parsing entities
+            let parse_entities = std::mem::replace(&mut self.parse_entities, false);
+            let result = self.skip_doctype();
+            self.parse_entities = parse_entities;
+            return result;
+        }
+
+        // Bracketed section: find the `]]>` closing it, counting nested `<![`
+        let mut depth = 0usize;
+        while self.pos < self.input.len() {
+            let rest = &self.input[self.pos..];
+            if rest.starts_with(b"<![") {
+                depth += 1;
+                self.pos += 3;
+                self.col += 3;
+            } else if rest.starts_with(b"]]>") {
+                self.pos += 3;
+                self.col += 3;
+                if depth == 0 {
+                    return Ok(());
+                }
+                depth -= 1;
+            } else {
+                self.update_position_for_range(self.pos, self.pos + 1);
+                self.pos += 1;
+            }
+        }
+
+        Err(Error::syntax("unterminated '<![' section").with_position(self.position()))
+    }
+
     /// Reads a comment using memchr for fast end detection.
     fn read_comment(&mut self) -> Result<XmlEvent<'a>> {
         self.pos += 2; // Skip --
@@ -486,6 +1270,7 @@ impl<'a> XmlReader<'a> {
                         .map_err(|_| Error::new(crate::error::ErrorKind::InvalidUtf8))?;
                     self.pos = check_pos + 3;
                     self.col += 3;
+                    self.track_expansion(data.len())?;
                     return Ok(XmlEvent::CData(Cow::Borrowed(data)));
                 }
                 self.update_position_for_range(self.pos, check_pos + 1);
@@ -498,8 +1283,9 @@ impl<'a> XmlReader<'a> {
         Err(Error::syntax("unterminated CDATA section").with_position(self.position()))
     }
 
-    /// Skips a DOCTYPE declaration.
-    fn skip_doctype(&mut self) -> Result<XmlEvent<'a>> {
+    /// Skips a DOCTYPE declaration, collecting entity declarations if enabled.
+    fn skip_doctype(&mut self) -> Result<()> {
+        let start = self.pos;
         let mut depth = 1;
 
         while self.pos < self.input.len() && depth > 0 {
@@ -522,14 +1308,28 @@ impl<'a> XmlReader<'a> {
             }
         }
 
-        // Skip to next event
-        self.next_event()
+        if self.parse_entities {
+            let doctype = std::str::from_utf8(&self.input[start..self.pos])
+                .map_err(|_| Error::new(crate::error::ErrorKind::InvalidUtf8))?;
+            for (name, value) in parse_entity_decls(doctype) {
+                if self.entities.contains_key(name) {
+                    continue;
+                }
+                let offset = start + (value.as_ptr() as usize - doctype.as_ptr() as usize);
+                let value = self.unescape_value(value, offset)?.into_owned();
+                self.track_expansion(value.len())?;
+                self.entities.insert(name.to_string(), value);
+            }
+        }
+
+        Ok(())
     }
 
     /// Reads an XML name using lookup table.
     #[inline]
     fn read_name(&mut self) -> Result<&'a str> {
         let start = self.pos;
+        let start_col = self.col;
 
         // First character must be a name start char
         if self.pos >= self.input.len() {
@@ -550,14 +1350,43 @@ impl<'a> XmlReader<'a> {
             self.col += 1;
         }
 
-        std::str::from_utf8(&self.input[start..self.pos])
-            .map_err(|_| Error::new(crate::error::ErrorKind::InvalidUtf8))
+        let name = std::str::from_utf8(&self.input[start..self.pos])
+            .map_err(|_| Error::new(crate::error::ErrorKind::InvalidUtf8))?;
+
+        if self.strict_names && !name.is_ascii() {
+            let invalid = name.char_indices().find(|&(i, c)| {
+                if i == 0 {
+                    !is_name_start_char(c)
+                } else {
+                    !is_name_char(c)
+                }
+            });
+            if let Some((i, c)) = invalid {
+                return Err(Error::invalid_name(format!(
+                    "invalid name character {:?} in '{}'",
+                    c, name
+                ))
+                .with_position(Position {
+                    line: self.line,
+                    column: start_col + i,
+                    offset: start + i,
+                }));
+            }
+        }
+
+        Ok(name)
     }
 
     /// Reads element attributes with pre-allocated vector.
+    ///
+    /// An invalid escape in the attributes of `element` is reported at the
+    /// attribute's path, such as `/root/item/@id`, since the element has not
+    /// been entered yet.
     #[inline]
-    fn read_attributes(&mut self) -> Result<Vec<Attribute<'a>>> {
-        let mut attributes = Vec::with_capacity(4); // Pre-allocate for typical case
+    fn read_attributes(&mut self, element: Option<&str>) -> Result<Vec<Attribute<'a>>> {
+        let mut attributes: Vec<Attribute<'a>> = Vec::with_capacity(4); // Pre-allocate for typical case
+        // Names seen so far, once there are too many to scan for duplicates
+        let mut names: HashSet<Cow<'a, str>> = HashSet::new();
 
         loop {
             self.skip_whitespace_fast();
@@ -573,7 +1402,22 @@ impl<'a> XmlReader<'a> {
             }
 
             // Read attribute name
+            let name_position = self.position();
             let name = self.read_name()?;
+            let duplicate = if self.allow_duplicate_attributes {
+                false
+            } else if attributes.len() < ATTRIBUTE_SCAN_LIMIT {
+                attributes.iter().any(|a| a.name == name)
+            } else {
+                if names.is_empty() {
+                    names.extend(attributes.iter().map(|a| a.name.clone()));
+                }
+                !names.insert(Cow::Borrowed(name))
+            };
+            if duplicate {
+                return Err(Error::syntax(format!("duplicate attribute '{}'", name))
+                    .with_position(name_position));
+            }
             self.skip_whitespace_fast();
 
             // Expect '='
@@ -581,7 +1425,12 @@ impl<'a> XmlReader<'a> {
             self.skip_whitespace_fast();
 
             // Read attribute value
-            let value = self.read_attribute_value()?;
+            let value = self.read_attribute_value().map_err(|e| match element {
+                Some(element) if matches!(e.kind(), ErrorKind::InvalidEscape(_)) => {
+                    e.with_path(format!("{}/{}/@{}", self.element_path(), element, name))
+                }
+                _ => e,
+            })?;
 
             attributes.push(Attribute {
                 name: Cow::Borrowed(name),
@@ -616,11 +1465,14 @@ impl<'a> XmlReader<'a> {
                 self.pos += offset + 1;
                 self.col += offset + 1;
 
-                // Unescape the value
-                match unescape(value) {
-                    Ok(unescaped) => Ok(unescaped),
-                    Err(e) => Err(Error::invalid_escape(e.entity)),
+                if self.raw_values {
+                    return Ok(Cow::Borrowed(value));
                 }
+
+                // Unescape the value
+                let unescaped = self.unescape_value(value, start)?;
+                self.track_expansion(unescaped.len())?;
+                Ok(unescaped)
             }
             None => Err(Error::syntax("unterminated attribute value").with_position(self.position())),
         }
@@ -648,6 +1500,302 @@ impl<'a> XmlReader<'a> {
     }
 }
 
+/// Collects [`XmlReader`] options and creates configured readers.
+///
+/// `XmlReader::from_str` and `XmlReader::from_bytes` remain the zero-config
+/// defaults; the builder can be reused to create any number of readers.
+///
+/// # Example
+///
+/// ```
+/// use serde_xml::{XmlEvent, XmlReaderBuilder};
+///
+/// let mut reader = XmlReaderBuilder::new()
+///     .max_elements(10)
+///     .track_ids(true)
+///     .from_str(r#"<doc xml:id="d"/>"#);
+/// while reader.next_event().unwrap() != XmlEvent::Eof {}
+/// assert!(reader.ids().contains_key("d"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct XmlReaderBuilder {
+    max_expanded_len: Option<usize>,
+    max_elements: Option<usize>,
+    max_depth: Option<usize>,
+    entities: HashMap<String, String>,
+    parse_entities: bool,
+    html_entities: bool,
+    track_ids: bool,
+    void_elements: Vec<String>,
+    skip_unknown_constructs: bool,
+    raw_values: bool,
+    allow_duplicate_attributes: bool,
+    strict_names: bool,
+    auto_close: bool,
+    trim_text: Option<bool>,
+}
+
+impl XmlReaderBuilder {
+    /// Creates a builder with default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// See [`XmlReader::max_expanded_len`].
+    pub fn max_expanded_len(mut self, limit: usize) -> Self {
+        self.max_expanded_len = Some(limit);
+        self
+    }
+
+    /// See [`XmlReader::max_elements`].
+    pub fn max_elements(mut self, limit: usize) -> Self {
+        self.max_elements = Some(limit);
+        self
+    }
+
+    /// See [`XmlReader::max_depth`].
+    pub fn max_depth(mut self, limit: usize) -> Self {
+        self.max_depth = Some(limit);
+        self
+    }
+
+    /// See [`XmlReader::with_entities`].
+    pub fn with_entities(mut self, entities: HashMap<String, String>) -> Self {
+        self.entities = entities;
+        self
+    }
+
+    /// See [`XmlReader::parse_internal_entities`].
+    pub fn parse_internal_entities(mut self, enabled: bool) -> Self {
+        self.parse_entities = enabled;
+        self
+    }
+
+    /// See [`XmlReader::html_entities`].
+    #[cfg(feature = "html-entities")]
+    pub fn html_entities(mut self, enabled: bool) -> Self {
+        self.html_entities = enabled;
+        self
+    }
+
+    /// See [`XmlReader::track_ids`].
+    pub fn track_ids(mut self, enabled: bool) -> Self {
+        self.track_ids = enabled;
+        self
+    }
+
+    /// See [`XmlReader::html_void_elements`].
+    pub fn html_void_elements(mut self, enabled: bool) -> Self {
+        self.void_elements = if enabled {
+            HTML_VOID_ELEMENTS
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self
+    }
+
+    /// See [`XmlReader::void_elements`].
+    pub fn void_elements<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.void_elements = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// See [`XmlReader::skip_unknown_constructs`].
+    pub fn skip_unknown_constructs(mut self, enabled: bool) -> Self {
+        self.skip_unknown_constructs = enabled;
+        self
+    }
+
+    /// See [`XmlReader::raw_values`].
+    pub fn raw_values(mut self, enabled: bool) -> Self {
+        self.raw_values = enabled;
+        self
+    }
+
+    /// See [`XmlReader::allow_duplicate_attributes`].
+    pub fn allow_duplicate_attributes(mut self, enabled: bool) -> Self {
+        self.allow_duplicate_attributes = enabled;
+        self
+    }
+
+    /// See [`XmlReader::strict_names`].
+    pub fn strict_names(mut self, enabled: bool) -> Self {
+        self.strict_names = enabled;
+        self
+    }
+
+    /// See [`XmlReader::auto_close`].
+    pub fn auto_close(mut self, enabled: bool) -> Self {
+        self.auto_close = enabled;
+        self
+    }
+
+    /// See [`XmlReader::trim_text`].
+    pub fn trim_text(mut self, enabled: bool) -> Self {
+        self.trim_text = Some(enabled);
+        self
+    }
+
+    /// Creates a configured reader for a string.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_str<'a>(&self, s: &'a str) -> XmlReader<'a> {
+        self.from_bytes(s.as_bytes())
+    }
+
+    /// Creates a configured reader for bytes.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_bytes<'a>(&self, input: &'a [u8]) -> XmlReader<'a> {
+        let mut reader = XmlReader::from_bytes(input);
+        reader.max_expanded_len = self.max_expanded_len;
+        reader.max_elements = self.max_elements;
+        reader.max_depth = self.max_depth;
+        reader.entities = self.entities.clone();
+        reader.parse_entities = self.parse_entities;
+        reader.html_entities = self.html_entities;
+        reader.track_ids = self.track_ids;
+        reader.void_elements = self.void_elements.clone();
+        reader.skip_unknown_constructs = self.skip_unknown_constructs;
+        reader.raw_values = self.raw_values;
+        reader.allow_duplicate_attributes = self.allow_duplicate_attributes;
+        reader.strict_names = self.strict_names;
+        reader.auto_close = self.auto_close;
+        reader.trim_text = self.trim_text.unwrap_or(true);
+        reader
+    }
+}
+
+/// Parses the pseudo-attributes in the data of a processing instruction, such
+/// as `href="style.xsl" type="text/xsl"` in `<?xml-stylesheet?>` or
+/// `<?xml-model?>`.
+///
+/// Values may be quoted with `"` or `'`, and entity and character references
+/// in them are expanded. Parsing stops at the first malformed pair, keeping
+/// the pairs before it.
+///
+/// # Example
+///
+/// ```
+/// use serde_xml::{parse_pi_pseudo_attrs, XmlEvent, XmlReader};
+///
+/// let mut reader = XmlReader::from_str(r#"<?xml-model href="doc.rng" type="application/xml"?>"#);
+/// if let XmlEvent::ProcessingInstruction { target, data } = reader.next_event().unwrap() {
+///     assert_eq!(target, "xml-model");
+///     let attrs = parse_pi_pseudo_attrs(data.as_deref().unwrap_or(""));
+///     assert_eq!(attrs[0], ("href".to_string(), "doc.rng".to_string()));
+/// }
+/// ```
+pub fn parse_pi_pseudo_attrs(data: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut rest = data.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        let value = match rest[name_end..].trim_start().strip_prefix('=') {
+            Some(value) if !name.is_empty() => value.trim_start(),
+            _ => break,
+        };
+        let quote = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => break,
+        };
+        let value = &value[1..];
+        let end = match value.find(quote) {
+            Some(end) => end,
+            None => break,
+        };
+        match unescape_resolving(&value[..end], Entities::default()) {
+            Ok(unescaped) => attrs.push((name.to_string(), unescaped.into_owned())),
+            Err(_) => break,
+        }
+        rest = value[end + 1..].trim_start();
+    }
+    attrs
+}
+
+/// Returns whether `s` is an XML `Name`.
+pub(crate) fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(is_name_start_char) && chars.all(is_name_char)
+}
+
+/// Returns whether `c` is an XML `NameStartChar`.
+fn is_name_start_char(c: char) -> bool {
+    matches!(c,
+        ':' | 'A'..='Z' | '_' | 'a'..='z'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}')
+}
+
+/// Returns whether `c` is an XML `NameChar`.
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c)
+        || matches!(c,
+            '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+}
+
+/// Returns the XML name at the start of `bytes`, or "" if there is none.
+fn leading_name(bytes: &[u8]) -> &str {
+    let len = bytes.iter().position(|&b| !IS_NAME_CHAR[b as usize]).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..len]).unwrap_or("")
+}
+
+/// Extracts internal general entity declarations from a DOCTYPE body.
+///
+/// Parameter entities and external (`SYSTEM`/`PUBLIC`) entities are skipped.
+fn parse_entity_decls(doctype: &str) -> Vec<(&str, &str)> {
+    let mut decls = Vec::new();
+    let mut rest = doctype;
+
+    while let Some(idx) = rest.find("<!ENTITY") {
+        rest = rest[idx + 8..].trim_start();
+
+        // Parameter entity: <!ENTITY % name ...>
+        if rest.starts_with('%') {
+            continue;
+        }
+
+        let name_len = rest
+            .bytes()
+            .position(|b| !IS_NAME_CHAR[b as usize])
+            .unwrap_or(rest.len());
+        if name_len == 0 {
+            continue;
+        }
+        let name = &rest[..name_len];
+        rest = rest[name_len..].trim_start();
+
+        let quote = match rest.as_bytes().first() {
+            Some(&q) if q == b'"' || q == b'\'' => q,
+            _ => continue,
+        };
+        if let Some(end) = memchr(quote, &rest.as_bytes()[1..]) {
+            decls.push((name, &rest[1..end + 1]));
+            rest = &rest[end + 2..];
+        }
+    }
+
+    decls
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -855,4 +2003,663 @@ mod tests {
         reader.next_event().unwrap(); // </c>
         assert_eq!(reader.depth(), 2);
     }
+
+    #[test]
+    fn test_utf8_bom() {
+        let xml = b"\xEF\xBB\xBF<?xml version=\"1.0\"?>\n<root>text</root>";
+        let mut reader = XmlReader::from_bytes(xml);
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::XmlDecl { .. }));
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::StartElement { .. }));
+        assert_eq!(reader.next_event().unwrap(), XmlEvent::Text(Cow::Borrowed("text")));
+
+        let mut reader = XmlReader::from_str("\u{feff}<root/>");
+        let event = reader.next_event().unwrap();
+        assert!(matches!(event, XmlEvent::EmptyElement { name, .. } if name == "root"));
+        assert_eq!(reader.next_event().unwrap(), XmlEvent::Eof);
+    }
+
+    #[test]
+    fn test_current_element() {
+        let mut reader = XmlReader::from_str("<a><b><c/>text<d>x</d></b></a>");
+        assert_eq!(reader.current_element(), None);
+        assert_eq!(reader.path().count(), 0);
+
+        reader.next_event().unwrap(); // <a>
+        assert_eq!(reader.current_element(), Some("a"));
+        reader.next_event().unwrap(); // <b>
+        assert_eq!(reader.current_element(), Some("b"));
+        reader.next_event().unwrap(); // <c/>
+        assert_eq!(reader.current_element(), Some("b"));
+        reader.next_event().unwrap(); // text
+        assert_eq!(reader.current_element(), Some("b"));
+        reader.next_event().unwrap(); // <d>
+        assert_eq!(reader.current_element(), Some("d"));
+        assert_eq!(reader.path().collect::<Vec<_>>(), ["a", "b", "d"]);
+        reader.next_event().unwrap(); // x
+        reader.next_event().unwrap(); // </d>
+        assert_eq!(reader.path().collect::<Vec<_>>(), ["a", "b"]);
+        reader.next_event().unwrap(); // </b>
+        reader.next_event().unwrap(); // </a>
+        assert_eq!(reader.current_element(), None);
+    }
+
+    #[test]
+    fn test_max_expanded_len() {
+        let xml = format!("<root>{}</root>", "&amp;".repeat(1000));
+        let mut reader = XmlReader::from_str(&xml).max_expanded_len(64);
+
+        reader.next_event().unwrap(); // <root>
+        let err = reader.next_event().unwrap_err();
+        assert!(matches!(err.kind(), crate::error::ErrorKind::Syntax(_)));
+    }
+
+    #[test]
+    fn test_max_expanded_len_counts_attributes() {
+        let xml = r#"<root a="0123456789" b="0123456789"/>"#;
+        let mut reader = XmlReader::from_str(xml).max_expanded_len(15);
+        assert!(reader.next_event().is_err());
+
+        let mut reader = XmlReader::from_str(xml).max_expanded_len(20);
+        assert!(reader.next_event().is_ok());
+    }
+
+    #[test]
+    fn test_parse_pi_pseudo_attrs() {
+        let xml = concat!(
+            "<?xml-stylesheet type=\"text/xsl\" href='style.xsl?v=1&amp;x=2'?>",
+            "<?xml-model href=\"schema.rng\"\n  type = \"application/xml\"\n",
+            "  schematypens=\"http://relaxng.org/ns/structure/1.0\"?>",
+            "<doc/>"
+        );
+        let mut reader = XmlReader::from_str(xml);
+        let mut pis = Vec::new();
+        loop {
+            match reader.next_event().unwrap() {
+                XmlEvent::ProcessingInstruction { target, data } => {
+                    let attrs = parse_pi_pseudo_attrs(data.as_deref().unwrap_or(""));
+                    pis.push((target.into_owned(), attrs));
+                }
+                XmlEvent::Eof => break,
+                _ => {}
+            }
+        }
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert_eq!(
+            pis,
+            [
+                (
+                    "xml-stylesheet".to_string(),
+                    vec![pair("type", "text/xsl"), pair("href", "style.xsl?v=1&x=2")]
+                ),
+                (
+                    "xml-model".to_string(),
+                    vec![
+                        pair("href", "schema.rng"),
+                        pair("type", "application/xml"),
+                        pair("schematypens", "http://relaxng.org/ns/structure/1.0"),
+                    ]
+                ),
+            ]
+        );
+
+        assert_eq!(parse_pi_pseudo_attrs(""), []);
+        assert_eq!(parse_pi_pseudo_attrs(r#"a="1" b=2 c="3""#), [pair("a", "1")]);
+        assert_eq!(parse_pi_pseudo_attrs(r#"a="1" ="2""#), [pair("a", "1")]);
+        assert_eq!(parse_pi_pseudo_attrs(r#"a="1" b="2"#), [pair("a", "1")]);
+    }
+
+    #[test]
+    fn test_last_span() {
+        let xml = "<root>\n  <item id=\"1\">text</item>\n  <!-- c --><br/>\n</root>";
+        let mut reader = XmlReader::from_str(xml);
+        let mut spans = Vec::new();
+        loop {
+            let event = reader.next_event().unwrap();
+            let span = reader.last_span();
+            assert_eq!(span.start.offset, reader.event_start().offset);
+            spans.push(&xml[span.start.offset..span.end.offset]);
+            if event == XmlEvent::Eof {
+                break;
+            }
+        }
+        assert_eq!(
+            spans,
+            ["<root>", "<item id=\"1\">", "text", "</item>", "<!-- c -->", "<br/>", "</root>", ""]
+        );
+
+        let mut reader = XmlReader::from_str(xml);
+        reader.next_event().unwrap();
+        reader.next_event().unwrap();
+        let span = reader.last_span();
+        let start = xml.find("<item").unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (start, start + 13));
+        assert_eq!((span.start.line, span.start.column), (2, 3));
+        assert_eq!((span.end.line, span.end.column), (2, 16));
+    }
+
+    #[test]
+    fn test_read_text_until_end() {
+        let mut reader = XmlReader::from_str("<user><name>Alice</name><id>7</id></user>");
+        reader.next_event().unwrap();
+        reader.next_event().unwrap();
+        let text = reader.read_text_until_end().unwrap();
+        assert_eq!(text, "Alice");
+        assert!(matches!(text, Cow::Borrowed(_)));
+        assert_eq!(reader.current_element(), Some("user"));
+        assert_eq!(
+            reader.next_event().unwrap(),
+            XmlEvent::StartElement { name: Cow::Borrowed("id"), attributes: vec![] }
+        );
+
+        let mut reader = XmlReader::from_str("<a>x &amp; <!-- c --><![CDATA[<y>]]></a><b/>");
+        reader.next_event().unwrap();
+        assert_eq!(reader.read_text_until_end().unwrap(), "x &<y>");
+        assert_eq!(reader.depth(), 0);
+
+        let mut reader = XmlReader::from_str("<a></a>");
+        reader.next_event().unwrap();
+        assert_eq!(reader.read_text_until_end().unwrap(), "");
+
+        let mut reader = XmlReader::from_str("<name><b/></name>");
+        reader.next_event().unwrap();
+        let err = reader.read_text_until_end().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UnexpectedElement(name) if name == "b"));
+        assert_eq!(err.position().unwrap().column, 7);
+
+        // An empty element has no text, and the next sibling is left alone
+        for xml in ["<r><a/><b>x</b></r>", "<r><br><b>x</b></r>"] {
+            let mut reader = XmlReader::from_str(xml).void_elements(["br"]);
+            reader.next_event().unwrap();
+            assert!(matches!(reader.next_event().unwrap(), XmlEvent::EmptyElement { .. }));
+            assert_eq!(reader.read_text_until_end().unwrap(), "");
+            let next = reader.next_event().unwrap();
+            assert!(matches!(next, XmlEvent::StartElement { name, .. } if name == "b"));
+        }
+
+        // Elsewhere there is no element to read, and nothing is consumed
+        let mut reader = XmlReader::from_str("<r><a>x</a><b>y</b></r>");
+        reader.next_event().unwrap();
+        reader.next_event().unwrap();
+        assert_eq!(reader.read_text_until_end().unwrap(), "x");
+        let err = reader.read_text_until_end().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Custom(_)));
+        let next = reader.next_event().unwrap();
+        assert!(matches!(next, XmlEvent::StartElement { name, .. } if name == "b"));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        fn read_all(xml: &str, limit: usize) -> Result<()> {
+            let mut reader = XmlReader::from_str(xml).max_depth(limit);
+            while reader.next_event()? != XmlEvent::Eof {}
+            Ok(())
+        }
+
+        assert!(read_all("<a><b><c/></b><b>x</b></a>", 3).is_ok());
+        let err = read_all("<a><b><c/></b></a>", 2).unwrap_err();
+        assert!(err.is_syntax());
+        assert_eq!(err.position().unwrap().column, 8);
+        assert!(read_all("<a><b><c></c></b></a>", 2).is_err());
+    }
+
+    #[test]
+    fn test_max_elements() {
+        fn read_all(xml: &str, limit: usize) -> Result<()> {
+            let mut reader = XmlReader::from_str(xml).max_elements(limit);
+            while reader.next_event()? != XmlEvent::Eof {}
+            Ok(())
+        }
+
+        let ok = format!("<r>{}</r>", "<i/>".repeat(99));
+        assert!(read_all(&ok, 100).is_ok());
+
+        let too_many = format!("<r>{}</r>", "<i/>".repeat(100));
+        let err = read_all(&too_many, 100).unwrap_err();
+        assert!(err.is_syntax());
+        assert!(err.to_string().contains("limit of 100 elements"));
+    }
+
+    #[test]
+    fn test_track_ids() {
+        let xml = "<doc>\n  <a xml:id=\"first\"/>\n  <b xml:id=\"second\"><c xml:id=\"third\">x</c></b>\n</doc>";
+        let mut reader = XmlReader::from_str(xml).track_ids(true);
+        while reader.next_event().unwrap() != XmlEvent::Eof {}
+
+        let ids = reader.ids();
+        assert_eq!(ids.len(), 3);
+        for (id, line, column) in [("first", 2, 3), ("second", 3, 3), ("third", 3, 22)] {
+            let pos = ids[id];
+            assert_eq!((pos.line, pos.column), (line, column), "{}", id);
+            assert_eq!(xml.as_bytes()[pos.offset], b'<');
+        }
+
+        let mut reader = XmlReader::from_str(xml);
+        while reader.next_event().unwrap() != XmlEvent::Eof {}
+        assert!(reader.ids().is_empty());
+    }
+
+    #[test]
+    fn test_builder_combined_options() {
+        fn read_all(reader: &mut XmlReader<'_>) -> Result<Vec<String>> {
+            let mut texts = Vec::new();
+            loop {
+                match reader.next_event()? {
+                    XmlEvent::Text(text) => texts.push(text.into_owned()),
+                    XmlEvent::Eof => return Ok(texts),
+                    _ => {}
+                }
+            }
+        }
+
+        let xml = r#"<!DOCTYPE d [<!ENTITY co "ACME">]><d xml:id="root"><a>&co;</a><b>&who;</b></d>"#;
+        let mut entities = HashMap::new();
+        entities.insert("who".to_string(), "me".to_string());
+        let builder = XmlReader::builder()
+            .parse_internal_entities(true)
+            .with_entities(entities)
+            .track_ids(true)
+            .max_elements(3)
+            .max_expanded_len(100);
+
+        let mut reader = builder.from_str(xml);
+        assert_eq!(read_all(&mut reader).unwrap(), vec!["ACME", "me"]);
+        assert!(reader.ids().contains_key("root"));
+
+        let err = read_all(&mut builder.clone().max_elements(2).from_str(xml)).unwrap_err();
+        assert!(err.to_string().contains("limit of 2 elements"));
+
+        let err = read_all(&mut builder.max_expanded_len(5).from_str(xml)).unwrap_err();
+        assert!(err.to_string().contains("limit of 5 bytes"));
+    }
+
+    #[test]
+    fn test_peek_kind() {
+        let xml = r#"<?xml version="1.0"?><!DOCTYPE r><?pi data?><r a="x>y"><!-- c --><br>
+            <e/><![CDATA[raw]]>text</br><i>1</i></r>"#;
+        let mut reader = XmlReader::from_str(xml).html_void_elements(true);
+        let mut kinds = Vec::new();
+        loop {
+            let kind = reader.peek_kind().unwrap();
+            assert_eq!(reader.peek_kind().unwrap(), kind);
+            let event = reader.next_event().unwrap();
+            assert_eq!(event.kind(), kind);
+            kinds.push(kind);
+            if kind == XmlEventKind::Eof {
+                break;
+            }
+        }
+        assert_eq!(
+            kinds,
+            [
+                XmlEventKind::XmlDecl,
+                XmlEventKind::ProcessingInstruction,
+                XmlEventKind::StartElement,
+                XmlEventKind::Comment,
+                XmlEventKind::EmptyElement,
+                XmlEventKind::EmptyElement,
+                XmlEventKind::CData,
+                XmlEventKind::Text,
+                XmlEventKind::StartElement,
+                XmlEventKind::Text,
+                XmlEventKind::EndElement,
+                XmlEventKind::EndElement,
+                XmlEventKind::Eof,
+            ]
+        );
+
+        let mut reader = XmlReader::from_str("<r>");
+        assert_eq!(reader.next_event().unwrap().kind(), XmlEventKind::StartElement);
+        assert!(reader.peek_kind().is_err());
+    }
+
+    #[test]
+    fn test_skip_unknown_constructs() {
+        let xml = "<root><![INCLUDE[<a>x</a><![IGNORE[ ]]> ]]><!ELEMENT b ANY><b/></root>";
+        let mut reader = XmlReader::from_str(xml);
+        reader.next_event().unwrap();
+        assert!(reader.next_event().is_err());
+
+        let mut reader = XmlReader::builder()
+            .skip_unknown_constructs(true)
+            .from_str(xml);
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::StartElement { .. }));
+        assert!(matches!(
+            reader.next_event().unwrap(),
+            XmlEvent::EmptyElement { name, .. } if name == "b"
+        ));
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::EndElement { .. }));
+        assert_eq!(reader.next_event().unwrap(), XmlEvent::Eof);
+
+        let mut reader = XmlReader::from_str("<root><![IGNORE[ x </root>")
+            .skip_unknown_constructs(true);
+        reader.next_event().unwrap();
+        assert!(reader.next_event().is_err());
+
+        // Long runs of skipped constructs don't exhaust the stack
+        let xml = format!("<root>{}</root>", "<![X[a]]>".repeat(200_000));
+        let mut reader = XmlReader::from_str(&xml).skip_unknown_constructs(true);
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::StartElement { .. }));
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::EndElement { .. }));
+    }
+
+    #[test]
+    fn test_duplicate_attributes() {
+        let mut reader = XmlReader::from_str(r#"<x a="1" b="2" a="3"/>"#);
+        let err = reader.next_event().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Syntax(msg) if msg == "duplicate attribute 'a'"));
+        assert_eq!(err.position().unwrap().column, 16);
+
+        // Names differing in prefix or case are distinct
+        let mut reader = XmlReader::from_str(r#"<x a="1" A="2" p:a="3"/>"#);
+        assert!(reader.next_event().is_ok());
+
+        let mut reader = XmlReader::from_str(r#"<x a="1" a="2"/>"#).allow_duplicate_attributes(true);
+        match reader.next_event().unwrap() {
+            XmlEvent::EmptyElement { attributes, .. } => assert_eq!(attributes.len(), 2),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        let mut reader = XmlReader::builder()
+            .allow_duplicate_attributes(true)
+            .from_str(r#"<x a="1" a="2"/>"#);
+        assert!(reader.next_event().is_ok());
+
+        // Elements with many attributes are checked through a set
+        let attrs: String = (0..100_000).map(|i| format!(" a{}=\"\"", i)).collect();
+        let xml = format!("<x{}/>", attrs);
+        match XmlReader::from_str(&xml).next_event().unwrap() {
+            XmlEvent::EmptyElement { attributes, .. } => assert_eq!(attributes.len(), 100_000),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        for dup in [3, 16, 50] {
+            let xml = format!("<x{} a{}=\"\"/>", &attrs[..attrs.find(" a60=").unwrap()], dup);
+            let err = XmlReader::from_str(&xml).next_event().unwrap_err();
+            let expected = format!("duplicate attribute 'a{}'", dup);
+            assert!(matches!(err.kind(), ErrorKind::Syntax(msg) if *msg == expected));
+        }
+    }
+
+    #[test]
+    fn test_strict_names() {
+        let mut reader = XmlReader::from_str("<café naïve=\"1\"><x·y/></café>").strict_names(true);
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::StartElement { .. }));
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::EmptyElement { .. }));
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::EndElement { .. }));
+
+        // An em dash is not a name character
+        let mut reader = XmlReader::from_str("<a\u{2014}b/>").strict_names(true);
+        let err = reader.next_event().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidName(_)));
+        assert_eq!(err.position().unwrap().offset, 2);
+
+        // A middle dot may not start a name
+        let mut reader = XmlReader::builder().strict_names(true).from_str("<r \u{B7}a=\"1\"/>");
+        assert!(reader.next_event().is_err());
+
+        let mut reader = XmlReader::from_str("<a\u{2014}b/>");
+        assert!(reader.next_event().is_ok());
+    }
+
+    #[test]
+    fn test_auto_close() {
+        let kinds = |xml: &str| {
+            let mut reader = XmlReader::from_str(xml).auto_close(true);
+            let mut events = Vec::new();
+            loop {
+                let kind = reader.peek_kind().unwrap();
+                let event = reader.next_event().unwrap();
+                assert_eq!(event.kind(), kind);
+                if kind == XmlEventKind::Eof {
+                    return events;
+                }
+                events.push(match event {
+                    XmlEvent::StartElement { name, .. } => format!("<{}>", name),
+                    XmlEvent::EndElement { name } => format!("</{}>", name),
+                    XmlEvent::Text(text) => text.into_owned(),
+                    other => panic!("unexpected event: {:?}", other),
+                });
+            }
+        };
+
+        assert_eq!(
+            kinds("<card><title>x</card>"),
+            ["<card>", "<title>", "x", "</title>", "</card>"]
+        );
+        assert_eq!(kinds("<a><b><c>x</a>"), ["<a>", "<b>", "<c>", "x", "</c>", "</b>", "</a>"]);
+        assert_eq!(kinds("<a>x</b></a>"), ["<a>", "x", "</a>"]);
+        // Long runs of stray end tags don't exhaust the stack
+        assert_eq!(kinds(&format!("<a>x{}</a>", "</b>".repeat(200_000))), ["<a>", "x", "</a>"]);
+        assert_eq!(kinds("<a><b>x"), ["<a>", "<b>", "x", "</b>", "</a>"]);
+
+        let mut reader = XmlReader::from_str("<card><title>x</card>");
+        reader.next_event().unwrap();
+        reader.next_event().unwrap();
+        reader.next_event().unwrap();
+        assert!(reader.next_event().is_err());
+    }
+
+    #[test]
+    fn test_trim_text() {
+        let texts = |reader: XmlReader<'static>| {
+            let mut reader = reader;
+            let mut texts = Vec::new();
+            loop {
+                let kind = reader.peek_kind().unwrap();
+                match reader.next_event().unwrap() {
+                    XmlEvent::Text(text) => {
+                        assert_eq!(kind, XmlEventKind::Text);
+                        texts.push(text.into_owned());
+                    }
+                    XmlEvent::Eof => return texts,
+                    event => assert_eq!(event.kind(), kind),
+                }
+            }
+        };
+        let xml = "<a>\n  <b> x &amp; y </b>\n  <c>z</c> tail\n</a>";
+
+        assert_eq!(texts(XmlReader::from_str(xml)), ["x & y", "z", "tail"]);
+        assert_eq!(
+            texts(XmlReader::from_str(xml).trim_text(false)),
+            [" x & y ", "z", " tail\n"]
+        );
+        assert_eq!(
+            texts(XmlReader::builder().trim_text(false).from_str(xml)),
+            [" x & y ", "z", " tail\n"]
+        );
+
+        let xml = "<a>\n  <b>   </b>\n  <c/>\n</a>";
+        assert!(texts(XmlReader::from_str(xml)).is_empty());
+        assert_eq!(texts(XmlReader::from_str(xml).trim_text(false)), ["   "]);
+    }
+
+    #[test]
+    fn test_raw_values() {
+        let xml = r#"<a href="?x=1&amp;y=&#50;"/>"#;
+        let attribute_value = |mut reader: XmlReader<'static>| match reader.next_event().unwrap() {
+            XmlEvent::EmptyElement { mut attributes, .. } => attributes.remove(0).value,
+            other => panic!("unexpected event: {:?}", other),
+        };
+
+        assert_eq!(attribute_value(XmlReader::from_str(xml)), "?x=1&y=2");
+
+        let raw = attribute_value(XmlReader::from_str(xml).raw_values(true));
+        assert!(matches!(raw, Cow::Borrowed("?x=1&amp;y=&#50;")));
+
+        let raw = attribute_value(XmlReader::builder().raw_values(true).from_str(xml));
+        assert_eq!(raw, "?x=1&amp;y=&#50;");
+    }
+
+    #[test]
+    fn test_html_void_elements() {
+        let xml = r#"<div><br><IMG src="a.png"></br></div>"#;
+        let mut reader = XmlReader::from_str(xml).html_void_elements(true);
+        let mut events = Vec::new();
+        loop {
+            match reader.next_event().unwrap() {
+                XmlEvent::Eof => break,
+                event => events.push(event),
+            }
+        }
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[1], XmlEvent::EmptyElement { name, .. } if name == "br"));
+        match &events[2] {
+            XmlEvent::EmptyElement { name, attributes } => {
+                assert_eq!(name, "IMG");
+                assert_eq!(attributes[0].value, "a.png");
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert!(matches!(&events[3], XmlEvent::EndElement { name } if name == "div"));
+
+        // Self-closing syntax still works, and XML mode is unchanged
+        let mut reader = XmlReader::from_str("<p><x/></p>").void_elements(["x"]);
+        while reader.next_event().unwrap() != XmlEvent::Eof {}
+        let mut reader = XmlReader::from_str("<div><br></div>");
+        while let Ok(event) = reader.next_event() {
+            assert_ne!(event, XmlEvent::Eof);
+        }
+
+        // Long runs of stray end tags don't exhaust the stack
+        let xml = format!("<div>{}</div>", "</br> ".repeat(200_000));
+        let mut reader = XmlReader::from_str(&xml).html_void_elements(true).trim_text(false);
+        assert!(matches!(reader.next_event().unwrap(), XmlEvent::StartElement { .. }));
+        let end = reader.next_event().unwrap();
+        assert!(matches!(end, XmlEvent::EndElement { name } if name == "div"));
+    }
+
+    #[test]
+    fn test_mismatched_tag_span() {
+        let xml = "<root>\n  <a>text</b>\n</root>";
+        let mut reader = XmlReader::from_str(xml);
+        let err = loop {
+            match reader.next_event() {
+                Ok(XmlEvent::Eof) => panic!("expected an error"),
+                Ok(_) => {}
+                Err(e) => break e,
+            }
+        };
+
+        let span = err.span().expect("mismatched tag has a span");
+        assert_eq!((span.start.line, span.start.column), (2, 3));
+        assert_eq!(&xml[span.start.offset..span.end.offset], "<a>text</b>");
+
+        let xml = "<root><a></a>";
+        let mut reader = XmlReader::from_str(xml);
+        let err = loop {
+            if let Err(e) = reader.next_event() {
+                break e;
+            }
+        };
+        let span = err.span().expect("unclosed tag has a span");
+        assert_eq!(&xml[span.start.offset..span.end.offset], xml);
+    }
+
+    #[test]
+    fn test_internal_entities() {
+        let xml = r#"<!DOCTYPE doc [
+            <!ENTITY company "ACME">
+            <!ENTITY full "&company; Corp">
+            <!ENTITY ext SYSTEM "http://example.com/ext.xml">
+        ]><doc name="&full;">&company;</doc>"#;
+        let mut reader = XmlReader::from_str(xml).parse_internal_entities(true);
+
+        match reader.next_event().unwrap() {
+            XmlEvent::StartElement { attributes, .. } => {
+                assert_eq!(attributes[0].value, "ACME Corp");
+            }
+            _ => panic!("expected StartElement"),
+        }
+        match reader.next_event().unwrap() {
+            XmlEvent::Text(text) => assert_eq!(text, "ACME"),
+            _ => panic!("expected Text"),
+        }
+        assert!(!reader.entities().contains_key("ext"));
+
+        // Without opting in, declarations are ignored
+        let mut reader = XmlReader::from_str(xml);
+        assert!(reader.next_event().is_err());
+    }
+
+    #[test]
+    fn test_with_entities() {
+        let mut entities = HashMap::new();
+        entities.insert("company".to_string(), "ACME".to_string());
+        let mut reader = XmlReader::from_str("<doc>&company;</doc>").with_entities(entities);
+
+        reader.next_event().unwrap(); // <doc>
+        match reader.next_event().unwrap() {
+            XmlEvent::Text(text) => assert_eq!(text, "ACME"),
+            _ => panic!("expected Text"),
+        }
+    }
+
+    #[cfg(feature = "html-entities")]
+    #[test]
+    fn test_html_entities() {
+        let xml = r#"<p title="&copy; ACME">a&nbsp;&mdash;&nbsp;b</p>"#;
+        let mut reader = XmlReader::from_str(xml).html_entities(true);
+
+        match reader.next_event().unwrap() {
+            XmlEvent::StartElement { attributes, .. } => {
+                assert_eq!(attributes[0].value, "\u{a9} ACME");
+            }
+            _ => panic!("expected StartElement"),
+        }
+        match reader.next_event().unwrap() {
+            XmlEvent::Text(text) => assert_eq!(text, "a\u{a0}\u{2014}\u{a0}b"),
+            _ => panic!("expected Text"),
+        }
+
+        assert!(XmlReader::from_str(xml).next_event().is_err());
+    }
+
+    #[test]
+    fn test_entity_expansion_budget() {
+        let xml = r#"<!DOCTYPE lolz [
+            <!ENTITY lol "lollollollollollollollollollol">
+            <!ENTITY lol1 "&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;">
+            <!ENTITY lol2 "&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;">
+            <!ENTITY lol3 "&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;">
+        ]><lolz>&lol3;&lol3;&lol3;</lolz>"#;
+        let mut reader = XmlReader::from_str(xml)
+            .parse_internal_entities(true)
+            .max_expanded_len(10_000);
+        assert!(reader.next_event().is_err());
+    }
+
+    #[test]
+    fn test_nested_entities_limited_by_default() {
+        let mut xml = String::from(r#"<!DOCTYPE lolz [<!ENTITY lol0 "lollollollol">"#);
+        for i in 1..10 {
+            let refs = format!("&lol{};", i - 1).repeat(10);
+            xml.push_str(&format!(r#"<!ENTITY lol{} "{}">"#, i, refs));
+        }
+        xml.push_str("]><lolz>&lol9;</lolz>");
+        let err = XmlReader::from_str(&xml)
+            .parse_internal_entities(true)
+            .next_event()
+            .unwrap_err();
+        assert!(err.to_string().contains("exceeds limit"), "{}", err);
+
+        // A single value made of many references fails before it is built
+        let xml = format!(
+            r#"<!DOCTYPE d [<!ENTITY a "{}"><!ENTITY b "{}">]><d/>"#,
+            "x".repeat(1000),
+            "&a;".repeat(100)
+        );
+        let mut reader = XmlReader::from_str(&xml)
+            .parse_internal_entities(true)
+            .max_expanded_len(50_000);
+        let err = reader.next_event().unwrap_err();
+        assert!(err.to_string().contains("exceeds limit of 50000 bytes"), "{}", err);
+
+        // Nesting within the limit expands fully
+        let xml = r#"<!DOCTYPE d [
+            <!ENTITY a "x">
+            <!ENTITY b "&a;&a;">
+            <!ENTITY c "&b;-&b;">
+        ]><d>&c;</d>"#;
+        let mut reader = XmlReader::from_str(xml).parse_internal_entities(true);
+        reader.next_event().unwrap(); // <d>
+        assert_eq!(reader.next_event().unwrap(), XmlEvent::Text("xx-xx".into()));
+    }
 }
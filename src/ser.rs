@@ -29,11 +29,71 @@
 //! let xml = to_string(&elem).unwrap();
 //! // Output: <Element id="main" class="container"><content>Hello</content></Element>
 //! ```
+//!
+//! Attributes are detected by the `@` prefix of the final key, after serde
+//! has applied any renaming. Since an explicit `rename` takes precedence over
+//! `rename_all`, an attribute's name is written exactly as given after the
+//! `@`, while other fields follow `rename_all`:
+//!
+//! ```rust
+//! use serde::Serialize;
+//! use serde_xml::to_string;
+//!
+//! #[derive(Serialize)]
+//! #[serde(rename_all = "camelCase")]
+//! struct User {
+//!     #[serde(rename = "@userId")]
+//!     user_id: u32,
+//!     display_name: String,
+//! }
+//!
+//! let user = User { user_id: 7, display_name: "Ann".to_string() };
+//! let xml = to_string(&user).unwrap();
+//! assert_eq!(xml, r#"<User userId="7"><displayName>Ann</displayName></User>"#);
+//! ```
+//!
+//! ## Comments
+//!
+//! A field renamed to `$comment` is written as an XML comment at its position
+//! among the element's children. A `Vec<String>` field writes one comment per
+//! item; on deserialization it receives the element's comments in order:
+//!
+//! ```rust
+//! use serde::Serialize;
+//! use serde_xml::to_string;
+//!
+//! #[derive(Serialize)]
+//! struct Config {
+//!     host: String,
+//!     #[serde(rename = "$comment")]
+//!     note: &'static str,
+//!     port: u16,
+//! }
+//!
+//! let config = Config { host: "localhost".to_string(), note: "port must be > 1024", port: 8080 };
+//! let xml = to_string(&config).unwrap();
+//! assert_eq!(
+//!     xml,
+//!     "<Config><host>localhost</host><!-- port must be > 1024 --><port>8080</port></Config>"
+//! );
+//! ```
+//!
+//! ## Maps
+//!
+//! Map entries are written like struct fields, so a `serde_json::Value`
+//! object becomes an element whose `@`-prefixed keys are attributes, whose
+//! arrays are repeated child elements and whose `null`s are empty elements.
 
 use crate::error::{Error, Result};
-use crate::escape::escape;
+use crate::escape::{escape_with, reject_control_chars, ControlChars, EscapeConfig};
+use crate::outer_xml::OUTER_XML_NAME;
+use crate::present::PRESENT_NAME;
+use crate::value::VALUE_NAME;
 use serde::ser::{self, Serialize};
+use std::borrow::Cow;
+use std::fmt;
 use std::io::Write;
+use std::sync::Arc;
 
 /// Serializes a value to an XML string.
 ///
@@ -67,6 +127,9 @@ where
 }
 
 /// Serializes a value to an XML string with a root element name.
+///
+/// The root name replaces the type name of a top-level struct and names the
+/// element wrapping a top-level map.
 pub fn to_string_with_root<T>(value: &T, root: &str) -> Result<String>
 where
     T: Serialize + ?Sized,
@@ -76,7 +139,84 @@ where
     Ok(serializer.into_string())
 }
 
+/// Serializes a value to a pretty-printed XML string, indenting nested
+/// elements by `indent`.
+///
+/// # Example
+///
+/// ```
+/// use serde::Serialize;
+/// use serde_xml::to_string_pretty;
+///
+/// #[derive(Serialize)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// let xml = to_string_pretty(&Point { x: 1, y: 2 }, "  ").unwrap();
+/// assert_eq!(xml, "<Point>\n  <x>1</x>\n  <y>2</y>\n</Point>");
+/// ```
+pub fn to_string_pretty<T>(value: &T, indent: &str) -> Result<String>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer::new().with_indent(indent);
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_string())
+}
+
+/// Serializes a value to a canonical XML string, for signing or comparing
+/// documents byte for byte.
+///
+/// This covers a subset of Canonical XML: attributes are sorted by name with
+/// namespace declarations first, empty elements are written as start and end
+/// tag pairs, and line breaks in text and attribute values are normalized to
+/// `\n`. No declaration or indentation is written. See
+/// [`Serializer::canonical`].
+///
+/// # Example
+///
+/// ```
+/// use serde::Serialize;
+/// use serde_xml::to_string_canonical;
+///
+/// #[derive(Serialize)]
+/// struct Link {
+///     #[serde(rename = "@rel")]
+///     rel: String,
+///     #[serde(rename = "@href")]
+///     href: String,
+/// }
+///
+/// let link = Link { rel: "next".to_string(), href: "/2".to_string() };
+/// let xml = to_string_canonical(&link).unwrap();
+/// assert_eq!(xml, r#"<Link href="/2" rel="next"></Link>"#);
+/// ```
+pub fn to_string_canonical<T>(value: &T) -> Result<String>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer::new().canonical(true);
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_string())
+}
+
+/// Serializes a value to an XML string with the given options.
+///
+/// See [`SerializeOptions`].
+pub fn to_string_with_options<T>(value: &T, options: &SerializeOptions) -> Result<String>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer::with_options(options.clone())?;
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_string())
+}
+
 /// Serializes a value to XML bytes.
+///
+/// See [`to_vec_with_options`] to apply serializer options.
 pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
 where
     T: Serialize + ?Sized,
@@ -84,78 +224,624 @@ where
     Ok(to_string(value)?.into_bytes())
 }
 
+/// Serializes a value to XML bytes with the given options.
+///
+/// See [`SerializeOptions`].
+pub fn to_vec_with_options<T>(value: &T, options: &SerializeOptions) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    Ok(to_string_with_options(value, options)?.into_bytes())
+}
+
+/// Serializes a value to pretty-printed XML bytes.
+///
+/// See [`to_string_pretty`].
+pub fn to_vec_pretty<T>(value: &T, indent: &str) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    Ok(to_string_pretty(value, indent)?.into_bytes())
+}
+
 /// Serializes a value to a writer.
+///
+/// The output is written out as it is produced rather than built up first.
+/// On error, part of the document may already have been written.
 pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
 where
     W: Write,
     T: Serialize + ?Sized,
 {
-    let xml = to_string(value)?;
+    to_writer_with_options(writer, value, &SerializeOptions::default())
+}
+
+/// Serializes a value to a writer with the given options.
+///
+/// See [`SerializeOptions`] and [`to_writer`].
+pub fn to_writer_with_options<W, T>(writer: W, value: &T, options: &SerializeOptions) -> Result<()>
+where
+    W: Write,
+    T: Serialize + ?Sized,
+{
     let mut writer = writer;
-    writer.write_all(xml.as_bytes())?;
-    Ok(())
+    let mut serializer = Serializer::with_options(options.clone())?;
+    if let Some(declaration) = serializer.take_declaration() {
+        writer.write_all(declaration.as_bytes())?;
+    }
+    serializer.sink = Some(&mut writer);
+    value.serialize(&mut serializer)?;
+    serializer.flush_output(0)
+}
+
+/// How `Option::None` fields are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoneMode {
+    /// Omit the element entirely.
+    #[default]
+    Omit,
+    /// Write an empty element: `<value/>`.
+    EmptyElement,
+    /// Write an empty element marked nil: `<value xsi:nil="true"/>`. The root
+    /// element declares the `xsi` prefix.
+    XsiNil,
+}
+
+/// How elements with empty text content are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyStyle {
+    /// A start and end tag: `<value></value>`.
+    #[default]
+    Expanded,
+    /// A self-closing tag: `<value/>`.
+    SelfClosing,
+}
+
+/// How floating-point numbers are written.
+///
+/// Whatever the format, non-finite values are written as in XML Schema:
+/// `NaN`, `INF` and `-INF`. All three read back as the same value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatFormat {
+    /// The shortest form that reads back as the same value: `19.9`.
+    #[default]
+    Shortest,
+    /// A fixed number of decimal places: `Fixed(2)` writes `19.90`.
+    Fixed(u8),
+    /// Scientific notation: `1.99e1`.
+    Scientific,
+}
+
+impl FloatFormat {
+    /// Formats `v`, using `buffer` for the shortest form.
+    fn format(self, v: f64, buffer: &mut ryu::Buffer) -> Cow<'_, str> {
+        if v.is_nan() {
+            return Cow::Borrowed("NaN");
+        }
+        if v.is_infinite() {
+            return Cow::Borrowed(if v > 0.0 { "INF" } else { "-INF" });
+        }
+        match self {
+            FloatFormat::Shortest => Cow::Borrowed(buffer.format(v)),
+            FloatFormat::Fixed(precision) => Cow::Owned(format!("{:.*}", precision as usize, v)),
+            FloatFormat::Scientific => Cow::Owned(format!("{:e}", v)),
+        }
+    }
+}
+
+/// How enum variants are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnumRepr {
+    /// An element named after the variant: `<Circle>...</Circle>`.
+    #[default]
+    Element,
+    /// The field's element, typed by the variant name:
+    /// `<shape xsi:type="Circle">...</shape>`. The root element declares the
+    /// `xsi` prefix.
+    XsiType,
+}
+
+/// How tuple fields are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TupleRepr {
+    /// An element named by the field per item, as for a sequence:
+    /// `<pair>1</pair><pair>2</pair>`.
+    #[default]
+    Repeated,
+    /// The field's element holding an element per item:
+    /// `<pair><item>1</item><item>2</item></pair>`. Items that are structs
+    /// need this to read back, as their children are taken by position.
+    Items,
+}
+
+/// A hook for transforming values as the serializer writes them.
+///
+/// All methods default to leaving the value unchanged.
+///
+/// ```
+/// use serde::Serialize;
+/// use serde_xml::{SerializeHook, Serializer};
+/// use std::borrow::Cow;
+///
+/// struct Upper;
+///
+/// impl SerializeHook for Upper {
+///     fn transform_text<'a>(&self, _element: Option<&str>, text: &'a str) -> Cow<'a, str> {
+///         Cow::Owned(text.to_uppercase())
+///     }
+/// }
+///
+/// #[derive(Serialize)]
+/// struct Greeting {
+///     text: String,
+/// }
+///
+/// let mut ser = Serializer::new().with_hook(Upper);
+/// Greeting { text: "hello".to_string() }.serialize(&mut ser).unwrap();
+/// assert_eq!(ser.into_string(), "<Greeting><text>HELLO</text></Greeting>");
+/// ```
+pub trait SerializeHook {
+    /// Transforms text content before it is escaped and written.
+    ///
+    /// `element` is the name of the element the text belongs to, if known.
+    fn transform_text<'a>(&self, element: Option<&str>, text: &'a str) -> Cow<'a, str> {
+        let _ = element;
+        Cow::Borrowed(text)
+    }
+}
+
+/// A [`SerializeHook`] that can be shared between serializers, for
+/// [`SerializeOptions::hook`].
+///
+/// Two handles are equal when they share the same hook.
+#[derive(Clone)]
+pub struct SharedHook(Arc<dyn SerializeHook + Send + Sync>);
+
+impl SharedHook {
+    /// Wraps `hook` for sharing.
+    pub fn new<H>(hook: H) -> Self
+    where
+        H: SerializeHook + Send + Sync + 'static,
+    {
+        Self(Arc::new(hook))
+    }
+}
+
+impl fmt::Debug for SharedHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedHook(..)")
+    }
+}
+
+impl PartialEq for SharedHook {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
 }
 
+impl Eq for SharedHook {}
+
+/// Settings for [`Serializer`], gathered in one value.
+///
+/// Each field matches a [`Serializer`] builder method; the defaults give the
+/// same output as [`to_string`].
+///
+/// # Example
+///
+/// ```
+/// use serde::Serialize;
+/// use serde_xml::{to_string_with_options, FloatFormat, SerializeOptions};
+///
+/// #[derive(Serialize)]
+/// struct Point {
+///     x: f64,
+/// }
+///
+/// let options = SerializeOptions {
+///     indent: Some("  ".to_string()),
+///     float_format: FloatFormat::Fixed(2),
+///     ..Default::default()
+/// };
+/// let xml = to_string_with_options(&Point { x: 1.5 }, &options).unwrap();
+/// assert_eq!(xml, "<Point>\n  <x>1.50</x>\n</Point>");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SerializeOptions {
+    /// Root element name, replacing the type name of a top-level value. See
+    /// [`Serializer::with_root`].
+    pub root: Option<String>,
+    /// Encoding label of the XML declaration, if one is written. See
+    /// [`Serializer::with_declaration_encoding`].
+    pub declaration: Option<String>,
+    /// Indentation string for pretty-printing. See [`Serializer::with_indent`].
+    pub indent: Option<String>,
+    /// How `None` values are written.
+    pub none_mode: NoneMode,
+    /// How floats are written.
+    pub float_format: FloatFormat,
+    /// How enum variants are written.
+    pub enum_repr: EnumRepr,
+    /// How tuple fields are written.
+    pub tuple_repr: TupleRepr,
+    /// Whether elements without content or attributes are omitted. See
+    /// [`Serializer::skip_empty_elements`].
+    pub skip_empty_elements: bool,
+    /// How elements with empty text content are written.
+    pub empty_style: EmptyStyle,
+    /// Whether output is written in canonical form. See
+    /// [`Serializer::canonical`].
+    pub canonical: bool,
+    /// Whether whitespace runs in text content are collapsed. See
+    /// [`Serializer::collapse_text_whitespace`].
+    pub collapse_text_whitespace: bool,
+    /// Whether each attribute is written on its own line. See
+    /// [`Serializer::attributes_one_per_line`].
+    pub attributes_one_per_line: bool,
+    /// Whether map entries are written sorted by key. See
+    /// [`Serializer::sort_map_keys`].
+    pub sort_map_keys: bool,
+    /// How control characters in text and attribute values are handled. See
+    /// [`Serializer::control_chars`].
+    pub control_chars: ControlChars,
+    /// Hook that can transform text before it is written. See
+    /// [`Serializer::with_hook`].
+    pub hook: Option<SharedHook>,
+}
+
+/// The number of bytes of output a serializer writing to a sink buffers
+/// before writing them out.
+const FLUSH_SIZE: usize = 8 * 1024;
+
 /// The XML serializer.
-pub struct Serializer {
+pub struct Serializer<'w> {
     output: String,
-    /// Root element name (for when we don't have type name info).
-    root: Option<String>,
+    /// Writer the output is streamed to as it grows, if any.
+    sink: Option<&'w mut dyn Write>,
+    /// Length of the output already written to the sink.
+    flushed: usize,
+    /// Whether the output written to the sink ended with a newline.
+    flushed_newline: bool,
+    /// Output settings.
+    options: SerializeOptions,
     /// Current element name.
     current_element: Option<String>,
     /// Stack of element names for nested structures.
     element_stack: Vec<String>,
-    /// Whether we're serializing a map key.
-    is_key: bool,
     /// Current key for map entries.
     current_key: Option<String>,
-    /// Whether to include XML declaration.
-    include_declaration: bool,
     /// Indentation level.
     indent_level: usize,
-    /// Indentation string.
-    indent_str: Option<String>,
+    /// Length of the output when markup was last written; text written
+    /// since then keeps the closing tag on the same line.
+    markup_end: usize,
+    /// Variant name to write as `xsi:type` on the next element.
+    xsi_type: Option<String>,
+    /// Set by [`Present`](crate::Present) to the element depth of its field,
+    /// whose `None` is then written as an empty element.
+    present: Option<usize>,
+    /// Whether this serializer writes a `$value` field, whose sequence
+    /// items may be written in place.
+    value_seq: bool,
+    /// Set while writing an item of a `$value` sequence.
+    value_item: bool,
+    /// Set for a map whose entries are written without its own element.
+    in_place: bool,
 }
 
-impl Serializer {
+impl<'w> Serializer<'w> {
     /// Creates a new serializer.
     pub fn new() -> Self {
         Self {
             output: String::new(),
-            root: None,
+            sink: None,
+            flushed: 0,
+            flushed_newline: false,
+            options: SerializeOptions::default(),
             current_element: None,
             element_stack: Vec::new(),
-            is_key: false,
             current_key: None,
-            include_declaration: false,
             indent_level: 0,
-            indent_str: None,
+            markup_end: 0,
+            xsi_type: None,
+            present: None,
+            value_seq: false,
+            value_item: false,
+            in_place: false,
         }
     }
 
     /// Creates a new serializer with a root element name.
     pub fn with_root(root: &str) -> Self {
-        Self {
-            root: Some(root.to_string()),
-            ..Self::new()
+        let mut serializer = Self::new();
+        serializer.options.root = Some(root.to_string());
+        serializer
+    }
+
+    /// Creates a new serializer with the given options.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `options.declaration` is not a valid encoding name; see
+    /// [`with_declaration_encoding`](Self::with_declaration_encoding).
+    pub fn with_options(options: SerializeOptions) -> Result<Self> {
+        if let Some(ref label) = options.declaration {
+            check_encoding_name(label)?;
         }
+        Ok(Self {
+            options,
+            ..Self::new()
+        })
     }
 
     /// Enables pretty-printing with the given indentation.
     pub fn with_indent(mut self, indent: &str) -> Self {
-        self.indent_str = Some(indent.to_string());
+        self.options.indent = Some(indent.to_string());
         self
     }
 
     /// Includes XML declaration in the output.
     pub fn with_declaration(mut self) -> Self {
-        self.include_declaration = true;
+        self.options.declaration = Some("UTF-8".to_string());
+        self
+    }
+
+    /// Includes an XML declaration naming the given encoding label.
+    ///
+    /// The label is written verbatim (e.g. `utf-8` stays lowercase); the output
+    /// itself is always UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `label` is not an XML encoding name: an ASCII letter followed
+    /// by letters, digits, `.`, `_` or `-`.
+    pub fn with_declaration_encoding(mut self, label: &str) -> Result<Self> {
+        check_encoding_name(label)?;
+        self.options.declaration = Some(label.to_string());
+        Ok(self)
+    }
+
+    /// Sets how `Option::None` fields are written.
+    pub fn with_none_as(mut self, mode: NoneMode) -> Self {
+        self.options.none_mode = mode;
+        self
+    }
+
+    /// Sets how `f32` and `f64` values are written.
+    pub fn with_float_format(mut self, format: FloatFormat) -> Self {
+        self.options.float_format = format;
+        self
+    }
+
+    /// Sets how enum variants are written.
+    pub fn with_enum_repr(mut self, repr: EnumRepr) -> Self {
+        self.options.enum_repr = repr;
+        self
+    }
+
+    /// Sets how tuple fields are written.
+    pub fn with_tuple_repr(mut self, repr: TupleRepr) -> Self {
+        self.options.tuple_repr = repr;
+        self
+    }
+
+    /// Omits fields whose content is an empty string, such as
+    /// `<value></value>`, the way `None` is omitted.
+    ///
+    /// Elements carrying attributes are still written.
+    pub fn skip_empty_elements(mut self, enabled: bool) -> Self {
+        self.options.skip_empty_elements = enabled;
+        self
+    }
+
+    /// Sets how elements with empty text content, such as an empty `String`
+    /// field, are written.
+    pub fn with_empty_element_style(mut self, style: EmptyStyle) -> Self {
+        self.options.empty_style = style;
+        self
+    }
+
+    /// Writes canonical output: attributes sorted by name with namespace
+    /// declarations first, no self-closing tags, `\r\n` or `\r` line breaks
+    /// in text normalized to `\n`, and tabs and line breaks in attribute
+    /// values written as `&#x9;`, `&#xA;` and `&#xD;`.
+    ///
+    /// Indentation and the declaration are still written if configured.
+    pub fn canonical(mut self, enabled: bool) -> Self {
+        self.options.canonical = enabled;
+        self
+    }
+
+    /// Trims text content and collapses each run of whitespace inside it to
+    /// a single space before writing, e.g. `"  a   b  "` becomes `"a b"`.
+    ///
+    /// This changes the data, so it is off by default. Attribute values are
+    /// left as they are.
+    pub fn collapse_text_whitespace(mut self, enabled: bool) -> Self {
+        self.options.collapse_text_whitespace = enabled;
+        self
+    }
+
+    /// Writes every attribute on its own line, indented one level deeper than
+    /// its element, so a changed attribute is a one-line diff.
+    ///
+    /// Uses the configured indentation, or two spaces if there is none.
+    pub fn attributes_one_per_line(mut self, enabled: bool) -> Self {
+        self.options.attributes_one_per_line = enabled;
+        self
+    }
+
+    /// Writes map entries sorted by key, so a `HashMap` gives the same output
+    /// on every run.
+    ///
+    /// Attributes and child elements are sorted separately. Entries are
+    /// buffered until the map ends.
+    pub fn sort_map_keys(mut self, enabled: bool) -> Self {
+        self.options.sort_map_keys = enabled;
+        self
+    }
+
+    /// Sets how control characters other than tab, CR and LF are handled in
+    /// text and attribute values.
+    ///
+    /// By default serializing fails, since XML 1.0 cannot represent them.
+    /// [`ControlChars::Strip`] drops them instead, and [`ControlChars::Escape`]
+    /// writes character references that only XML 1.1 parsers accept.
+    pub fn control_chars(mut self, mode: ControlChars) -> Self {
+        self.options.control_chars = mode;
+        self
+    }
+
+    /// Installs a hook that can transform text before it is written.
+    pub fn with_hook<H>(mut self, hook: H) -> Self
+    where
+        H: SerializeHook + Send + Sync + 'static,
+    {
+        self.options.hook = Some(SharedHook::new(hook));
         self
     }
 
     /// Returns the serialized XML string.
-    pub fn into_string(self) -> String {
-        self.output
+    pub fn into_string(mut self) -> String {
+        match self.take_declaration() {
+            Some(declaration) => declaration + &self.output,
+            None => self.output,
+        }
+    }
+
+    /// Takes the XML declaration to write before the output, if one is set,
+    /// with the line break that follows it when indenting.
+    fn take_declaration(&mut self) -> Option<String> {
+        let label = self.options.declaration.take()?;
+        let separator = if self.options.indent.is_some() { "\n" } else { "" };
+        Some(format!("<?xml version=\"1.0\" encoding=\"{}\"?>{}", label, separator))
+    }
+
+    /// Returns the length of the output, including any part already written
+    /// to the sink.
+    #[inline]
+    fn output_len(&self) -> usize {
+        self.flushed + self.output.len()
+    }
+
+    /// Writes the buffered output to the sink, if there is one, once at
+    /// least `min` bytes are buffered.
+    fn flush_output(&mut self, min: usize) -> Result<()> {
+        if let Some(sink) = self.sink.as_mut() {
+            if !self.output.is_empty() && self.output.len() >= min {
+                sink.write_all(self.output.as_bytes())?;
+                self.flushed += self.output.len();
+                self.flushed_newline = self.output.ends_with('\n');
+                self.output.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the serialized XML as UTF-8 bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.into_string().into_bytes()
+    }
+
+    /// Clears the mark left by [`Present`](crate::Present), returning whether
+    /// it was set for the value at the current depth.
+    fn take_present(&mut self) -> bool {
+        if self.present == Some(self.element_stack.len()) {
+            self.present = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Creates a serializer for content nested inside `element` that is
+    /// rendered separately, sharing this serializer's settings.
+    fn nested(&self, element: &str) -> Serializer<'static> {
+        Serializer {
+            element_stack: vec![element.to_string()],
+            options: SerializeOptions {
+                root: None,
+                declaration: None,
+                indent: None,
+                ..self.options.clone()
+            },
+            ..Serializer::new()
+        }
+    }
+
+    /// Creates a serializer for a child of `element` that is rendered
+    /// separately and inserted later, sharing this serializer's settings and
+    /// indentation.
+    fn detached_child(&self, element: &str) -> Serializer<'static> {
+        let mut element_stack = self.element_stack.clone();
+        element_stack.push(element.to_string());
+        Serializer {
+            element_stack,
+            indent_level: self.indent_level + 1,
+            options: SerializeOptions {
+                root: None,
+                declaration: None,
+                ..self.options.clone()
+            },
+            ..Serializer::new()
+        }
+    }
+
+    /// Applies the hook, if any, to text about to be written.
+    #[inline]
+    fn transform_text<'a>(&self, element: Option<&str>, text: &'a str) -> Cow<'a, str> {
+        match self.options.hook {
+            Some(ref hook) => hook.0.transform_text(element, text),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// Escapes text or an attribute value for output.
+    fn escape<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let config = EscapeConfig {
+            control_chars: self.options.control_chars,
+            ..EscapeConfig::default()
+        };
+        escape_with(s, &config)
+    }
+
+    /// Fails if `s` has a control character and they are rejected.
+    fn check_control_chars(&self, s: &str) -> Result<()> {
+        match self.options.control_chars {
+            ControlChars::Reject => reject_control_chars(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Prepares text content for writing: applies the hook, then normalizes
+    /// line breaks and collapses whitespace as configured.
+    fn prepare_text<'a>(&self, element: Option<&str>, text: &'a str) -> Cow<'a, str> {
+        let text = match self.transform_text(element, text) {
+            Cow::Borrowed(text) => self.normalize_newlines(text),
+            Cow::Owned(text) => Cow::Owned(self.normalize_newlines(&text).into_owned()),
+        };
+        if self.options.collapse_text_whitespace && !is_collapsed(&text) {
+            Cow::Owned(text.split_whitespace().collect::<Vec<_>>().join(" "))
+        } else {
+            text
+        }
+    }
+
+    /// Takes the pending `xsi:type` as an attribute list.
+    fn take_type_attr(&mut self) -> Vec<(String, String)> {
+        self.xsi_type
+            .take()
+            .map(|variant| ("xsi:type".to_string(), variant))
+            .into_iter()
+            .collect()
+    }
+
+    /// Returns the element name for a variant written with
+    /// [`EnumRepr::XsiType`].
+    fn typed_element_name(&mut self, name: &str) -> String {
+        self.current_key
+            .take()
+            .or_else(|| self.options.root.clone())
+            .unwrap_or_else(|| name.to_string())
     }
 
     /// Writes an opening tag.
@@ -163,24 +849,69 @@ impl Serializer {
         self.write_indent();
         self.output.push('<');
         self.output.push_str(name);
+        self.write_attrs(&[]);
         self.output.push('>');
+        self.markup_end = self.output_len();
         self.element_stack.push(name.to_string());
         self.indent_level += 1;
     }
 
-    /// Writes an opening tag with attributes.
-    fn write_start_tag_with_attrs(&mut self, name: &str, attrs: &[(String, String)]) {
-        self.write_indent();
-        self.output.push('<');
-        self.output.push_str(name);
+    /// Writes the attributes of a tag, sorted if output is canonical.
+    ///
+    /// A root element also declares the `xsi` prefix if the options may use
+    /// it and the element doesn't declare it itself.
+    fn write_attrs(&mut self, attrs: &[(String, String)]) {
+        let xsi_decl;
+        let mut attrs: Vec<_> = attrs.iter().collect();
+        if self.element_stack.is_empty()
+            && (self.options.none_mode == NoneMode::XsiNil
+                || self.options.enum_repr == EnumRepr::XsiType)
+            && !attrs.iter().any(|(name, _)| name == "xmlns:xsi")
+        {
+            xsi_decl = ("xmlns:xsi".to_string(), XSI_NAMESPACE.to_string());
+            attrs.insert(0, &xsi_decl);
+        }
+        if self.options.canonical {
+            attrs.sort_by_key(|(name, _)| (!is_namespace_decl(name), name.as_str()));
+        }
         for (attr_name, attr_value) in attrs {
-            self.output.push(' ');
+            let mut attr_value = self.escape(attr_value);
+            if self.options.canonical {
+                attr_value = escape_attr_whitespace(attr_value);
+            }
+            if self.options.attributes_one_per_line {
+                self.output.push('\n');
+                let indent = self.options.indent.as_deref().unwrap_or("  ");
+                for _ in 0..=self.indent_level {
+                    self.output.push_str(indent);
+                }
+            } else {
+                self.output.push(' ');
+            }
             self.output.push_str(attr_name);
             self.output.push_str("=\"");
-            self.output.push_str(&escape(attr_value));
+            self.output.push_str(&attr_value);
             self.output.push('"');
         }
+    }
+
+    /// Normalizes line breaks to `\n` if output is canonical.
+    fn normalize_newlines<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.options.canonical && text.contains('\r') {
+            Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Writes an opening tag with attributes.
+    fn write_start_tag_with_attrs(&mut self, name: &str, attrs: &[(String, String)]) {
+        self.write_indent();
+        self.output.push('<');
+        self.output.push_str(name);
+        self.write_attrs(attrs);
         self.output.push('>');
+        self.markup_end = self.output_len();
         self.element_stack.push(name.to_string());
         self.indent_level += 1;
     }
@@ -190,60 +921,108 @@ impl Serializer {
         self.indent_level = self.indent_level.saturating_sub(1);
 
         if let Some(name) = self.element_stack.pop() {
-            self.write_indent();
+            // Text content keeps its closing tag on the same line
+            if self.output_len() == self.markup_end {
+                self.write_indent();
+            }
             self.output.push_str("</");
             self.output.push_str(&name);
             self.output.push('>');
+            self.markup_end = self.output_len();
         }
     }
 
     /// Writes an empty element.
     fn write_empty_element(&mut self, name: &str) {
-        self.write_indent();
-        self.output.push('<');
-        self.output.push_str(name);
-        self.output.push_str("/>");
+        let attrs = self.take_type_attr();
+        self.write_empty_element_with_attrs(name, &attrs);
     }
 
-    /// Writes an empty element with attributes.
+    /// Writes an empty element with attributes, expanded if output is
+    /// canonical.
     fn write_empty_element_with_attrs(&mut self, name: &str, attrs: &[(String, String)]) {
         self.write_indent();
         self.output.push('<');
         self.output.push_str(name);
-        for (attr_name, attr_value) in attrs {
-            self.output.push(' ');
-            self.output.push_str(attr_name);
-            self.output.push_str("=\"");
-            self.output.push_str(&escape(attr_value));
-            self.output.push('"');
+        self.write_attrs(attrs);
+        if self.options.canonical {
+            self.output.push_str("></");
+            self.output.push_str(name);
+            self.output.push('>');
+        } else {
+            self.output.push_str("/>");
+        }
+        self.markup_end = self.output_len();
+    }
+
+    /// Writes an element whose text content is empty, in the configured style.
+    fn write_empty_text_element(&mut self, name: &str, attrs: &[(String, String)]) {
+        match self.options.empty_style {
+            EmptyStyle::Expanded => {
+                self.write_start_tag_with_attrs(name, attrs);
+                // Keep the end tag on the same line
+                self.indent_level = self.indent_level.saturating_sub(1);
+                self.element_stack.pop();
+                self.output.push_str("</");
+                self.output.push_str(name);
+                self.output.push('>');
+                self.markup_end = self.output_len();
+            }
+            EmptyStyle::SelfClosing => self.write_empty_element_with_attrs(name, attrs),
         }
-        self.output.push_str("/>");
     }
 
     /// Writes a complete element with text content.
     fn write_element(&mut self, name: &str, content: &str) {
+        let content = self.prepare_text(Some(name), content);
+        let attrs = self.take_type_attr();
+        if content.is_empty() {
+            if !self.options.skip_empty_elements || !attrs.is_empty() {
+                self.write_empty_text_element(name, &attrs);
+            }
+            return;
+        }
         self.write_indent();
         self.output.push('<');
         self.output.push_str(name);
+        self.write_attrs(&attrs);
         self.output.push('>');
-        self.output.push_str(&escape(content));
+        let content = self.escape(&content);
+        self.output.push_str(&content);
         self.output.push_str("</");
         self.output.push_str(name);
         self.output.push('>');
+        self.markup_end = self.output_len();
+    }
+
+    /// Writes a comment.
+    fn write_comment(&mut self, comment: &str) {
+        self.write_indent();
+        self.output.push_str("<!-- ");
+        self.output.push_str(comment);
+        self.output.push_str(" -->");
+        self.markup_end = self.output_len();
     }
 
     /// Writes text content.
     fn write_text(&mut self, content: &str) {
-        self.output.push_str(&escape(content));
+        let element = self.element_stack.last().map(String::as_str);
+        let content = self.prepare_text(element, content);
+        let content = self.escape(&content);
+        self.output.push_str(&content);
     }
 
     /// Writes indentation if configured.
     fn write_indent(&mut self) {
-        if let Some(ref indent) = self.indent_str {
-            if !self.output.is_empty() && !self.output.ends_with('\n') {
+        if let Some(ref indent) = self.options.indent {
+            let at_line_start = match self.output.as_bytes().last() {
+                Some(&last) => last == b'\n',
+                None => self.flushed == 0 || self.flushed_newline,
+            };
+            if !at_line_start {
                 self.output.push('\n');
             }
-            for _ in 0..self.indent_level.saturating_sub(1) {
+            for _ in 0..self.indent_level {
                 self.output.push_str(indent);
             }
         }
@@ -254,28 +1033,28 @@ impl Serializer {
         self.current_key
             .clone()
             .or_else(|| self.current_element.clone())
-            .or_else(|| self.root.clone())
+            .or_else(|| self.options.root.clone())
             .unwrap_or_else(|| fallback.to_string())
     }
 }
 
-impl Default for Serializer {
+impl Default for Serializer<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl<'a, 'w> ser::Serializer for &'a mut Serializer<'w> {
     type Ok = ();
     type Error = Error;
 
-    type SerializeSeq = SeqSerializer<'a>;
-    type SerializeTuple = SeqSerializer<'a>;
-    type SerializeTupleStruct = SeqSerializer<'a>;
-    type SerializeTupleVariant = SeqSerializer<'a>;
-    type SerializeMap = MapSerializer<'a>;
-    type SerializeStruct = StructSerializer<'a>;
-    type SerializeStructVariant = StructSerializer<'a>;
+    type SerializeSeq = SeqSerializer<'a, 'w>;
+    type SerializeTuple = SeqSerializer<'a, 'w>;
+    type SerializeTupleStruct = SeqSerializer<'a, 'w>;
+    type SerializeTupleVariant = SeqSerializer<'a, 'w>;
+    type SerializeMap = MapSerializer<'a, 'w>;
+    type SerializeStruct = StructSerializer<'a, 'w>;
+    type SerializeStructVariant = StructSerializer<'a, 'w>;
 
     fn serialize_bool(self, v: bool) -> Result<()> {
         let text = if v { "true" } else { "false" };
@@ -339,11 +1118,11 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     fn serialize_f64(self, v: f64) -> Result<()> {
         let mut buffer = ryu::Buffer::new();
-        let text = buffer.format(v);
+        let text = self.options.float_format.format(v, &mut buffer);
         if let Some(ref key) = self.current_key.take() {
-            self.write_element(key, text);
+            self.write_element(key, &text);
         } else {
-            self.write_text(text);
+            self.write_text(&text);
         }
         Ok(())
     }
@@ -351,6 +1130,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     fn serialize_char(self, v: char) -> Result<()> {
         let mut buf = [0u8; 4];
         let text = v.encode_utf8(&mut buf);
+        self.check_control_chars(text)?;
         if let Some(ref key) = self.current_key.take() {
             self.write_element(key, text);
         } else {
@@ -360,10 +1140,8 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        if self.is_key {
-            self.current_key = Some(v.to_string());
-            self.is_key = false;
-        } else if let Some(ref key) = self.current_key.take() {
+        self.check_control_chars(v)?;
+        if let Some(ref key) = self.current_key.take() {
             self.write_element(key, v);
         } else {
             self.write_text(v);
@@ -387,8 +1165,18 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_none(self) -> Result<()> {
-        // Don't output anything for None
-        self.current_key = None;
+        let present = self.take_present();
+        if let Some(ref key) = self.current_key.take() {
+            let none_mode = if present { NoneMode::EmptyElement } else { self.options.none_mode };
+            match none_mode {
+                NoneMode::Omit => {}
+                NoneMode::EmptyElement => self.write_empty_element(key),
+                NoneMode::XsiNil => self.write_empty_element_with_attrs(
+                    key,
+                    &[("xsi:nil".to_string(), "true".to_string())],
+                ),
+            }
+        }
         Ok(())
     }
 
@@ -396,6 +1184,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: Serialize + ?Sized,
     {
+        self.take_present();
         value.serialize(self)
     }
 
@@ -414,10 +1203,16 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     fn serialize_unit_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<()> {
+        if self.options.enum_repr == EnumRepr::XsiType {
+            let elem_name = self.typed_element_name(name);
+            self.xsi_type = Some(variant.to_string());
+            self.write_empty_element(&elem_name);
+            return Ok(());
+        }
         if let Some(ref key) = self.current_key.take() {
             self.write_element(key, variant);
         } else {
@@ -430,13 +1225,41 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: Serialize + ?Sized,
     {
+        if name == PRESENT_NAME {
+            // The field's own `None` is written as an empty element
+            self.present = Some(self.element_stack.len());
+            let result = value.serialize(&mut *self);
+            self.present = None;
+            return result;
+        }
+        if name == OUTER_XML_NAME {
+            // Captured markup replaces the field's element
+            let mut xml_ser = AttrValueSerializer::new(self.options.float_format);
+            value.serialize(&mut xml_ser)?;
+            self.current_key = None;
+            self.write_indent();
+            self.output.push_str(&xml_ser.into_string());
+            self.markup_end = self.output_len();
+            return Ok(());
+        }
+        if name == VALUE_NAME {
+            if std::mem::take(&mut self.value_item) && self.element_stack.len() == 1 {
+                // Text is written as text and an element as its children
+                self.current_key = None;
+                self.in_place = true;
+                let result = value.serialize(&mut *self);
+                self.in_place = false;
+                return result;
+            }
+            return value.serialize(self);
+        }
         self.current_element = Some(name.to_string());
         value.serialize(self)
     }
 
     fn serialize_newtype_variant<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
         variant: &'static str,
         value: &T,
@@ -444,22 +1267,63 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        self.write_start_tag(variant);
+        if self.options.enum_repr == EnumRepr::XsiType {
+            // The value's own element carries the variant name
+            self.current_key = Some(self.typed_element_name(name));
+            self.xsi_type = Some(variant.to_string());
+            value.serialize(&mut *self)?;
+            if self.xsi_type.take().is_some() {
+                return Err(Error::unsupported(format!(
+                    "variant '{}' has no element to carry its xsi:type",
+                    variant
+                )));
+            }
+            return Ok(());
+        }
+        // A field holding the enum wraps the variant element
+        let key = self.current_key.take();
+        if let Some(ref key) = key {
+            self.write_start_tag(key);
+        }
+        self.current_key = Some(variant.to_string());
         value.serialize(&mut *self)?;
-        self.write_end_tag();
+        if key.is_some() {
+            self.write_end_tag();
+        }
         Ok(())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        if self.xsi_type.is_some() {
+            return Err(Error::unsupported("xsi:type on a sequence"));
+        }
         let element_name = self.current_key.take().unwrap_or_else(|| "item".to_string());
         Ok(SeqSerializer {
             ser: self,
             element_name,
+            open_tags: 0,
         })
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
-        self.serialize_seq(Some(len))
+        if self.xsi_type.is_some() {
+            return Err(Error::unsupported("xsi:type on a sequence"));
+        }
+        // A tuple field holds its items by position inside the field element
+        match self.current_key.take() {
+            Some(key) if self.options.tuple_repr == TupleRepr::Items => {
+                self.write_start_tag(&key);
+                Ok(SeqSerializer {
+                    ser: self,
+                    element_name: "item".to_string(),
+                    open_tags: 1,
+                })
+            }
+            key => {
+                self.current_key = key;
+                self.serialize_seq(Some(len))
+            }
+        }
     }
 
     fn serialize_tuple_struct(
@@ -467,55 +1331,118 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        self.write_start_tag(name);
+        let name = self.current_key.take().unwrap_or_else(|| name.to_string());
+        let attrs = self.take_type_attr();
+        self.write_start_tag_with_attrs(&name, &attrs);
         Ok(SeqSerializer {
             ser: self,
             element_name: "item".to_string(),
+            open_tags: 1,
         })
     }
 
     fn serialize_tuple_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
+        if self.options.enum_repr == EnumRepr::XsiType {
+            let elem_name = self.typed_element_name(name);
+            let attrs = [("xsi:type".to_string(), variant.to_string())];
+            self.write_start_tag_with_attrs(&elem_name, &attrs);
+            return Ok(SeqSerializer {
+                ser: self,
+                element_name: "item".to_string(),
+                open_tags: 1,
+            });
+        }
+        // A field holding the enum wraps the variant element
+        let mut open_tags = 1;
+        if let Some(key) = self.current_key.take() {
+            self.write_start_tag(&key);
+            open_tags += 1;
+        }
         self.write_start_tag(variant);
         Ok(SeqSerializer {
             ser: self,
             element_name: "item".to_string(),
+            open_tags,
         })
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        let name = self.current_key.take()
-            .or_else(|| self.root.clone())
+        let in_place = std::mem::take(&mut self.in_place);
+        let elem_name = self.current_key.take()
+            .or_else(|| self.options.root.clone())
             .unwrap_or_else(|| "map".to_string());
-        self.write_start_tag(&name);
-        Ok(MapSerializer { ser: self })
+        let sorted = self.options.sort_map_keys.then(Vec::new);
+        Ok(MapSerializer {
+            inner: StructSerializer {
+                attrs: self.take_type_attr(),
+                ser: self,
+                elem_name,
+                children: Vec::new(),
+                text_content: None,
+                started: in_place,
+                wrapped: false,
+                in_place,
+            },
+            key: None,
+            sorted,
+        })
     }
 
     fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
-        let elem_name = self.current_key.take().unwrap_or_else(|| name.to_string());
+        let elem_name = match self.current_key.take() {
+            Some(key) => key,
+            // The root name replaces the type name of the outermost struct
+            None if self.element_stack.is_empty() => {
+                self.options.root.clone().unwrap_or_else(|| name.to_string())
+            }
+            None => name.to_string(),
+        };
         // Don't write start tag yet - collect attributes first
         Ok(StructSerializer {
+            attrs: self.take_type_attr(),
             ser: self,
             elem_name,
-            attrs: Vec::new(),
             children: Vec::new(),
             text_content: None,
             started: false,
+            wrapped: false,
+            in_place: false,
         })
     }
 
     fn serialize_struct_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
+        if self.options.enum_repr == EnumRepr::XsiType {
+            return Ok(StructSerializer {
+                elem_name: self.typed_element_name(name),
+                ser: self,
+                attrs: vec![("xsi:type".to_string(), variant.to_string())],
+                children: Vec::new(),
+                text_content: None,
+                started: false,
+                wrapped: false,
+                in_place: false,
+            });
+        }
+        // A field holding the enum wraps the variant element
+        let wrapped = match self.current_key.take() {
+            Some(key) => {
+                self.write_start_tag(&key);
+                true
+            }
+            None => false,
+        };
         Ok(StructSerializer {
             ser: self,
             elem_name: variant.to_string(),
@@ -523,6 +1450,8 @@ impl<'a> ser::Serializer for &'a mut Serializer {
             children: Vec::new(),
             text_content: None,
             started: false,
+            wrapped,
+            in_place: false,
         })
     }
 }
@@ -530,23 +1459,73 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 /// Simple serializer for attribute values (no XML escaping - escaping done at output).
 struct AttrValueSerializer {
     output: String,
+    float_format: FloatFormat,
+    /// Items of a sequence, if sequences are accepted.
+    items: Option<Vec<String>>,
 }
 
 impl AttrValueSerializer {
-    fn new() -> Self {
-        Self { output: String::new() }
+    fn new(float_format: FloatFormat) -> Self {
+        Self {
+            output: String::new(),
+            float_format,
+            items: None,
+        }
+    }
+
+    /// Creates a serializer that also accepts a sequence of values.
+    fn list(float_format: FloatFormat) -> Self {
+        Self {
+            items: Some(Vec::new()),
+            ..Self::new(float_format)
+        }
     }
 
     fn into_string(self) -> String {
         self.output
     }
+
+    /// Returns the items of a sequence, or the single value if there was one.
+    fn into_items(self) -> Vec<String> {
+        let mut items = self.items.unwrap_or_default();
+        if !self.output.is_empty() {
+            items.push(self.output);
+        }
+        items
+    }
+}
+
+/// Collects the items of a sequence serialized by [`AttrValueSerializer::list`].
+struct AttrItemSerializer<'a> {
+    ser: &'a mut AttrValueSerializer,
 }
 
-impl ser::Serializer for &mut AttrValueSerializer {
+impl<'a> ser::SerializeSeq for AttrItemSerializer<'a> {
     type Ok = ();
     type Error = Error;
 
-    type SerializeSeq = ser::Impossible<(), Error>;
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut item_ser = AttrValueSerializer::new(self.ser.float_format);
+        value.serialize(&mut item_ser)?;
+        if let Some(ref mut items) = self.ser.items {
+            items.push(item_ser.into_string());
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut AttrValueSerializer {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = AttrItemSerializer<'a>;
     type SerializeTuple = ser::Impossible<(), Error>;
     type SerializeTupleStruct = ser::Impossible<(), Error>;
     type SerializeTupleVariant = ser::Impossible<(), Error>;
@@ -580,7 +1559,8 @@ impl ser::Serializer for &mut AttrValueSerializer {
     fn serialize_f32(self, v: f32) -> Result<()> { self.serialize_f64(v as f64) }
     fn serialize_f64(self, v: f64) -> Result<()> {
         let mut buffer = ryu::Buffer::new();
-        self.output.push_str(buffer.format(v));
+        let text = self.float_format.format(v, &mut buffer);
+        self.output.push_str(&text);
         Ok(())
     }
 
@@ -615,7 +1595,10 @@ impl ser::Serializer for &mut AttrValueSerializer {
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        Err(Error::unsupported("sequence in attribute"))
+        if self.items.is_none() {
+            return Err(Error::unsupported("sequence in attribute"));
+        }
+        Ok(AttrItemSerializer { ser: self })
     }
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
         Err(Error::unsupported("tuple in attribute"))
@@ -638,12 +1621,24 @@ impl ser::Serializer for &mut AttrValueSerializer {
 }
 
 /// Sequence serializer.
-pub struct SeqSerializer<'a> {
-    ser: &'a mut Serializer,
+pub struct SeqSerializer<'a, 'w> {
+    ser: &'a mut Serializer<'w>,
     element_name: String,
+    /// Number of enclosing elements opened for the sequence, closed at the end.
+    open_tags: usize,
+}
+
+impl<'a, 'w> SeqSerializer<'a, 'w> {
+    /// Closes the enclosing elements.
+    fn close(self) -> Result<()> {
+        for _ in 0..self.open_tags {
+            self.ser.write_end_tag();
+        }
+        Ok(())
+    }
 }
 
-impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
+impl<'a, 'w> ser::SerializeSeq for SeqSerializer<'a, 'w> {
     type Ok = ();
     type Error = Error;
 
@@ -652,15 +1647,19 @@ impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
         T: Serialize + ?Sized,
     {
         self.ser.current_key = Some(self.element_name.clone());
-        value.serialize(&mut *self.ser)
+        self.ser.value_item = self.ser.value_seq && self.ser.element_stack.len() == 1;
+        let result = value.serialize(&mut *self.ser);
+        self.ser.value_item = false;
+        result?;
+        self.ser.flush_output(FLUSH_SIZE)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.close()
     }
 }
 
-impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
+impl<'a, 'w> ser::SerializeTuple for SeqSerializer<'a, 'w> {
     type Ok = ();
     type Error = Error;
 
@@ -676,7 +1675,7 @@ impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
+impl<'a, 'w> ser::SerializeTupleStruct for SeqSerializer<'a, 'w> {
     type Ok = ();
     type Error = Error;
 
@@ -689,12 +1688,11 @@ impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
     }
 
     fn end(self) -> Result<()> {
-        self.ser.write_end_tag();
-        Ok(())
+        self.close()
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
+impl<'a, 'w> ser::SerializeTupleVariant for SeqSerializer<'a, 'w> {
     type Ok = ();
     type Error = Error;
 
@@ -707,17 +1705,23 @@ impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
     }
 
     fn end(self) -> Result<()> {
-        self.ser.write_end_tag();
-        Ok(())
+        self.close()
     }
 }
 
 /// Map serializer.
-pub struct MapSerializer<'a> {
-    ser: &'a mut Serializer,
+///
+/// Entries are written like struct fields: keys name child elements, and
+/// `@`-prefixed, `$value` and `$comment` keys have the same meaning.
+pub struct MapSerializer<'a, 'w> {
+    inner: StructSerializer<'a, 'w>,
+    /// Key of the entry whose value is serialized next.
+    key: Option<String>,
+    /// Child elements rendered so far with their keys, if entries are sorted.
+    sorted: Option<Vec<(String, String)>>,
 }
 
-impl<'a> ser::SerializeMap for MapSerializer<'a> {
+impl<'a, 'w> ser::SerializeMap for MapSerializer<'a, 'w> {
     type Ok = ();
     type Error = Error;
 
@@ -725,34 +1729,66 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
     where
         T: Serialize + ?Sized,
     {
-        self.ser.is_key = true;
-        key.serialize(&mut *self.ser)
+        let mut key_ser = AttrValueSerializer::new(self.inner.ser.options.float_format);
+        key.serialize(&mut key_ser)?;
+        self.key = Some(key_ser.into_string());
+        Ok(())
     }
 
     fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
         T: Serialize + ?Sized,
     {
-        value.serialize(&mut *self.ser)
+        let key = self.key.take().unwrap_or_default();
+        match self.sorted {
+            Some(ref mut sorted) if !key.starts_with(['@', '$']) => {
+                let mut child = self.inner.ser.detached_child(&self.inner.elem_name);
+                child.current_key = Some(key.clone());
+                value.serialize(&mut child)?;
+                if !child.output.is_empty() {
+                    sorted.push((key, child.output));
+                }
+                Ok(())
+            }
+            _ => self.inner.serialize_entry(&key, value),
+        }
     }
 
-    fn end(self) -> Result<()> {
-        self.ser.write_end_tag();
-        Ok(())
+    fn end(mut self) -> Result<()> {
+        if let Some(mut sorted) = self.sorted.take() {
+            self.inner.attrs.sort_by(|(a, _), (b, _)| a.cmp(b));
+            if !sorted.is_empty() {
+                sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+                self.inner.ensure_started();
+                for (_, child) in sorted {
+                    if self.inner.ser.options.indent.is_some() {
+                        self.inner.ser.output.push('\n');
+                    }
+                    self.inner.ser.output.push_str(&child);
+                    self.inner.ser.markup_end = self.inner.ser.output_len();
+                }
+            }
+        }
+        self.inner.finish()
     }
 }
 
 /// Struct serializer with attribute support.
-pub struct StructSerializer<'a> {
-    ser: &'a mut Serializer,
+pub struct StructSerializer<'a, 'w> {
+    ser: &'a mut Serializer<'w>,
     elem_name: String,
     attrs: Vec<(String, String)>,
     children: Vec<String>,
     text_content: Option<String>,
     started: bool,
+    /// Whether the element is wrapped in an element named by the field key,
+    /// which is closed after it.
+    wrapped: bool,
+    /// Whether only the entries are written, without the element itself.
+    in_place: bool,
 }
 
-impl<'a> StructSerializer<'a> {
+impl<'a, 'w> StructSerializer<'a, 'w> {
     fn ensure_started(&mut self) {
         if !self.started {
             self.ser.write_start_tag_with_attrs(&self.elem_name, &self.attrs);
@@ -760,26 +1796,28 @@ impl<'a> StructSerializer<'a> {
             for child in &self.children {
                 self.ser.output.push_str(child);
             }
+            self.ser.markup_end = self.ser.output_len();
             self.children.clear();
+            // Text that preceded the first child element stays in front of it
+            if let Some(text) = self.text_content.take() {
+                self.ser.output.push_str(&text);
+            }
             self.started = true;
         }
     }
-}
-
-impl<'a> ser::SerializeStruct for StructSerializer<'a> {
-    type Ok = ();
-    type Error = Error;
 
-    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    /// Writes one field or map entry.
+    fn serialize_entry<T>(&mut self, key: &str, value: &T) -> Result<()>
     where
         T: Serialize + ?Sized,
     {
         // Check if this is an attribute (starts with @)
         if let Some(attr_name) = key.strip_prefix('@') {
             // Serialize value to string - use a special mode that doesn't escape
-            let mut attr_ser = AttrValueSerializer::new();
+            let mut attr_ser = AttrValueSerializer::new(self.ser.options.float_format);
             value.serialize(&mut attr_ser)?;
             let attr_value = attr_ser.into_string();
+            self.ser.check_control_chars(&attr_value)?;
             self.attrs.push((attr_name.to_string(), attr_value));
             return Ok(());
         }
@@ -787,9 +1825,33 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
         // Check if this is text content ($value or $text)
         if key == "$value" || key == "$text" {
             // Serialize value to string
-            let mut text_ser = Serializer::new();
+            let mut text_ser = self.ser.nested(&self.elem_name);
+            text_ser.value_seq = true;
             value.serialize(&mut text_ser)?;
-            self.text_content = Some(text_ser.into_string());
+            let text = text_ser.into_string();
+            if self.started {
+                self.ser.output.push_str(&text);
+            } else {
+                self.text_content = Some(text);
+            }
+            return Ok(());
+        }
+
+        // Check if this is a comment placed among the children
+        if key == "$comment" {
+            let mut comment_ser = AttrValueSerializer::list(self.ser.options.float_format);
+            value.serialize(&mut comment_ser)?;
+            for comment in comment_ser.into_items() {
+                if comment.is_empty() {
+                    continue;
+                }
+                // The text is padded with spaces, so an edge `-` is fine
+                if comment.contains("--") {
+                    return Err(Error::invalid_value("comment must not contain '--'"));
+                }
+                self.ensure_started();
+                self.ser.write_comment(&comment);
+            }
             return Ok(());
         }
 
@@ -799,7 +1861,17 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
         value.serialize(&mut *self.ser)
     }
 
-    fn end(self) -> Result<()> {
+    /// Writes whatever of the element is still pending and closes it.
+    fn finish(self) -> Result<()> {
+        if self.in_place {
+            if !self.attrs.is_empty() {
+                return Err(Error::unsupported("attributes on an item of a $value sequence"));
+            }
+            if let Some(text) = self.text_content {
+                self.ser.output.push_str(&text);
+            }
+            return Ok(());
+        }
         if self.started {
             // Write text content if any
             if let Some(text) = self.text_content {
@@ -809,6 +1881,14 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
         } else if self.attrs.is_empty() && self.text_content.is_none() {
             // Empty element with no attributes
             self.ser.write_empty_element(&self.elem_name);
+        } else if self.ser.options.skip_empty_elements
+            && self.attrs.is_empty()
+            && self.text_content.as_deref() == Some("")
+            && !self.ser.element_stack.is_empty()
+        {
+            // Field whose text content is empty
+        } else if self.text_content.as_deref() == Some("") {
+            self.ser.write_empty_text_element(&self.elem_name, &self.attrs);
         } else if let Some(text) = self.text_content {
             // Element with just text content and possibly attributes
             self.ser.write_start_tag_with_attrs(&self.elem_name, &self.attrs);
@@ -818,11 +1898,30 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
             // Element with only attributes
             self.ser.write_empty_element_with_attrs(&self.elem_name, &self.attrs);
         }
-        Ok(())
+        if self.wrapped {
+            self.ser.write_end_tag();
+        }
+        self.ser.flush_output(FLUSH_SIZE)
     }
 }
 
-impl<'a> ser::SerializeStructVariant for StructSerializer<'a> {
+impl<'a, 'w> ser::SerializeStruct for StructSerializer<'a, 'w> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.serialize_entry(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<'a, 'w> ser::SerializeStructVariant for StructSerializer<'a, 'w> {
     type Ok = ();
     type Error = Error;
 
@@ -838,6 +1937,62 @@ impl<'a> ser::SerializeStructVariant for StructSerializer<'a> {
     }
 }
 
+/// Fails unless `label` matches the XML `EncName` production,
+/// `[A-Za-z] ([A-Za-z0-9._] | '-')*`.
+fn check_encoding_name(label: &str) -> Result<()> {
+    let mut bytes = label.bytes();
+    let valid = matches!(bytes.next(), Some(b) if b.is_ascii_alphabetic())
+        && bytes.all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::invalid_value(format!("invalid encoding name '{}'", label)))
+    }
+}
+
+/// Writes tabs and line breaks in an escaped attribute value as character
+/// references, which attribute-value normalization leaves intact.
+fn escape_attr_whitespace(value: Cow<'_, str>) -> Cow<'_, str> {
+    if !value.contains(['\t', '\n', '\r']) {
+        return value;
+    }
+    let mut out = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        match c {
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Returns true if `text` has no leading, trailing or repeated whitespace and
+/// no whitespace other than spaces.
+fn is_collapsed(text: &str) -> bool {
+    let mut previous_space = true;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if c != ' ' || previous_space {
+                return false;
+            }
+            previous_space = true;
+        } else {
+            previous_space = false;
+        }
+    }
+    !previous_space || text.is_empty()
+}
+
+/// The namespace of the `xsi:nil` and `xsi:type` attributes.
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Returns true for `xmlns` and `xmlns:prefix` attribute names.
+pub(crate) fn is_namespace_decl(name: &str) -> bool {
+    name == "xmlns" || name.starts_with("xmlns:")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -978,6 +2133,134 @@ mod tests {
         assert!(!xml.contains("<value>"));
     }
 
+    #[test]
+    fn test_serialize_none_modes() {
+        #[derive(Serialize)]
+        struct Config {
+            name: String,
+            value: Option<String>,
+        }
+
+        let config = Config {
+            name: "test".to_string(),
+            value: None,
+        };
+        let render = |mode| {
+            let mut ser = Serializer::new().with_none_as(mode);
+            config.serialize(&mut ser).unwrap();
+            ser.into_string()
+        };
+
+        assert_eq!(render(NoneMode::Omit), "<Config><name>test</name></Config>");
+        assert_eq!(
+            render(NoneMode::EmptyElement),
+            "<Config><name>test</name><value/></Config>"
+        );
+        assert_eq!(
+            render(NoneMode::XsiNil),
+            concat!(
+                r#"<Config xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+                r#"<name>test</name><value xsi:nil="true"/></Config>"#,
+            )
+        );
+
+        // A declaration of the prefix on the root isn't repeated
+        #[derive(Serialize)]
+        struct Declared {
+            #[serde(rename = "@xmlns:xsi")]
+            xsi: &'static str,
+            value: Option<i32>,
+        }
+        let declared = Declared { xsi: "urn:xsi", value: None };
+        let mut ser = Serializer::new().with_none_as(NoneMode::XsiNil);
+        declared.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            r#"<Declared xmlns:xsi="urn:xsi"><value xsi:nil="true"/></Declared>"#
+        );
+    }
+
+    #[test]
+    fn test_serialize_hook() {
+        struct Upper;
+
+        impl SerializeHook for Upper {
+            fn transform_text<'a>(&self, _element: Option<&str>, text: &'a str) -> Cow<'a, str> {
+                Cow::Owned(text.to_uppercase())
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Link {
+            #[serde(rename = "@href")]
+            href: String,
+            #[serde(rename = "$value")]
+            text: String,
+        }
+
+        #[derive(Serialize)]
+        struct Page {
+            title: String,
+            tags: Vec<String>,
+            link: Link,
+        }
+
+        let page = Page {
+            title: "home".to_string(),
+            tags: vec!["a".to_string(), "b".to_string()],
+            link: Link {
+                href: "/about".to_string(),
+                text: "about us".to_string(),
+            },
+        };
+
+        let mut ser = Serializer::new().with_hook(Upper);
+        page.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            r#"<Page><title>HOME</title><tags>A</tags><tags>B</tags><link href="/about">ABOUT US</link></Page>"#
+        );
+
+        let options = SerializeOptions {
+            hook: Some(SharedHook::new(Upper)),
+            ..Default::default()
+        };
+        assert_eq!(
+            to_string_with_options(&page, &options).unwrap(),
+            r#"<Page><title>HOME</title><tags>A</tags><tags>B</tags><link href="/about">ABOUT US</link></Page>"#
+        );
+        let hook = SharedHook::new(Upper);
+        assert_eq!(hook, hook.clone());
+        assert_ne!(hook, SharedHook::new(Upper));
+    }
+
+    #[test]
+    fn test_serialize_hook_element_name() {
+        struct TwoDecimals;
+
+        impl SerializeHook for TwoDecimals {
+            fn transform_text<'a>(&self, element: Option<&str>, text: &'a str) -> Cow<'a, str> {
+                match (element, text.parse::<f64>()) {
+                    (Some("price"), Ok(v)) => Cow::Owned(format!("{:.2}", v)),
+                    _ => Cow::Borrowed(text),
+                }
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Item {
+            price: f64,
+            weight: f64,
+        }
+
+        let mut ser = Serializer::new().with_hook(TwoDecimals);
+        Item { price: 3.5, weight: 1.25 }.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            "<Item><price>3.50</price><weight>1.25</weight></Item>"
+        );
+    }
+
     #[test]
     fn test_serialize_vector() {
         #[derive(Serialize)]
@@ -1030,6 +2313,33 @@ mod tests {
         assert!(xml.contains("&lt;"));
     }
 
+    #[test]
+    fn test_control_chars() {
+        #[derive(Serialize)]
+        struct Note {
+            #[serde(rename = "@title")]
+            title: String,
+            body: String,
+        }
+
+        let note = Note { title: "a\u{7}b".to_string(), body: "\u{0}x\u{1B}".to_string() };
+        let mut ser = Serializer::new().control_chars(ControlChars::Strip);
+        note.serialize(&mut ser).unwrap();
+        assert_eq!(ser.into_string(), r#"<Note title="ab"><body>x</body></Note>"#);
+
+        let mut ser = Serializer::new().control_chars(ControlChars::Escape);
+        note.serialize(&mut ser).unwrap();
+        assert_eq!(ser.into_string(), r#"<Note title="a&#x7;b"><body>x&#x1B;</body></Note>"#);
+
+        for note in [
+            Note { title: "a\u{7}b".to_string(), body: String::new() },
+            Note { title: String::new(), body: "\u{1B}".to_string() },
+        ] {
+            let err = to_string(&note).unwrap_err();
+            assert!(err.to_string().contains("control character"), "{}", err);
+        }
+    }
+
     #[test]
     fn test_serialize_bool() {
         #[derive(Serialize)]
@@ -1093,6 +2403,22 @@ mod tests {
         assert!(xml.contains("<status>Active</status>") || xml.contains("<Active/>"));
     }
 
+    #[test]
+    fn test_serialize_enum_rename_all() {
+        #[derive(Serialize)]
+        #[serde(rename_all = "snake_case")]
+        enum Kind {
+            CamelCase,
+            NewType(String),
+        }
+
+        assert_eq!(to_string(&Kind::CamelCase).unwrap(), "<camel_case/>");
+        assert_eq!(
+            to_string(&Kind::NewType("x".to_string())).unwrap(),
+            "<new_type>x</new_type>"
+        );
+    }
+
     #[test]
     fn test_serialize_unit_struct() {
         #[derive(Serialize)]
@@ -1159,10 +2485,28 @@ mod tests {
         };
 
         let xml = to_string_with_root(&data, "root").unwrap();
-        // The struct name takes precedence, but root is used for maps
+        assert!(xml.contains("<root>"));
+        assert!(xml.contains("</root>"));
+        assert!(!xml.contains("<Data>"));
         assert!(xml.contains("<value>test</value>"));
     }
 
+    #[test]
+    fn test_with_root_nested_struct() {
+        #[derive(Serialize)]
+        struct Inner {
+            value: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Outer {
+            inner: Inner,
+        }
+
+        let xml = to_string_with_root(&Outer { inner: Inner { value: 1 } }, "root").unwrap();
+        assert_eq!(xml, "<root><inner><value>1</value></inner></root>");
+    }
+
     #[test]
     fn test_complex_with_attributes() {
         #[derive(Serialize)]
@@ -1206,4 +2550,775 @@ mod tests {
         assert!(xml.contains(r#"class="primary""#));
         assert!(xml.contains("<name>First</name>"));
     }
+
+    #[test]
+    fn test_serialize_comment_field() {
+        #[derive(Serialize)]
+        struct Server {
+            host: String,
+            #[serde(rename = "$comment")]
+            note: String,
+            port: u16,
+        }
+
+        let server = Server {
+            host: "localhost".to_string(),
+            note: "listening port".to_string(),
+            port: 8080,
+        };
+
+        let xml = to_string(&server).unwrap();
+        assert_eq!(
+            xml,
+            "<Server><host>localhost</host><!-- listening port --><port>8080</port></Server>"
+        );
+
+        let invalid = Server {
+            host: "localhost".to_string(),
+            note: "a -- b".to_string(),
+            port: 8080,
+        };
+        assert!(to_string(&invalid).is_err());
+
+        let edges = Server {
+            host: "localhost".to_string(),
+            note: "-port-".to_string(),
+            port: 8080,
+        };
+        assert_eq!(
+            to_string(&edges).unwrap(),
+            "<Server><host>localhost</host><!-- -port- --><port>8080</port></Server>"
+        );
+    }
+
+    #[test]
+    fn test_with_declaration() {
+        #[derive(Serialize)]
+        struct Data {
+            value: u32,
+        }
+
+        let mut ser = Serializer::new().with_declaration();
+        Data { value: 1 }.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            r#"<?xml version="1.0" encoding="UTF-8"?><Data><value>1</value></Data>"#
+        );
+    }
+
+    #[test]
+    fn test_skip_empty_elements() {
+        #[derive(Serialize)]
+        struct Note {
+            #[serde(rename = "@lang")]
+            lang: String,
+            #[serde(rename = "$value")]
+            text: String,
+        }
+
+        #[derive(Serialize)]
+        struct Body {
+            #[serde(rename = "$value")]
+            text: String,
+        }
+
+        #[derive(Serialize)]
+        struct Item {
+            name: String,
+            value: String,
+            note: Note,
+            empty: Note,
+            body: Body,
+        }
+
+        let item = Item {
+            name: "n".to_string(),
+            value: String::new(),
+            note: Note { lang: "en".to_string(), text: String::new() },
+            empty: Note { lang: String::new(), text: String::new() },
+            body: Body { text: String::new() },
+        };
+
+        let xml = to_string(&item).unwrap();
+        assert_eq!(
+            xml,
+            r#"<Item><name>n</name><value></value><note lang="en"></note><empty lang=""></empty><body></body></Item>"#
+        );
+
+        let mut ser = Serializer::new().skip_empty_elements(true);
+        item.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            r#"<Item><name>n</name><note lang="en"></note><empty lang=""></empty></Item>"#
+        );
+    }
+
+    #[test]
+    fn test_empty_element_style() {
+        #[derive(Serialize)]
+        struct Note {
+            #[serde(rename = "@lang")]
+            lang: String,
+            #[serde(rename = "$value")]
+            text: String,
+        }
+
+        #[derive(Serialize)]
+        struct Item {
+            value: String,
+            note: Note,
+        }
+
+        let item = Item {
+            value: String::new(),
+            note: Note { lang: "en".to_string(), text: String::new() },
+        };
+
+        let mut ser = Serializer::new().with_indent("  ");
+        item.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            "<Item>\n  <value></value>\n  <note lang=\"en\"></note>\n</Item>"
+        );
+
+        let mut ser = Serializer::new().with_empty_element_style(EmptyStyle::SelfClosing);
+        item.serialize(&mut ser).unwrap();
+        let xml = ser.into_string();
+        assert_eq!(xml, r#"<Item><value/><note lang="en"/></Item>"#);
+    }
+
+    #[test]
+    fn test_serialize_attributes_map() {
+        use crate::Attributes;
+
+        let mut attrs = Attributes::default();
+        attrs.0.insert("b".to_string(), "2".to_string());
+        attrs.0.insert("a".to_string(), "1".to_string());
+        assert_eq!(to_string_with_root(&attrs, "elem").unwrap(), r#"<elem a="1" b="2"/>"#);
+
+        #[derive(Serialize)]
+        struct Link {
+            elem: Attributes,
+            text: String,
+        }
+
+        attrs.0.insert("title".to_string(), "Q&A".to_string());
+        let link = Link { elem: attrs, text: "t".to_string() };
+        assert_eq!(
+            to_string(&link).unwrap(),
+            r#"<Link><elem a="1" b="2" title="Q&amp;A"/><text>t</text></Link>"#
+        );
+    }
+
+    #[test]
+    fn test_collapse_text_whitespace() {
+        #[derive(Serialize)]
+        struct Doc {
+            f: String,
+            g: String,
+            h: String,
+        }
+
+        let doc = Doc {
+            f: "  a   b  ".to_string(),
+            g: "one\n\ttwo".to_string(),
+            h: "   ".to_string(),
+        };
+        let mut ser = Serializer::new().collapse_text_whitespace(true);
+        doc.serialize(&mut ser).unwrap();
+        assert_eq!(ser.into_string(), "<Doc><f>a b</f><g>one two</g><h></h></Doc>");
+
+        assert_eq!(
+            to_string(&doc).unwrap(),
+            "<Doc><f>  a   b  </f><g>one\n\ttwo</g><h>   </h></Doc>"
+        );
+    }
+
+    #[test]
+    fn test_enum_repr_xsi_type() {
+        #[derive(Serialize)]
+        enum Shape {
+            Label(String),
+            Pair(i32, i32),
+            Empty,
+            List(Vec<i32>),
+        }
+
+        #[derive(Serialize)]
+        struct Drawing {
+            shapes: Vec<Shape>,
+        }
+
+        let drawing = Drawing {
+            shapes: vec![Shape::Label("hi".to_string()), Shape::Pair(1, 2), Shape::Empty],
+        };
+        let mut ser = Serializer::new().with_enum_repr(EnumRepr::XsiType);
+        drawing.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            concat!(
+                r#"<Drawing xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+                r#"<shapes xsi:type="Label">hi</shapes>"#,
+                r#"<shapes xsi:type="Pair"><item>1</item><item>2</item></shapes>"#,
+                r#"<shapes xsi:type="Empty"/></Drawing>"#,
+            )
+        );
+
+        let mut ser = Serializer::new().with_enum_repr(EnumRepr::XsiType);
+        assert!(Shape::List(vec![1]).serialize(&mut ser).is_err());
+    }
+
+    #[test]
+    fn test_serialize_raw_nodes() {
+        use crate::RawNode;
+
+        #[derive(Serialize)]
+        struct Doc {
+            first: u32,
+            #[serde(rename = "$nodes")]
+            nodes: Vec<RawNode>,
+            second: u32,
+        }
+
+        let doc = Doc {
+            first: 1,
+            nodes: vec![
+                RawNode::Comment("between".to_string()),
+                RawNode::Pi("page-break".to_string(), None),
+            ],
+            second: 2,
+        };
+        assert_eq!(
+            to_string(&doc).unwrap(),
+            "<Doc><first>1</first><!-- between --><?page-break?><second>2</second></Doc>"
+        );
+        assert_eq!(
+            to_string_pretty(&doc, "  ").unwrap(),
+            concat!(
+                "<Doc>\n  <first>1</first>\n  <!-- between -->\n  <?page-break?>\n",
+                "  <second>2</second>\n</Doc>"
+            )
+        );
+
+        let bad = Doc { nodes: vec![RawNode::Comment("a--b".to_string())], ..doc };
+        assert!(to_string(&bad).is_err());
+        let bad = Doc { nodes: vec![RawNode::Pi("xml".to_string(), None)], ..bad };
+        assert!(to_string(&bad).is_err());
+        // Targets must be XML names, and data must not close the PI early
+        let with_node = |node| Doc { first: 1, nodes: vec![node], second: 2 };
+        for target in ["", "a b", "a?>", "1st", "x<y"] {
+            let bad = with_node(RawNode::Pi(target.to_string(), None));
+            assert!(to_string(&bad).is_err(), "{:?}", target);
+        }
+        let bad = with_node(RawNode::Pi("t".to_string(), Some("a ?> b".to_string())));
+        assert!(to_string(&bad).is_err());
+        let edge = Doc { nodes: vec![RawNode::Comment("end-".to_string())], ..bad };
+        assert!(to_string(&edge).unwrap().contains("<!-- end- -->"));
+    }
+
+    #[test]
+    fn test_canonical() {
+        #[derive(Serialize)]
+        struct Entry {
+            #[serde(rename = "@zeta")]
+            zeta: u32,
+            #[serde(rename = "@alpha")]
+            alpha: String,
+            #[serde(rename = "@xmlns:ds")]
+            ns: String,
+            #[serde(rename = "@mid")]
+            mid: String,
+            note: String,
+            marker: (),
+            text: String,
+        }
+
+        let entry = Entry {
+            zeta: 1,
+            alpha: "a\r\nb\tc".to_string(),
+            ns: "urn:ds".to_string(),
+            mid: "it's".to_string(),
+            note: String::new(),
+            marker: (),
+            text: "x\r\ny\rz".to_string(),
+        };
+        assert_eq!(
+            to_string_canonical(&entry).unwrap(),
+            "<Entry xmlns:ds=\"urn:ds\" alpha=\"a&#xD;&#xA;b&#x9;c\" mid=\"it&apos;s\" zeta=\"1\">\
+             <note></note><marker></marker><text>x\ny\nz</text></Entry>"
+        );
+
+        let mut ser = Serializer::new()
+            .canonical(true)
+            .with_empty_element_style(EmptyStyle::SelfClosing);
+        entry.serialize(&mut ser).unwrap();
+        assert!(ser.into_string().contains("<note></note>"));
+
+        // The escaped whitespace reads back unchanged
+        let xml = to_string_canonical(&entry).unwrap();
+        let mut reader = crate::reader::XmlReader::from_str(&xml);
+        match reader.next_event().unwrap() {
+            crate::reader::XmlEvent::StartElement { attributes, .. } => {
+                assert_eq!(attributes[1].value, "a\r\nb\tc");
+            }
+            _ => panic!("expected StartElement"),
+        }
+    }
+
+    #[test]
+    fn test_to_vec_pretty() {
+        #[derive(Serialize)]
+        struct Inner {
+            value: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Outer {
+            name: String,
+            inner: Inner,
+        }
+
+        let outer = Outer { name: "n".to_string(), inner: Inner { value: 1 } };
+        let bytes = to_vec_pretty(&outer, "  ").unwrap();
+        assert_eq!(bytes, to_string_pretty(&outer, "  ").unwrap().into_bytes());
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "<Outer>\n  <name>n</name>\n  <inner>\n    <value>1</value>\n  </inner>\n</Outer>"
+        );
+
+        let mut ser = Serializer::new().with_indent("\t").with_declaration();
+        outer.serialize(&mut ser).unwrap();
+        let bytes = ser.into_bytes();
+        assert!(bytes.starts_with(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Outer>\n\t<name>"));
+    }
+
+    #[test]
+    fn test_serialize_json_value() {
+        use serde_json::json;
+
+        let value = json!({
+            "@version": 2,
+            "name": "widget",
+            "price": 9.5,
+            "active": true,
+            "discontinued": null,
+            "tags": ["a", "b"],
+            "size": {"@unit": "cm", "$value": 12},
+            "parts": [{"id": 1}, {"id": 2}],
+        });
+        let xml = to_string_with_root(&value, "product").unwrap();
+        assert_eq!(
+            xml,
+            concat!(
+                r#"<product version="2"><active>true</active><discontinued/><name>widget</name>"#,
+                r#"<parts><id>1</id></parts><parts><id>2</id></parts><price>9.5</price>"#,
+                r#"<size unit="cm">12</size><tags>a</tags><tags>b</tags></product>"#,
+            )
+        );
+
+        let mut de = crate::Deserializer::from_str(&xml)
+            .group_repeated_elements(true)
+            .infer_scalars(true);
+        let parsed: serde_json::Value = serde::Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(parsed["tags"], json!(["a", "b"]));
+        assert_eq!(parsed["parts"], json!([{"id": 1}, {"id": 2}]));
+        assert_eq!(parsed["size"], json!({"@unit": "cm", "$value": 12}));
+    }
+
+    #[test]
+    fn test_float_format() {
+        use serde::Deserialize;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Price {
+            #[serde(rename = "@tax")]
+            tax: f64,
+            amount: f64,
+        }
+
+        let price = Price { tax: 0.5, amount: 19.9 };
+        let cases = [
+            (FloatFormat::Shortest, r#"<Price tax="0.5"><amount>19.9</amount></Price>"#),
+            (FloatFormat::Fixed(2), r#"<Price tax="0.50"><amount>19.90</amount></Price>"#),
+            (FloatFormat::Scientific, r#"<Price tax="5e-1"><amount>1.99e1</amount></Price>"#),
+        ];
+        for (format, expected) in cases {
+            let mut ser = Serializer::new().with_float_format(format);
+            price.serialize(&mut ser).unwrap();
+            let xml = ser.into_string();
+            assert_eq!(xml, expected);
+            assert_eq!(crate::from_str::<Price>(&xml).unwrap(), price);
+        }
+    }
+
+    #[test]
+    fn test_float_format_integral_values() {
+        #[derive(Serialize)]
+        struct Values {
+            whole: f64,
+            single: f32,
+            negative: f64,
+            large: f64,
+        }
+
+        let values = Values { whole: 3.0, single: 1.5, negative: -0.25, large: 1e21 };
+        let cases = [
+            (
+                FloatFormat::Shortest,
+                "<whole>3.0</whole><single>1.5</single><negative>-0.25</negative><large>1e21</large>",
+            ),
+            (
+                FloatFormat::Fixed(2),
+                concat!(
+                    "<whole>3.00</whole><single>1.50</single><negative>-0.25</negative>",
+                    "<large>1000000000000000000000.00</large>"
+                ),
+            ),
+            (
+                FloatFormat::Fixed(0),
+                concat!(
+                    "<whole>3</whole><single>2</single><negative>-0</negative>",
+                    "<large>1000000000000000000000</large>"
+                ),
+            ),
+            (
+                FloatFormat::Scientific,
+                "<whole>3e0</whole><single>1.5e0</single><negative>-2.5e-1</negative><large>1e21</large>",
+            ),
+        ];
+        for (format, fields) in cases {
+            let mut ser = Serializer::new().with_float_format(format);
+            values.serialize(&mut ser).unwrap();
+            assert_eq!(ser.into_string(), format!("<Values>{}</Values>", fields));
+        }
+    }
+
+    #[test]
+    fn test_non_finite_floats() {
+        use serde::Deserialize;
+
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Reading {
+            #[serde(rename = "@min")]
+            min: f64,
+            max: f64,
+            mean: f32,
+        }
+
+        let reading = Reading { min: f64::NEG_INFINITY, max: f64::INFINITY, mean: f32::NAN };
+        for format in [FloatFormat::Shortest, FloatFormat::Fixed(2), FloatFormat::Scientific] {
+            let mut ser = Serializer::new().with_float_format(format);
+            reading.serialize(&mut ser).unwrap();
+            let xml = ser.into_string();
+            assert_eq!(xml, r#"<Reading min="-INF"><max>INF</max><mean>NaN</mean></Reading>"#);
+
+            let parsed: Reading = crate::from_str(&xml).unwrap();
+            assert_eq!(parsed.min, f64::NEG_INFINITY);
+            assert_eq!(parsed.max, f64::INFINITY);
+            assert!(parsed.mean.is_nan());
+        }
+    }
+
+    #[test]
+    fn test_declaration_encoding_roundtrip() {
+        use crate::reader::{XmlEvent, XmlReader};
+
+        #[derive(Serialize)]
+        struct Data {
+            value: u32,
+        }
+
+        for label in ["utf-8", "UTF-8", "x-custom-label"] {
+            let input = format!(r#"<?xml version="1.0" encoding="{}"?><Data/>"#, label);
+            let encoding = match XmlReader::from_str(&input).next_event().unwrap() {
+                XmlEvent::XmlDecl { encoding, .. } => encoding.unwrap(),
+                other => panic!("expected XmlDecl, got {:?}", other),
+            };
+            assert_eq!(encoding, label);
+
+            let mut ser = Serializer::new().with_declaration_encoding(&encoding).unwrap();
+            Data { value: 1 }.serialize(&mut ser).unwrap();
+            let xml = ser.into_string();
+            let expected = format!(r#"<?xml version="1.0" encoding="{}"?>"#, label);
+            assert!(xml.starts_with(&expected));
+        }
+
+        for label in ["", "8bit", "utf 8", "utf-8\"?><x", "caf\u{e9}"] {
+            let options = SerializeOptions {
+                declaration: Some(label.to_string()),
+                ..SerializeOptions::default()
+            };
+            let err = to_string_with_options(&Data { value: 1 }, &options).unwrap_err();
+            assert!(err.to_string().contains("invalid encoding name"), "{}", err);
+            assert!(Serializer::with_options(options).is_err());
+            assert!(Serializer::new().with_declaration_encoding(label).is_err());
+        }
+    }
+
+    #[test]
+    fn test_pretty_print_attribute_only_elements() {
+        #[derive(Serialize)]
+        struct Meta {
+            #[serde(rename = "@charset")]
+            charset: String,
+        }
+
+        #[derive(Serialize)]
+        struct Link {
+            #[serde(rename = "@href")]
+            href: String,
+            #[serde(rename = "$value")]
+            text: String,
+        }
+
+        #[derive(Serialize)]
+        struct Head {
+            meta: Meta,
+            link: Link,
+            title: String,
+        }
+
+        #[derive(Serialize)]
+        struct Html {
+            head: Head,
+        }
+
+        let html = Html {
+            head: Head {
+                meta: Meta { charset: "UTF-8".to_string() },
+                link: Link { href: "/".to_string(), text: "home".to_string() },
+                title: "Page".to_string(),
+            },
+        };
+        let mut ser = Serializer::new().with_indent("  ");
+        html.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            "<Html>\n  <head>\n    <meta charset=\"UTF-8\"/>\n    <link href=\"/\">home</link>\n    <title>Page</title>\n  </head>\n</Html>"
+        );
+    }
+
+    #[test]
+    fn test_pretty_print_end_tag_after_content() {
+        #[derive(Serialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Inline {
+            B(String),
+            I(String),
+        }
+
+        #[derive(Serialize)]
+        struct Para {
+            #[serde(rename = "$value")]
+            content: Vec<Inline>,
+        }
+
+        #[derive(Serialize)]
+        struct Doc {
+            para: Para,
+            icon: crate::OuterXml,
+        }
+
+        let doc = Doc {
+            para: Para {
+                content: vec![Inline::B("a".to_string()), Inline::I("b".to_string())],
+            },
+            icon: crate::OuterXml("<svg/>".to_string()),
+        };
+        let mut ser = Serializer::new().with_indent("  ");
+        doc.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            "<Doc>\n  <para><item><b>a</b></item><item><i>b</i></item></para>\n  <svg/>\n</Doc>"
+        );
+    }
+
+    #[test]
+    fn test_attributes_one_per_line() {
+        use serde::Deserialize;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Link {
+            #[serde(rename = "@href")]
+            href: String,
+            #[serde(rename = "@rel")]
+            rel: String,
+            #[serde(rename = "@title")]
+            title: String,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Head {
+            link: Link,
+        }
+
+        let head = Head {
+            link: Link {
+                href: "style.css".to_string(),
+                rel: "stylesheet".to_string(),
+                title: "A & B".to_string(),
+            },
+        };
+
+        let mut ser = Serializer::new().attributes_one_per_line(true);
+        head.serialize(&mut ser).unwrap();
+        let xml = ser.into_string();
+        assert_eq!(
+            xml,
+            "<Head><link\n    href=\"style.css\"\n    rel=\"stylesheet\"\n    title=\"A &amp; B\"/></Head>"
+        );
+        assert_eq!(crate::from_str::<Head>(&xml).unwrap(), head);
+
+        let mut ser = Serializer::new().with_indent("\t").attributes_one_per_line(true);
+        head.serialize(&mut ser).unwrap();
+        let xml = ser.into_string();
+        assert_eq!(
+            xml,
+            "<Head>\n\t<link\n\t\thref=\"style.css\"\n\t\trel=\"stylesheet\"\n\t\ttitle=\"A &amp; B\"/>\n</Head>"
+        );
+        assert_eq!(crate::from_str::<Head>(&xml).unwrap(), head);
+    }
+
+    #[test]
+    fn test_serialize_options() {
+        #[derive(Serialize)]
+        struct Item {
+            #[serde(rename = "@id")]
+            id: u32,
+            price: f64,
+            note: Option<String>,
+            tag: String,
+        }
+
+        let item = Item { id: 7, price: 2.5, note: None, tag: String::new() };
+        let options = SerializeOptions {
+            root: Some("item".to_string()),
+            declaration: Some("UTF-8".to_string()),
+            indent: Some("  ".to_string()),
+            none_mode: NoneMode::EmptyElement,
+            float_format: FloatFormat::Fixed(2),
+            empty_style: EmptyStyle::SelfClosing,
+            ..Default::default()
+        };
+        let expected = concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<item id=\"7\">\n",
+            "  <price>2.50</price>\n",
+            "  <note/>\n",
+            "  <tag/>\n",
+            "</item>"
+        );
+        assert_eq!(to_string_with_options(&item, &options).unwrap(), expected);
+
+        let mut output = Vec::new();
+        to_writer_with_options(&mut output, &item, &options).unwrap();
+        assert_eq!(output, expected.as_bytes());
+
+        let mut ser = Serializer::with_root("item")
+            .with_declaration()
+            .with_indent("  ")
+            .with_none_as(NoneMode::EmptyElement)
+            .with_float_format(FloatFormat::Fixed(2))
+            .with_empty_element_style(EmptyStyle::SelfClosing);
+        item.serialize(&mut ser).unwrap();
+        assert_eq!(ser.into_string(), expected);
+
+        assert_eq!(
+            to_string_with_options(&item, &SerializeOptions::default()).unwrap(),
+            to_string(&item).unwrap()
+        );
+        assert_eq!(to_vec_with_options(&item, &options).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_to_writer_streams() {
+        struct Chunks(Vec<Vec<u8>>);
+
+        impl Write for Chunks {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(buf.to_vec());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Row {
+            #[serde(rename = "@n")]
+            n: usize,
+            text: String,
+        }
+
+        #[derive(Serialize)]
+        struct Table {
+            row: Vec<Row>,
+        }
+
+        let table = Table {
+            row: (0..2000).map(|n| Row { n, text: "x".repeat(20) }).collect(),
+        };
+        let options = SerializeOptions {
+            declaration: Some("UTF-8".to_string()),
+            indent: Some("  ".to_string()),
+            ..Default::default()
+        };
+        let expected = to_string_with_options(&table, &options).unwrap();
+
+        let mut chunks = Chunks(Vec::new());
+        to_writer_with_options(&mut chunks, &table, &options).unwrap();
+        assert!(chunks.0.len() > 2);
+        assert!(chunks.0.iter().all(|chunk| chunk.len() < expected.len() / 2));
+        assert_eq!(chunks.0.concat(), expected.as_bytes());
+
+        let mut output = Vec::new();
+        to_writer(&mut output, &table).unwrap();
+        assert_eq!(output, to_string(&table).unwrap().as_bytes());
+    }
+
+    #[test]
+    fn test_sort_map_keys() {
+        use std::collections::HashMap;
+
+        #[derive(Serialize)]
+        struct Config {
+            settings: HashMap<String, String>,
+        }
+
+        let config = || {
+            let settings = (0..20)
+                .map(|i| (format!("key{:02}", i), i.to_string()))
+                .chain([("@version".to_string(), "2".to_string())])
+                .chain([("@id".to_string(), "c".to_string())])
+                .collect();
+            Config { settings }
+        };
+
+        let serialize = |config: &Config| {
+            let mut ser = Serializer::new().sort_map_keys(true);
+            config.serialize(&mut ser).unwrap();
+            ser.into_string()
+        };
+        let xml = serialize(&config());
+        assert_eq!(xml, serialize(&config()));
+        let entries: String =
+            (0..20).map(|i| format!("<key{:02}>{}</key{:02}>", i, i, i)).collect();
+        assert_eq!(
+            xml,
+            format!(r#"<Config><settings id="c" version="2">{}</settings></Config>"#, entries)
+        );
+
+        let mut settings = HashMap::new();
+        settings.insert("b".to_string(), Some(vec![1, 2]));
+        settings.insert("a".to_string(), None);
+        settings.insert("c".to_string(), Some(vec![3]));
+        let mut ser = Serializer::new().with_indent("  ").sort_map_keys(true);
+        settings.serialize(&mut ser).unwrap();
+        assert_eq!(
+            ser.into_string(),
+            "<map>\n  <b>1</b>\n  <b>2</b>\n  <c>3</c>\n</map>"
+        );
+    }
 }
@@ -0,0 +1,220 @@
+//! Reading and writing `std::time::Duration` as an `xs:duration` string.
+//!
+//! Use it on a field with `#[serde(with = "serde_xml::serde_duration")]`. A
+//! duration is written in days, hours, minutes and seconds, such as
+//! `P1DT2H0.5S`, and zero as `PT0S`. Years and months have no fixed length,
+//! so durations using them are rejected, as are negative durations.
+//!
+//! # Example
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use serde_xml::{from_str, to_string};
+//! use std::time::Duration;
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! struct Job {
+//!     #[serde(with = "serde_xml::serde_duration")]
+//!     timeout: Duration,
+//! }
+//!
+//! let job = Job { timeout: Duration::from_millis(90_500) };
+//! let xml = to_string(&job).unwrap();
+//! assert_eq!(xml, "<Job><timeout>PT1M30.5S</timeout></Job>");
+//! assert_eq!(from_str::<Job>(&xml).unwrap(), job);
+//! ```
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::Serializer;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Serializes a `Duration` as an `xs:duration` string.
+pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format_duration(*duration))
+}
+
+/// Deserializes a `Duration` from an `xs:duration` string.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = Cow::<str>::deserialize(deserializer)?;
+    parse_duration(text.trim()).map_err(de::Error::custom)
+}
+
+/// Formats `duration` in days, hours, minutes and seconds.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let nanos = duration.subsec_nanos();
+    let (days, hours, minutes, seconds) =
+        (secs / 86_400, secs % 86_400 / 3_600, secs % 3_600 / 60, secs % 60);
+
+    let mut out = String::from("P");
+    if days > 0 {
+        write!(out, "{}D", days).unwrap();
+        if hours == 0 && minutes == 0 && seconds == 0 && nanos == 0 {
+            return out;
+        }
+    }
+    out.push('T');
+    if hours > 0 {
+        write!(out, "{}H", hours).unwrap();
+    }
+    if minutes > 0 {
+        write!(out, "{}M", minutes).unwrap();
+    }
+    if seconds > 0 || nanos > 0 || (hours == 0 && minutes == 0) {
+        write!(out, "{}", seconds).unwrap();
+        if nanos > 0 {
+            let fraction = format!("{:09}", nanos);
+            out.push('.');
+            out.push_str(fraction.trim_end_matches('0'));
+        }
+        out.push('S');
+    }
+    out
+}
+
+/// Parses an `xs:duration` without years or months.
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid xs:duration '{}'", text);
+    if text.starts_with('-') {
+        return Err(format!("negative xs:duration '{}' is not supported", text));
+    }
+    let rest = text.strip_prefix('P').ok_or_else(invalid)?;
+    let (date, time) = match rest.split_once('T') {
+        Some((_, "")) => return Err(invalid()),
+        Some((date, time)) => (date, time),
+        None if rest.is_empty() => return Err(invalid()),
+        None => (rest, ""),
+    };
+
+    let mut secs = 0u64;
+    let mut nanos = 0u32;
+    for (value, unit) in components(date, &['Y', 'M', 'D']).ok_or_else(invalid)? {
+        match unit {
+            'D' => secs = add_units(secs, value, 86_400).ok_or_else(invalid)?,
+            _ => {
+                return Err(format!(
+                    "xs:duration '{}' uses years or months, which have no fixed length",
+                    text
+                ));
+            }
+        }
+    }
+    for (value, unit) in components(time, &['H', 'M', 'S']).ok_or_else(invalid)? {
+        let (value, fraction) = match value.split_once('.') {
+            // A fraction needs digits on both sides of the point
+            Some((value, fraction))
+                if unit == 'S'
+                    && !value.is_empty()
+                    && !fraction.is_empty()
+                    && fraction.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                (value, fraction)
+            }
+            Some(_) => return Err(invalid()),
+            None => (value, ""),
+        };
+        let scale = match unit {
+            'H' => 3_600,
+            'M' => 60,
+            _ => 1,
+        };
+        secs = add_units(secs, value, scale).ok_or_else(invalid)?;
+        if !fraction.is_empty() {
+            // Digits beyond nanoseconds are truncated
+            let digits: String = fraction.chars().take(9).collect();
+            nanos = format!("{:0<9}", digits).parse().map_err(|_| invalid())?;
+        }
+    }
+    Ok(Duration::new(secs, nanos))
+}
+
+/// Splits `part` into its number and unit pairs, which must use `units` in
+/// order and each at most once.
+fn components<'a>(part: &'a str, units: &[char]) -> Option<Vec<(&'a str, char)>> {
+    let mut pairs = Vec::new();
+    let mut rest = part;
+    let mut next = 0;
+    while !rest.is_empty() {
+        let end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let unit = rest[end..].chars().next()?;
+        let index = next + units[next..].iter().position(|&u| u == unit)?;
+        if end == 0 {
+            return None;
+        }
+        pairs.push((&rest[..end], unit));
+        next = index + 1;
+        rest = &rest[end + unit.len_utf8()..];
+    }
+    Some(pairs)
+}
+
+/// Adds `value` units of `scale` seconds to `secs`, failing on overflow or
+/// a malformed number.
+fn add_units(secs: u64, value: &str, scale: u64) -> Option<u64> {
+    if !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse::<u64>().ok()?.checked_mul(scale)?.checked_add(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_str, to_string};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Job {
+        #[serde(with = "crate::serde_duration")]
+        timeout: Duration,
+    }
+
+    #[test]
+    fn test_roundtrip_durations() {
+        for (duration, text) in [
+            (Duration::ZERO, "PT0S"),
+            (Duration::from_millis(500), "PT0.5S"),
+            (Duration::from_nanos(1), "PT0.000000001S"),
+            (Duration::from_secs(3_600), "PT1H"),
+            (Duration::from_secs(86_400), "P1D"),
+            (Duration::from_secs(86_400 + 60), "P1DT1M"),
+            (Duration::new(90_061, 250_000_000), "P1DT1H1M1.25S"),
+        ] {
+            let job = Job { timeout: duration };
+            let xml = to_string(&job).unwrap();
+            assert_eq!(xml, format!("<Job><timeout>{}</timeout></Job>", text));
+            assert_eq!(from_str::<Job>(&xml).unwrap(), job);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("PT36H"), Ok(Duration::from_secs(36 * 3_600)));
+        assert_eq!(parse_duration("P2DT0.5S"), Ok(Duration::new(2 * 86_400, 500_000_000)));
+        assert_eq!(parse_duration("P0D"), Ok(Duration::ZERO));
+        assert_eq!(parse_duration("PT1.1234567899S"), Ok(Duration::new(1, 123_456_789)));
+
+        let invalid = [
+            "", "P", "PT", "1S", "P1H", "PT1S1M", "PT1M1M", "PT1.5M", "PT.5S", "PT1xS", "PT0.S",
+            "PT1.2.3S",
+        ];
+        for text in invalid {
+            assert!(parse_duration(text).is_err(), "{}", text);
+        }
+        assert!(parse_duration("P1Y").unwrap_err().contains("years or months"));
+        assert!(parse_duration("-PT1S").unwrap_err().contains("negative"));
+        assert!(parse_duration("PT99999999999999999999S").is_err());
+
+        let job: Job = from_str("<Job><timeout> PT2M </timeout></Job>").unwrap();
+        assert_eq!(job.timeout, Duration::from_secs(120));
+        assert!(from_str::<Job>("<Job><timeout>P1M</timeout></Job>").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! A list of whitespace-separated tokens held in one value.
+
+use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+
+/// A list of tokens written as one space-separated string, such as an HTML
+/// `class` attribute or an XSD `NMTOKENS` value.
+///
+/// On deserialization the value is split on any run of whitespace, so
+/// `" a  b\tc "` reads as `["a", "b", "c"]`.
+///
+/// # Example
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_xml::{from_str, to_string, TokenList};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// struct Div {
+///     #[serde(rename = "@class")]
+///     class: TokenList,
+/// }
+///
+/// let div: Div = from_str(r#"<Div class="a b c"/>"#).unwrap();
+/// assert_eq!(div.class.0, ["a", "b", "c"]);
+/// assert_eq!(to_string(&div).unwrap(), r#"<Div class="a b c"/>"#);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TokenList(pub Vec<String>);
+
+impl Serialize for TokenList {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0.join(" "))
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TokenListVisitor;
+
+        impl<'de> Visitor<'de> for TokenListVisitor {
+            type Value = TokenList;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a space-separated list of tokens")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<TokenList, E> {
+                Ok(TokenList(v.split_whitespace().map(str::to_string).collect()))
+            }
+        }
+
+        deserializer.deserialize_str(TokenListVisitor)
+    }
+}
@@ -0,0 +1,468 @@
+//! Schema-less representation of XML content.
+//!
+//! [`Element`] and [`Value`] can be deserialized from any document, which is
+//! useful for tools that inspect or transform XML without a fixed schema.
+//! They serialize back to XML, with attributes written in name order.
+
+use crate::error::Error;
+use crate::reader::{XmlEvent, XmlReader};
+use serde::de::{self, Deserialize, Deserializer, EnumAccess, MapAccess, VariantAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Newtype name the XML deserializer recognizes.
+pub(crate) const VALUE_NAME: &str = "$serde_xml::Value";
+
+/// Merges the children of `overlay`'s root element into `base`'s and returns
+/// the combined document.
+///
+/// Both roots must have the same name. See [`Element::merge`] for how
+/// attributes and children are combined.
+///
+/// # Example
+///
+/// ```
+/// let base = r#"<config env="dev"><host>localhost</host><port>80</port></config>"#;
+/// let overlay = r#"<config env="prod"><port>443</port><tls>true</tls></config>"#;
+///
+/// let merged = serde_xml::merge(base, overlay).unwrap();
+/// assert_eq!(
+///     merged,
+///     r#"<config env="prod"><host>localhost</host><port>443</port><tls>true</tls></config>"#
+/// );
+/// ```
+pub fn merge(base: &str, overlay: &str) -> crate::Result<String> {
+    let root = root_name(base)?;
+    let overlay_root = root_name(overlay)?;
+    if overlay_root != root {
+        return Err(Error::invalid_value(format!(
+            "cannot merge root element '{}' into '{}'",
+            overlay_root, root
+        )));
+    }
+
+    let mut element: Element = crate::from_str(base)?;
+    element.merge(crate::from_str(overlay)?);
+    crate::to_string_with_root(&element, &root)
+}
+
+/// Returns the name of a document's root element.
+fn root_name(xml: &str) -> crate::Result<String> {
+    let mut reader = XmlReader::from_str(xml);
+    loop {
+        match reader.next_event()? {
+            XmlEvent::StartElement { name, .. } | XmlEvent::EmptyElement { name, .. } => {
+                return Ok(name.into_owned());
+            }
+            XmlEvent::Eof => return Err(Error::syntax("document has no root element")),
+            _ => {}
+        }
+    }
+}
+
+/// A generic XML element: its attributes and its children in document order.
+///
+/// Child elements are keyed by their element name. Text mixed in between
+/// child elements is keyed by `$value`.
+///
+/// # Example
+///
+/// ```
+/// use serde_xml::{from_str, Element, Value};
+///
+/// let element: Element = from_str(r#"<e a="1"><b>2</b></e>"#).unwrap();
+/// assert_eq!(element.attributes["a"], "1");
+/// assert_eq!(element.children, vec![("b".to_string(), Value::Text("2".to_string()))]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Element {
+    /// Attribute names mapped to their values.
+    pub attributes: HashMap<String, String>,
+    /// Child elements and text nodes in document order.
+    pub children: Vec<(String, Value)>,
+}
+
+/// A generic XML value.
+///
+/// In a `$value` sequence, which has no key to name its items, a child
+/// element is read as an `Element` holding just that child under its name,
+/// so that `<p>one<b>two</b></p>` gives `[Text("one"), Element({b: "two"})]`.
+/// Such elements are written back as their children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// Text content, or an element holding only text and no attributes.
+    Text(String),
+    /// An element with attributes or child elements.
+    Element(Element),
+}
+
+impl Element {
+    /// Returns the first child with the given name.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.children
+            .iter()
+            .find(|(child, _)| child == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Merges `overlay` into this element, with `overlay` winning conflicts.
+    ///
+    /// Attributes of `overlay` are added, replacing any of the same name.
+    /// Children of `overlay` replace all children of the same name, taking
+    /// the place of the first one; children with new names are appended.
+    /// Replaced children are not merged recursively.
+    pub fn merge(&mut self, overlay: Element) {
+        self.attributes.extend(overlay.attributes);
+
+        let mut overlay = overlay.children;
+        let mut replaced: Vec<String> = Vec::new();
+        let mut children = Vec::with_capacity(self.children.len() + overlay.len());
+        for (name, value) in std::mem::take(&mut self.children) {
+            if overlay.iter().any(|(child, _)| *child == name) {
+                // The first child of this name makes way for all of overlay's
+                let (taken, rest): (Vec<_>, Vec<_>) =
+                    overlay.into_iter().partition(|(child, _)| *child == name);
+                overlay = rest;
+                children.extend(taken);
+                replaced.push(name);
+            } else if !replaced.contains(&name) {
+                children.push((name, value));
+            }
+        }
+        children.extend(overlay);
+        self.children = children;
+    }
+}
+
+impl Value {
+    /// Returns the text if this value is text.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            Value::Element(_) => None,
+        }
+    }
+
+    /// Returns the element if this value is an element.
+    pub fn as_element(&self) -> Option<&Element> {
+        match self {
+            Value::Text(_) => None,
+            Value::Element(element) => Some(element),
+        }
+    }
+}
+
+/// Collects attributes (`@`-prefixed keys) and children from a map.
+fn visit_element<'de, A>(mut map: A) -> Result<Element, A::Error>
+where
+    A: MapAccess<'de>,
+{
+    let mut element = Element::default();
+    while let Some(key) = map.next_key::<String>()? {
+        match key.strip_prefix('@') {
+            Some(name) => {
+                let value = map.next_value::<String>()?;
+                element.attributes.insert(name.to_string(), value);
+            }
+            None => {
+                let value = map.next_value::<Value>()?;
+                element.children.push((key, value));
+            }
+        }
+    }
+    Ok(element)
+}
+
+impl<'de> Deserialize<'de> for Element {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ElementVisitor;
+
+        impl<'de> Visitor<'de> for ElementVisitor {
+            type Value = Element;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("an XML element")
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Element, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                visit_element(map)
+            }
+        }
+
+        deserializer.deserialize_map(ElementVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("XML text or element")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(Value::Text(v.to_string()))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(Value::Text(v))
+            }
+
+            fn visit_unit<E>(self) -> Result<Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(Value::Text(String::new()))
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Value, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                deserializer.deserialize_any(self)
+            }
+
+            fn visit_enum<A>(self, data: A) -> Result<Value, A::Error>
+            where
+                A: EnumAccess<'de>,
+            {
+                // A named item of a `$value` sequence
+                let (name, variant): (String, _) = data.variant()?;
+                let value = variant.newtype_variant()?;
+                Ok(Value::Element(Element {
+                    attributes: HashMap::new(),
+                    children: vec![(name, value)],
+                }))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut element = visit_element(map)?;
+                if !element.attributes.is_empty() {
+                    return Ok(Value::Element(element));
+                }
+                match element.children.len() {
+                    0 => Ok(Value::Text(String::new())),
+                    1 if element.children[0].0 == "$value" => {
+                        Ok(element.children.pop().unwrap().1)
+                    }
+                    _ => Ok(Value::Element(element)),
+                }
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(VALUE_NAME, ValueVisitor)
+    }
+}
+
+impl Serialize for Element {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut attributes: Vec<_> = self.attributes.iter().collect();
+        attributes.sort();
+        let mut map = serializer.serialize_map(Some(attributes.len() + self.children.len()))?;
+        for (name, value) in attributes {
+            map.serialize_entry(&format!("@{}", name), value)?;
+        }
+        for (name, value) in &self.children {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(VALUE_NAME, &Content(self))
+    }
+}
+
+/// The content of a [`Value`], wrapped so the XML serializer can tell a
+/// `Value` apart.
+struct Content<'a>(&'a Value);
+
+impl Serialize for Content<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            Value::Text(text) => serializer.serialize_str(text),
+            Value::Element(element) => element.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_str;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    fn named(name: &str, value: Value) -> Value {
+        Value::Element(Element {
+            attributes: HashMap::new(),
+            children: vec![(name.to_string(), value)],
+        })
+    }
+
+    #[test]
+    fn test_element_attributes_and_children() {
+        let element: Element = from_str(r#"<e a="1"><b>2</b><c>3</c></e>"#).unwrap();
+        assert_eq!(element.attributes.len(), 1);
+        assert_eq!(element.attributes["a"], "1");
+        assert_eq!(
+            element.children,
+            vec![("b".to_string(), text("2")), ("c".to_string(), text("3"))]
+        );
+    }
+
+    #[test]
+    fn test_element_nested() {
+        let xml = r#"<e><b x="y"><c>1</c><c>2</c></b><d/>tail</e>"#;
+        let element: Element = from_str(xml).unwrap();
+        assert_eq!(element.children.len(), 3);
+
+        let b = element.get("b").and_then(Value::as_element).unwrap();
+        assert_eq!(b.attributes["x"], "y");
+        assert_eq!(
+            b.children,
+            vec![("c".to_string(), text("1")), ("c".to_string(), text("2"))]
+        );
+        assert_eq!(element.get("d"), Some(&text("")));
+        assert_eq!(element.get("$value").and_then(Value::as_text), Some("tail"));
+    }
+
+    #[test]
+    fn test_element_serialize_roundtrip() {
+        let xml = r#"<e b="2" a="1"><x>1</x><y k="v"><z/></y>tail<x>2</x></e>"#;
+        let element: Element = from_str(xml).unwrap();
+        let written = crate::to_string_with_root(&element, "e").unwrap();
+        assert_eq!(written, r#"<e a="1" b="2"><x>1</x><y k="v"><z></z></y>tail<x>2</x></e>"#);
+        assert_eq!(from_str::<Element>(&written).unwrap(), element);
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = r#"<config env="dev" debug="true">
+            <host>localhost</host>
+            <plugin>a</plugin>
+            <port>80</port>
+            <plugin>b</plugin>
+        </config>"#;
+        let overlay = r#"<?xml version="1.0"?>
+            <config env="prod"><plugin>c</plugin><db><url>pg://x</url></db><port>443</port></config>"#;
+
+        let merged = merge(base, overlay).unwrap();
+        assert_eq!(
+            merged,
+            r#"<config debug="true" env="prod"><host>localhost</host><plugin>c</plugin><port>443</port><db><url>pg://x</url></db></config>"#
+        );
+
+        assert!(merge(base, "<settings/>").is_err());
+        assert!(merge(base, "").is_err());
+    }
+
+    #[test]
+    fn test_value_children() {
+        use serde::Deserialize;
+
+        #[derive(Debug, Deserialize)]
+        struct Children {
+            #[serde(rename = "@id")]
+            id: String,
+            #[serde(rename = "$value")]
+            nodes: Vec<Value>,
+        }
+
+        let xml = r#"<list id="l"><title>Hi</title><meta charset="UTF-8"/><item><a>1</a><b>2</b></item></list>"#;
+        let children: Children = from_str(xml).unwrap();
+        assert_eq!(children.id, "l");
+        assert_eq!(children.nodes.len(), 3);
+        assert_eq!(children.nodes[0], named("title", text("Hi")));
+
+        let meta = children.nodes[1].as_element().unwrap().get("meta").unwrap();
+        let meta = meta.as_element().unwrap();
+        assert_eq!(meta.attributes["charset"], "UTF-8");
+        assert!(meta.children.is_empty());
+
+        let item = children.nodes[2].as_element().unwrap().get("item").unwrap();
+        let item = item.as_element().unwrap();
+        assert_eq!(
+            item.children,
+            vec![("a".to_string(), text("1")), ("b".to_string(), text("2"))]
+        );
+    }
+
+    #[test]
+    fn test_value_children_mixed_text() {
+        use serde::Deserialize;
+
+        #[derive(Debug, Deserialize)]
+        struct Para {
+            title: String,
+            #[serde(rename = "$value")]
+            nodes: Vec<Value>,
+        }
+
+        let para: Para = from_str("<p>one<b>two</b><title>t</title>three</p>").unwrap();
+        assert_eq!(para.title, "t");
+        assert_eq!(
+            para.nodes,
+            vec![text("one"), named("b", text("two")), text("three")]
+        );
+    }
+
+    #[test]
+    fn test_value_children_roundtrip() {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Para {
+            #[serde(rename = "@id")]
+            id: String,
+            #[serde(rename = "$value")]
+            nodes: Vec<Value>,
+        }
+
+        let xml = r#"<Para id="p">Call:<b>Bob</b>,<i lang="en">noon</i><br></br>!</Para>"#;
+        let para: Para = from_str(xml).unwrap();
+        assert_eq!(para.nodes[1], named("b", text("Bob")));
+        assert_eq!(para.nodes[4], named("br", text("")));
+        assert_eq!(crate::to_string(&para).unwrap(), xml);
+
+        // A `Value` field that is not an item keeps its own element
+        let element: Element = from_str("<e><b>1</b></e>").unwrap();
+        assert_eq!(crate::to_string_with_root(&element, "e").unwrap(), "<e><b>1</b></e>");
+    }
+}
@@ -2,7 +2,8 @@
 //!
 //! This module provides a fast XML writer that produces well-formed XML output.
 
-use crate::escape::escape_to;
+use crate::escape::{escape_to, is_restricted_control, reject_control_chars};
+use crate::reader::is_name;
 use std::io::{self, Write};
 
 /// An XML writer that produces well-formed XML output.
@@ -18,6 +19,10 @@ pub struct XmlWriter<W: Write> {
     level: usize,
     /// Whether the last write was a start element (for formatting).
     last_was_start: bool,
+    /// Namespace prefixes in scope with the depth they were declared at.
+    namespaces: Vec<(Option<String>, String, usize)>,
+    /// Prefix of the open tag that is not yet declared in scope.
+    undeclared_prefix: Option<String>,
 }
 
 /// Indentation configuration.
@@ -49,6 +54,8 @@ impl<W: Write> XmlWriter<W> {
             indent: None,
             level: 0,
             last_was_start: false,
+            namespaces: Vec::new(),
+            undeclared_prefix: None,
         }
     }
 
@@ -62,15 +69,36 @@ impl<W: Write> XmlWriter<W> {
             indent: Some(indent),
             level: 0,
             last_was_start: false,
+            namespaces: Vec::new(),
+            undeclared_prefix: None,
         }
     }
 
     /// Returns the inner writer.
+    ///
+    /// Elements still open are left unclosed; use [`XmlWriter::finish`] to
+    /// catch that.
     #[inline]
     pub fn into_inner(self) -> W {
         self.writer
     }
 
+    /// Checks that every element has been closed, flushes, and returns the
+    /// inner writer.
+    ///
+    /// Fails if an element is still open, which usually means a call to
+    /// `end_element` was forgotten and the output is truncated.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(name) = self.element_stack.last() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("element '{}' is not closed", name),
+            ));
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
     /// Returns the current nesting depth.
     #[inline]
     pub fn depth(&self) -> usize {
@@ -99,7 +127,88 @@ impl<W: Write> XmlWriter<W> {
         Ok(())
     }
 
+    /// Starts an element and writes all of its attributes, in order.
+    ///
+    /// More attributes may follow with [`XmlWriter::write_attribute`] until
+    /// content is written.
+    pub fn start_element_with_attributes(
+        &mut self,
+        name: &str,
+        attrs: &[(&str, &str)],
+    ) -> io::Result<()> {
+        self.start_element(name)?;
+        for (attr_name, value) in attrs {
+            self.write_attribute(attr_name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Starts an element with a namespace prefix, e.g. `soap:Envelope`.
+    ///
+    /// The prefix must be declared with [`XmlWriter::declare_namespace`] on
+    /// this element or an enclosing one before the tag is closed.
+    pub fn start_element_ns(&mut self, prefix: Option<&str>, local: &str) -> io::Result<()> {
+        match prefix {
+            Some(prefix) => {
+                self.start_element(&format!("{}:{}", prefix, local))?;
+                if !self.is_prefix_in_scope(prefix) {
+                    self.undeclared_prefix = Some(prefix.to_string());
+                }
+                Ok(())
+            }
+            None => self.start_element(local),
+        }
+    }
+
+    /// Declares a namespace on the current element, writing an `xmlns` or
+    /// `xmlns:prefix` attribute.
+    ///
+    /// The mapping stays in scope until the element is closed.
+    pub fn declare_namespace(&mut self, prefix: Option<&str>, uri: &str) -> io::Result<()> {
+        match prefix {
+            Some(prefix) => self.write_attribute(&format!("xmlns:{}", prefix), uri)?,
+            None => self.write_attribute("xmlns", uri)?,
+        }
+        if self.undeclared_prefix.as_deref() == prefix {
+            self.undeclared_prefix = None;
+        }
+        self.namespaces.push((
+            prefix.map(str::to_string),
+            uri.to_string(),
+            self.element_stack.len(),
+        ));
+        Ok(())
+    }
+
+    /// Returns the namespace URI bound to `prefix` in the current scope.
+    pub fn namespace_uri(&self, prefix: Option<&str>) -> Option<&str> {
+        self.namespaces
+            .iter()
+            .rev()
+            .find(|(p, _, _)| p.as_deref() == prefix)
+            .map(|(_, uri, _)| uri.as_str())
+    }
+
+    /// Returns whether `prefix` may be used on an element name.
+    fn is_prefix_in_scope(&self, prefix: &str) -> bool {
+        prefix == "xml" || self.namespace_uri(Some(prefix)).is_some()
+    }
+
+    /// Fails if the open tag uses a prefix that was never declared.
+    fn check_prefix_declared(&mut self) -> io::Result<()> {
+        match self.undeclared_prefix.take() {
+            Some(prefix) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("namespace prefix '{}' is not declared", prefix),
+            )),
+            None => Ok(()),
+        }
+    }
+
     /// Writes an attribute for the current element.
+    ///
+    /// Fails if `value` holds a control character that XML 1.0 does not
+    /// allow.
     pub fn write_attribute(&mut self, name: &str, value: &str) -> io::Result<()> {
         if !self.in_tag {
             return Err(io::Error::new(
@@ -107,6 +216,7 @@ impl<W: Write> XmlWriter<W> {
                 "cannot write attribute outside of element tag",
             ));
         }
+        check_text(value)?;
         write!(self.writer, " {}=\"", name)?;
         self.write_escaped(value)?;
         self.writer.write_all(b"\"")
@@ -116,7 +226,14 @@ impl<W: Write> XmlWriter<W> {
     pub fn end_element(&mut self) -> io::Result<()> {
         self.level = self.level.saturating_sub(1);
 
+        if self.in_tag {
+            self.check_prefix_declared()?;
+        }
+
         if let Some(name) = self.element_stack.pop() {
+            let depth = self.element_stack.len();
+            self.namespaces.retain(|&(_, _, declared)| declared <= depth);
+
             if self.in_tag {
                 // Self-closing tag
                 self.writer.write_all(b"/>")?;
@@ -138,17 +255,61 @@ impl<W: Write> XmlWriter<W> {
     }
 
     /// Writes text content.
+    ///
+    /// Fails if `text` holds a control character that XML 1.0 does not
+    /// allow, such as U+0007.
     pub fn write_text(&mut self, text: &str) -> io::Result<()> {
+        check_text(text)?;
         self.close_tag_if_open()?;
         self.write_escaped(text)?;
         self.last_was_start = false;
         Ok(())
     }
 
+    /// Writes a pre-rendered fragment verbatim, without escaping.
+    ///
+    /// The caller is responsible for `s` being well-formed XML; nothing is
+    /// checked, and markup written here is not tracked by the writer. Use this
+    /// to splice in cached sub-documents or SVG/MathML blobs.
+    pub fn write_raw(&mut self, s: &str) -> io::Result<()> {
+        self.close_tag_if_open()?;
+        self.writer.write_all(s.as_bytes())?;
+        self.last_was_start = false;
+        Ok(())
+    }
+
     /// Writes a CDATA section.
+    ///
+    /// Any `]]>` in `data` is split across two sections, and control
+    /// characters that XML 1.0 forbids are dropped, so the output is
+    /// well-formed for any input.
     pub fn write_cdata(&mut self, data: &str) -> io::Result<()> {
         self.close_tag_if_open()?;
-        write!(self.writer, "<![CDATA[{}]]>", data)
+
+        let bytes = data.as_bytes();
+        let mut out = String::with_capacity(data.len() + 12);
+        out.push_str("<![CDATA[");
+        let mut start = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            if byte == b'>' {
+                // Keep `]]` and `>` in different sections, checking the
+                // output as dropped characters may have brought them together
+                out.push_str(&data[start..i]);
+                start = i;
+                if out.ends_with("]]") {
+                    out.push_str("]]><![CDATA[");
+                }
+            } else if is_restricted_control(byte) {
+                out.push_str(&data[start..i]);
+                start = i + 1;
+            }
+        }
+        out.push_str(&data[start..]);
+        out.push_str("]]>");
+
+        self.writer.write_all(out.as_bytes())?;
+        self.last_was_start = false;
+        Ok(())
     }
 
     /// Writes a comment.
@@ -169,6 +330,46 @@ impl<W: Write> XmlWriter<W> {
         self.writer.write_all(b"?>")
     }
 
+    /// Writes a processing instruction whose data is pseudo-attributes, such
+    /// as `<?xml-model href="schema.rng"?>`. Values are escaped.
+    ///
+    /// Fails if the target or an attribute name is not an XML name, if the
+    /// target is the reserved `xml` in any case, or if a value holds a
+    /// control character XML 1.0 does not allow.
+    ///
+    /// See [`parse_pi_pseudo_attrs`](crate::parse_pi_pseudo_attrs) for
+    /// reading them back.
+    pub fn write_pi_with_pseudo_attrs(
+        &mut self,
+        target: &str,
+        attrs: &[(&str, &str)],
+    ) -> io::Result<()> {
+        if !is_name(target) || target.eq_ignore_ascii_case("xml") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid processing instruction target '{}'", target),
+            ));
+        }
+        let mut data = String::new();
+        for (name, value) in attrs {
+            if !is_name(name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid pseudo-attribute name '{}'", name),
+                ));
+            }
+            check_text(value)?;
+            if !data.is_empty() {
+                data.push(' ');
+            }
+            data.push_str(name);
+            data.push_str("=\"");
+            escape_to(value, &mut data);
+            data.push('"');
+        }
+        self.write_pi(target, (!data.is_empty()).then_some(data.as_str()))
+    }
+
     /// Writes a complete element with text content.
     pub fn write_element(&mut self, name: &str, content: &str) -> io::Result<()> {
         self.start_element(name)?;
@@ -185,9 +386,21 @@ impl<W: Write> XmlWriter<W> {
         Ok(())
     }
 
+    /// Writes an empty element with the given attributes, e.g.
+    /// `<img src="a.png"/>`.
+    pub fn write_empty_element_with_attributes(
+        &mut self,
+        name: &str,
+        attrs: &[(&str, &str)],
+    ) -> io::Result<()> {
+        self.start_element_with_attributes(name, attrs)?;
+        self.end_element()
+    }
+
     /// Closes the opening tag if one is open.
     fn close_tag_if_open(&mut self) -> io::Result<()> {
         if self.in_tag {
+            self.check_prefix_declared()?;
             self.writer.write_all(b">")?;
             self.in_tag = false;
         }
@@ -220,6 +433,12 @@ impl<W: Write> XmlWriter<W> {
     }
 }
 
+/// Fails, before anything is written, if `s` holds a control character
+/// XML 1.0 does not allow.
+fn check_text(s: &str) -> io::Result<()> {
+    reject_control_chars(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
 /// A string-based XML writer for convenience.
 pub struct StringXmlWriter {
     writer: XmlWriter<Vec<u8>>,
@@ -244,6 +463,14 @@ impl StringXmlWriter {
     pub fn into_string(self) -> String {
         String::from_utf8(self.writer.into_inner()).unwrap_or_default()
     }
+
+    /// Checks that every element has been closed and returns the XML string.
+    ///
+    /// See [`XmlWriter::finish`].
+    pub fn finish(self) -> io::Result<String> {
+        let bytes = self.writer.finish()?;
+        Ok(String::from_utf8(bytes).unwrap_or_default())
+    }
 }
 
 impl Default for StringXmlWriter {
@@ -288,6 +515,22 @@ mod tests {
         assert_eq!(result, "<root/>");
     }
 
+    #[test]
+    fn test_finish() {
+        let mut writer = XmlWriter::new(Vec::new());
+        writer.start_element("root").unwrap();
+        writer.start_element("child").unwrap();
+        writer.end_element().unwrap();
+        let err = writer.finish().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("'root'"));
+
+        let mut writer = XmlWriter::new(Vec::new());
+        writer.start_element("root").unwrap();
+        writer.end_element().unwrap();
+        assert_eq!(writer.finish().unwrap(), b"<root/>");
+    }
+
     #[test]
     fn test_element_with_text() {
         let result = write_to_string(|w| {
@@ -341,6 +584,25 @@ mod tests {
         assert_eq!(result, r#"<root attr="value with &quot;quotes&quot;"/>"#);
     }
 
+    #[test]
+    fn test_control_chars_rejected() {
+        let mut writer = XmlWriter::new(Vec::new());
+        writer.start_element("a").unwrap();
+        let err = writer.write_text("bell\u{7}").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("U+0007"), "{}", err);
+        assert!(writer.write_attribute("x", "\u{0}").is_err());
+        writer.end_element().unwrap();
+        assert_eq!(writer.into_inner(), b"<a/>");
+
+        let mut writer = XmlWriter::new(Vec::new());
+        writer.start_element("a").unwrap();
+        assert!(writer.write_attribute("x", "\u{1B}").is_err());
+        writer.write_text("tab\tok").unwrap();
+        writer.end_element().unwrap();
+        assert_eq!(writer.into_inner(), b"<a>tab\tok</a>");
+    }
+
     #[test]
     fn test_xml_declaration() {
         let result = write_to_string(|w| {
@@ -371,6 +633,82 @@ mod tests {
         assert_eq!(result, "<root><![CDATA[<special>content</special>]]></root>");
     }
 
+    #[test]
+    fn test_write_raw() {
+        let result = write_to_string(|w| {
+            w.start_element("root")?;
+            w.write_raw(r#"<custom attr="x"/>"#)?;
+            w.write_text("<&>")?;
+            w.end_element()
+        });
+        assert_eq!(result, r#"<root><custom attr="x"/>&lt;&amp;&gt;</root>"#);
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let soap = "http://schemas.xmlsoap.org/soap/envelope/";
+        let result = write_to_string(|w| {
+            w.start_element_ns(Some("soap"), "Envelope")?;
+            w.declare_namespace(Some("soap"), soap)?;
+            w.start_element_ns(Some("soap"), "Body")?;
+            w.start_element_ns(None, "GetPrice")?;
+            w.declare_namespace(None, "urn:prices")?;
+            assert_eq!(w.namespace_uri(None), Some("urn:prices"));
+            w.end_element()?;
+            assert_eq!(w.namespace_uri(None), None);
+            w.end_element()?;
+            w.end_element()
+        });
+        assert_eq!(
+            result,
+            format!(
+                r#"<soap:Envelope xmlns:soap="{}"><soap:Body><GetPrice xmlns="urn:prices"/></soap:Body></soap:Envelope>"#,
+                soap
+            )
+        );
+    }
+
+    #[test]
+    fn test_undeclared_namespace_prefix() {
+        let mut writer = XmlWriter::new(Vec::new());
+        writer.start_element_ns(Some("soap"), "Envelope").unwrap();
+        assert!(writer.write_text("x").is_err());
+
+        let mut writer = XmlWriter::new(Vec::new());
+        writer.start_element_ns(Some("a"), "root").unwrap();
+        writer.declare_namespace(Some("a"), "urn:a").unwrap();
+        writer.end_element().unwrap();
+        writer.start_element_ns(Some("a"), "next").unwrap();
+        assert!(writer.end_element().is_err());
+    }
+
+    #[test]
+    fn test_cdata_split() {
+        use crate::reader::{XmlEvent, XmlReader};
+
+        for data in ["foo]]>bar", "]]>]]>", "a\u{1}b", "a]]\u{1}>b", "]\u{1}]>"] {
+            let result = write_to_string(|w| {
+                w.start_element("root")?;
+                w.write_cdata(data)?;
+                w.end_element()
+            });
+
+            let mut reader = XmlReader::from_str(&result);
+            let mut text = String::new();
+            loop {
+                match reader.next_event().unwrap() {
+                    XmlEvent::CData(s) | XmlEvent::Text(s) => text.push_str(&s),
+                    XmlEvent::Eof => break,
+                    _ => {}
+                }
+            }
+            assert_eq!(text, data.replace('\u{1}', ""), "{}", result);
+        }
+
+        let result = write_to_string(|w| w.write_cdata("foo]]>bar"));
+        assert_eq!(result, "<![CDATA[foo]]]]><![CDATA[>bar]]>");
+    }
+
     #[test]
     fn test_empty_element() {
         let result = write_to_string(|w| {
@@ -379,6 +717,41 @@ mod tests {
         assert_eq!(result, "<br/>");
     }
 
+    #[test]
+    fn test_element_with_attribute_list() {
+        #[derive(serde::Serialize)]
+        struct Link {
+            #[serde(rename = "@href")]
+            href: &'static str,
+            #[serde(rename = "@title")]
+            title: &'static str,
+            #[serde(rename = "$value")]
+            text: &'static str,
+        }
+
+        #[derive(serde::Serialize)]
+        struct Img {
+            #[serde(rename = "@src")]
+            src: &'static str,
+        }
+
+        let link = Link { href: "/a?x=1&y=2", title: "\"A\" <b>", text: "A" };
+        let result = write_to_string(|w| {
+            w.start_element_with_attributes("Link", &[("href", link.href), ("title", link.title)])?;
+            w.write_text(link.text)?;
+            w.end_element()
+        });
+        assert_eq!(result, crate::to_string(&link).unwrap());
+
+        let result =
+            write_to_string(|w| w.write_empty_element_with_attributes("Img", &[("src", "a.png")]));
+        assert_eq!(result, crate::to_string(&Img { src: "a.png" }).unwrap());
+        assert_eq!(result, r#"<Img src="a.png"/>"#);
+
+        let result = write_to_string(|w| w.write_empty_element_with_attributes("br", &[]));
+        assert_eq!(result, "<br/>");
+    }
+
     #[test]
     fn test_write_element_shorthand() {
         let result = write_to_string(|w| {
@@ -413,6 +786,44 @@ mod tests {
         assert_eq!(result, r#"<?xml-stylesheet type="text/xsl" href="style.xsl"?>"#);
     }
 
+    #[test]
+    fn test_pi_with_pseudo_attrs() {
+        let attrs = [("href", "schema.rng?a=1&b=\"2\""), ("type", "application/xml")];
+        let result = write_to_string(|w| w.write_pi_with_pseudo_attrs("xml-model", &attrs));
+        assert_eq!(
+            result,
+            r#"<?xml-model href="schema.rng?a=1&amp;b=&quot;2&quot;" type="application/xml"?>"#
+        );
+
+        let data = result.strip_prefix("<?xml-model ").unwrap().strip_suffix("?>").unwrap();
+        let parsed = crate::parse_pi_pseudo_attrs(data);
+        let parsed: Vec<_> = parsed.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+        assert_eq!(parsed, attrs);
+
+        let result = write_to_string(|w| w.write_pi_with_pseudo_attrs("marker", &[]));
+        assert_eq!(result, "<?marker?>");
+
+        // Targets and names must be XML names, and the target not `xml`
+        let cases = [
+            ("xml", "a"),
+            ("XmL", "a"),
+            ("bad target", "a"),
+            ("", "a"),
+            ("1st", "a"),
+            ("ok", "bad name"),
+            ("ok", ""),
+            ("ok", "a=b"),
+        ];
+        for (target, name) in cases {
+            let mut writer = XmlWriter::new(Vec::new());
+            let err = writer.write_pi_with_pseudo_attrs(target, &[(name, "v")]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?} {:?}", target, name);
+            assert!(writer.into_inner().is_empty());
+        }
+        let mut writer = XmlWriter::new(Vec::new());
+        assert!(writer.write_pi_with_pseudo_attrs("ok", &[("a", "\u{7}")]).is_err());
+    }
+
     #[test]
     fn test_indented_output() {
         let mut writer = XmlWriter::with_indent(Vec::new(), IndentConfig::default());